@@ -0,0 +1,397 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Qubit connectivity of quantum devices, and CNOT synthesis respecting it
+
+use std::fmt;
+use std::collections::VecDeque;
+use crate::linalg::*;
+use crate::circuit::Circuit;
+use crate::gate::*;
+
+/// An error reading an architecture from an edge list. Lines are numbered from 1.
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub enum ArchitectureError {
+    /// A line doesn't have exactly two qubits
+    WrongArity { line: usize, found: usize },
+    /// A token isn't a qubit number
+    InvalidQubit { line: usize, token: String },
+}
+
+impl fmt::Display for ArchitectureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchitectureError::WrongArity { line, found } =>
+                write!(f, "line {}: expected 2 qubits, got {}", line, found),
+            ArchitectureError::InvalidQubit { line, token } =>
+                write!(f, "line {}: invalid qubit: {}", line, token),
+        }
+    }
+}
+
+impl std::error::Error for ArchitectureError {}
+
+/// The coupling graph of a device, giving the pairs of qubits which a CNOT can act on
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub struct Architecture {
+    adj: Vec<Vec<usize>>,
+}
+
+impl Architecture {
+    /// An architecture with the given number of qubits and no couplings
+    pub fn new(nqubits: usize) -> Architecture {
+        Architecture { adj: vec![Vec::new(); nqubits] }
+    }
+
+    /// An architecture with the given couplings
+    pub fn from_edges(nqubits: usize, edges: &[(usize,usize)]) -> Architecture {
+        let mut arch = Architecture::new(nqubits);
+        for &(q0, q1) in edges { arch.add_edge(q0, q1); }
+        arch
+    }
+
+    /// An architecture where every pair of qubits is coupled
+    pub fn complete(nqubits: usize) -> Architecture {
+        Architecture {
+            adj: (0..nqubits).map(|q| (0..nqubits).filter(|&q1| q1 != q).collect()).collect()
+        }
+    }
+
+    /// Qubits in a line, where qubit i is coupled to qubit i+1
+    pub fn line(nqubits: usize) -> Architecture {
+        let edges: Vec<(usize,usize)> = (1..nqubits).map(|q| (q-1, q)).collect();
+        Architecture::from_edges(nqubits, &edges)
+    }
+
+    /// A grid of qubits coupled to their horizontal and vertical neighbours
+    ///
+    /// Qubits are numbered row by row, so the qubit in row r and column c is `r * cols + c`.
+    pub fn grid(rows: usize, cols: usize) -> Architecture {
+        let mut arch = Architecture::new(rows * cols);
+        for r in 0..rows {
+            for c in 0..cols {
+                if c + 1 < cols { arch.add_edge(r * cols + c, r * cols + c + 1); }
+                if r + 1 < rows { arch.add_edge(r * cols + c, (r + 1) * cols + c); }
+            }
+        }
+        arch
+    }
+
+    /// A heavy-hex lattice, as used by IBM devices
+    ///
+    /// This has the given number of rows, each a line of `width` qubits. Consecutive rows
+    /// are joined by bridge qubits at every fourth column, starting at column 0 below even
+    /// rows and column 2 below odd rows, so the rows and bridges make up hexagons of 12 qubits.
+    /// Qubits are numbered row by row, with the bridges below a row numbered after it. For
+    /// example, `heavy_hex(7, 15)` is close to the 127-qubit Eagle layout.
+    pub fn heavy_hex(rows: usize, width: usize) -> Architecture {
+        let mut edges = Vec::new();
+        let mut q = 0;
+        for r in 0..rows {
+            let row_start = q;
+            for c in 1..width { edges.push((row_start + c - 1, row_start + c)); }
+            q += width;
+
+            if r + 1 < rows {
+                let next_start = q + (width + 3 - 2 * (r % 2)) / 4;
+                for c in (2 * (r % 2)..width).step_by(4) {
+                    edges.push((row_start + c, q));
+                    edges.push((q, next_start + c));
+                    q += 1;
+                }
+            }
+        }
+        Architecture::from_edges(q, &edges)
+    }
+
+    /// Read an architecture from a list of couplings, one per line
+    ///
+    /// Each line should give two qubit numbers, separated by spaces or a comma. Blank lines,
+    /// and anything after a '#', are ignored. The number of qubits is one more than the
+    /// largest qubit number.
+    pub fn from_edge_list(source: &str) -> Result<Architecture, ArchitectureError> {
+        let mut edges = Vec::new();
+        for (l, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let qs: Vec<&str> = line.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .collect();
+            if qs.is_empty() { continue; }
+            if qs.len() != 2 {
+                return Err(ArchitectureError::WrongArity { line: l + 1, found: qs.len() });
+            }
+            let qubit = |token: &str| token.parse::<usize>().map_err(|_|
+                ArchitectureError::InvalidQubit { line: l + 1, token: token.to_string() });
+            let q0 = qubit(qs[0])?;
+            let q1 = qubit(qs[1])?;
+            edges.push((q0, q1));
+        }
+
+        let nqubits = edges.iter().map(|&(q0, q1)| q0.max(q1) + 1).max().unwrap_or(0);
+        Ok(Architecture::from_edges(nqubits, &edges))
+    }
+
+    /// Couple two qubits. Self-loops and couplings which are already there are ignored.
+    pub fn add_edge(&mut self, q0: usize, q1: usize) {
+        if q0 == q1 || self.connected(q0, q1) { return; }
+        self.adj[q0].push(q1);
+        self.adj[q1].push(q0);
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.adj.len()
+    }
+
+    pub fn connected(&self, q0: usize, q1: usize) -> bool {
+        self.adj[q0].contains(&q1)
+    }
+
+    pub fn neighbors(&self, q: usize) -> &[usize] {
+        &self.adj[q]
+    }
+
+    /// The couplings, each given once with the smaller qubit first
+    pub fn edges(&self) -> Vec<(usize,usize)> {
+        (0..self.num_qubits())
+            .flat_map(|q| self.adj[q].iter().filter(move |&&q1| q < q1).map(move |&q1| (q, q1)))
+            .collect()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.elimination_order().is_some()
+    }
+
+    /// An order of all the qubits, such that removing them one at a time leaves
+    /// the rest connected
+    ///
+    /// This is the reverse of a breadth-first ordering, and returns None if the coupling
+    /// graph is not connected.
+    pub fn elimination_order(&self) -> Option<Vec<usize>> {
+        let n = self.num_qubits();
+        if n == 0 { return Some(Vec::new()); }
+        let mut seen = vec![false; n];
+        let mut order = vec![0];
+        let mut queue = VecDeque::from(vec![0]);
+        seen[0] = true;
+        while let Some(q) = queue.pop_front() {
+            for &q1 in &self.adj[q] {
+                if !seen[q1] {
+                    seen[q1] = true;
+                    order.push(q1);
+                    queue.push_back(q1);
+                }
+            }
+        }
+
+        if order.len() < n { return None; }
+        order.reverse();
+        Some(order)
+    }
+
+    /// Find a small tree containing the root and the terminals, using only the allowed qubits
+    ///
+    /// The tree is returned as a list of (parent, child) couplings, where each parent is either the
+    /// root or the child of an earlier coupling. This uses the usual approximation of growing the
+    /// tree from the root by adding a shortest path to the nearest terminal, one at a time.
+    pub fn steiner_tree(&self, root: usize, terminals: &[usize], allowed: &[bool]) -> Vec<(usize,usize)> {
+        let n = self.num_qubits();
+        let mut in_tree = vec![false; n];
+        in_tree[root] = true;
+        let mut tree = Vec::new();
+
+        loop {
+            let mut parent: Vec<Option<usize>> = vec![None; n];
+            let mut queue: VecDeque<usize> = (0..n).filter(|&q| in_tree[q]).collect();
+            let mut found = None;
+            'search: while let Some(q) = queue.pop_front() {
+                for &q1 in &self.adj[q] {
+                    if !allowed[q1] || in_tree[q1] || parent[q1].is_some() { continue; }
+                    parent[q1] = Some(q);
+                    if terminals.contains(&q1) { found = Some(q1); break 'search; }
+                    queue.push_back(q1);
+                }
+            }
+
+            let mut q = match found { Some(q) => q, None => return tree };
+            let mut path = Vec::new();
+            while !in_tree[q] {
+                in_tree[q] = true;
+                let p = parent[q].unwrap();
+                path.push((p, q));
+                q = p;
+            }
+            tree.extend(path.into_iter().rev());
+        }
+    }
+}
+
+/// Clear column p of m, apart from the entry in row p, by adding rows along couplings
+///
+/// Only rows which are marked as remaining are used. The row operations are pushed on to ops.
+fn steiner_reduce_column(m: &mut Mat2, p: usize, arch: &Architecture, remaining: &[bool],
+                         ops: &mut Vec<(usize,usize)>)
+{
+    let terminals: Vec<usize> = (0..m.num_rows())
+        .filter(|&r| r != p && remaining[r] && m[r][p] == 1)
+        .collect();
+    let tree = arch.steiner_tree(p, &terminals, remaining);
+
+    // working up from the leaves, make sure every row in the tree has a 1 in column p
+    for &(r0, r1) in tree.iter().rev() {
+        if m[r0][p] == 0 {
+            m.row_add(r1, r0);
+            ops.push((r1, r0));
+        }
+    }
+
+    // then, again from the leaves, clear every row in the tree apart from the root
+    for &(r0, r1) in tree.iter().rev() {
+        m.row_add(r0, r1);
+        ops.push((r0, r1));
+    }
+}
+
+/// Synthesise a CNOT circuit with the given parity matrix, using only CNOTs allowed by
+/// the architecture
+///
+/// This uses the Steiner-Gauss algorithm from:
+///
+/// A. Kissinger, A. Meijer-van de Griend. CNOT circuit extraction for topologically-constrained
+/// quantum memories. QIC 2020
+///
+/// Each qubit is eliminated in turn, in an order which leaves the remaining qubits connected.
+/// Its column is cleared with row operations along a Steiner tree, then its row is cleared the
+/// same way with column operations. Returns None if the matrix is not invertible, or the
+/// architecture is not connected or has the wrong number of qubits.
+pub fn steiner_gauss(m: &Mat2, arch: &Architecture) -> Option<Circuit> {
+    let n = m.num_rows();
+    if m.num_cols() != n || arch.num_qubits() != n { return None; }
+    let order = arch.elimination_order()?;
+
+    let mut m = m.clone();
+    let mut remaining = vec![true; n];
+    let mut row_ops = Vec::new();
+    let mut col_ops = Vec::new();
+    for p in order {
+        if (0..n).all(|r| !remaining[r] || m[r][p] == 0) { return None; }
+        steiner_reduce_column(&mut m, p, arch, &remaining, &mut row_ops);
+
+        // column operations are row operations on the transpose
+        let mut mt = m.transpose();
+        steiner_reduce_column(&mut mt, p, arch, &remaining, &mut col_ops);
+        m = mt.transpose();
+        remaining[p] = false;
+    }
+
+    // Now R * m * C = 1, where R and C are the row and column operations, so m = R^-1 * C^-1.
+    // Adding row r0 to row r1 is a CNOT from r0 to r1, whereas adding column c0 to column c1
+    // is a CNOT from c1 to c0.
+    let mut c = Circuit::new(n);
+    for &(c0, c1) in &col_ops { c.push(Gate::new(CNOT, vec![c1, c0])); }
+    for &(r0, r1) in row_ops.iter().rev() { c.push(Gate::new(CNOT, vec![r0, r1])); }
+    Some(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+    use crate::test_util::cnot_circuit;
+
+
+    #[test]
+    fn presets() {
+        let line = Architecture::line(4);
+        assert_eq!(line.edges(), vec![(0,1), (1,2), (2,3)]);
+
+        let grid = Architecture::grid(2, 3);
+        assert_eq!(grid.edges(), vec![(0,1), (0,3), (1,2), (1,4), (2,5), (3,4), (4,5)]);
+
+        // two rows of 5, joined by bridges 5 and 6 at columns 0 and 4
+        let hex = Architecture::heavy_hex(2, 5);
+        assert_eq!(hex.num_qubits(), 12);
+        assert!(hex.connected(0, 5) && hex.connected(5, 7));
+        assert!(hex.connected(4, 6) && hex.connected(6, 11));
+        assert_eq!(hex.edges().len(), 12);
+
+        // 4 bridges between each pair of rows make 3 hexagons, and no qubit has more than 3 couplings
+        let eagle = Architecture::heavy_hex(7, 15);
+        assert_eq!(eagle.num_qubits(), 7 * 15 + 6 * 4);
+        assert!(eagle.is_connected());
+        assert!((0..eagle.num_qubits()).all(|q| eagle.neighbors(q).len() <= 3));
+        let edges = eagle.edges().len();
+        let hexagons = edges + 1 - eagle.num_qubits();
+        assert_eq!(hexagons, 6 * 3);
+    }
+
+    #[test]
+    fn edge_lists() {
+        let arch = Architecture::from_edge_list("# a square\n0 1\n1,2\n\n  2 3 # last one\n3, 0\n").unwrap();
+        assert_eq!(arch.edges(), vec![(0,1), (0,3), (1,2), (2,3)]);
+        assert_eq!(Architecture::from_edge_list("0 1\n1 2 3"),
+                   Err(ArchitectureError::WrongArity { line: 2, found: 3 }));
+        let err = Architecture::from_edge_list("0 1\n\n1 q2").unwrap_err();
+        assert_eq!(err, ArchitectureError::InvalidQubit { line: 3, token: "q2".to_string() });
+        assert_eq!(err.to_string(), "line 3: invalid qubit: q2");
+    }
+
+    #[test]
+    fn elimination_order() {
+        let line = Architecture::from_edges(4, &[(0,1), (1,2), (2,3)]);
+        assert_eq!(line.elimination_order(), Some(vec![3, 2, 1, 0]));
+        let split = Architecture::from_edges(4, &[(0,1), (2,3)]);
+        assert_eq!(split.elimination_order(), None);
+    }
+
+    #[test]
+    fn steiner_tree() {
+        let arch = Architecture::from_edges(6, &[(0,1), (1,2), (2,3), (3,4), (1,5)]);
+        let all = vec![true; 6];
+        assert_eq!(arch.steiner_tree(0, &[2, 5], &all), vec![(0,1), (1,2), (1,5)]);
+        assert_eq!(arch.steiner_tree(2, &[], &all), vec![]);
+
+        let mut allowed = all.clone();
+        allowed[0] = false;
+        assert_eq!(arch.steiner_tree(4, &[5], &allowed), vec![(4,3), (3,2), (2,1), (1,5)]);
+    }
+
+    #[test]
+    fn steiner_synthesis() {
+        let (c, m) = cnot_circuit(5, 30);
+        let archs = vec![
+            Architecture::from_edges(5, &[(0,1), (1,2), (2,3), (3,4)]),
+            Architecture::from_edges(5, &[(2,0), (2,1), (2,3), (2,4)]),
+            Architecture::from_edges(5, &[(0,1), (1,2), (2,3), (3,4), (4,0)]),
+            Architecture::complete(5),
+        ];
+
+        for arch in &archs {
+            let c1 = steiner_gauss(&m, arch).expect("synthesis should succeed");
+            assert!(c1.gates.iter().all(|g| g.t == CNOT && arch.connected(g.qs[0], g.qs[1])));
+            assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+        }
+    }
+
+    #[test]
+    fn steiner_failures() {
+        let line = Architecture::from_edges(3, &[(0,1), (1,2)]);
+        assert_eq!(steiner_gauss(&Mat2::ones(3, 3), &line), None);
+        assert_eq!(steiner_gauss(&Mat2::id(4), &line), None);
+        let split = Architecture::from_edges(3, &[(0,1)]);
+        assert_eq!(steiner_gauss(&Mat2::id(3), &split), None);
+        assert_eq!(steiner_gauss(&Mat2::id(3), &line), Some(Circuit::new(3)));
+    }
+}
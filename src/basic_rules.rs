@@ -240,95 +240,6 @@ pub fn local_comp_unsafe(g: &mut impl GraphLike, v: V) {
 
 safe_rule1!(check_local_comp, local_comp_unsafe, local_comp);
 
-#[derive(PartialEq,Eq)]
-pub enum PivotAction {
-    Delete,
-    Boundary(usize),
-    Toggle,
-}
-
-// pub fn check_gen_pivot(g: &impl GraphLike, v0: V, v1: V, pa: (PivotAction, PivotAction)) -> bool {
-//     use PivotAction::*;
-//     if !(g.vertex_type(v0) == VType::Z &&
-//          g.vertex_type(v1) == VType::Z &&
-//          g.edge_type_opt(v0, v1) == Some(EType::H)) {
-//         return false;
-//     }
-
-//     if pa.0 == Delete && !g.phase(v0).is_integer() { return false; }
-//     if pa.1 == Delete && !g.phase(v1).is_integer() { return false; }
-
-//     if !g.neighbors(v0).all(|n| g.vertex_type(n) == VType::Z || pa.0 == Boundary(n)) { return false; }
-//     if !g.neighbors(v1).all(|n| g.vertex_type(n) == VType::Z || pa.1 == Boundary(n)) { return false; }
-//     true
-// }
-
-fn adj_boundary(g: &impl GraphLike, v: V) -> Option<usize> {
-    g.neighbors(v).find(|&n| g.vertex_type(n) == VType::B)
-}
-
-fn adj_gadget(g: &impl GraphLike, v: V) -> Option<usize> {
-    g.neighbors(v).find(|&n| g.vertex_type(n) == VType::Z && g.degree(n) == 1)
-}
-
-fn unfuse(g: &mut impl GraphLike, v: V) -> usize {
-    let vd = VData {
-        ty: VType::Z,
-        phase: g.phase(v),
-        row: g.row(v),
-        qubit: g.qubit(v)-1 };
-    let v1 = g.add_vertex_with_data(vd);
-    g.set_phase(v, Rational::zero());
-    g.add_edge(v, v1);
-
-    v1
-}
-
-fn simp_pair(g: &mut impl GraphLike, v0: V, v1: V) {
-    if g.vertex_type(v1) == VType::Z {
-        if g.edge_type(v0, v1) == EType::N {
-            g.add_to_phase(v0, g.phase(v1));
-            g.remove_vertex(v1);
-        } else if g.phase(v0) == Rational::one() {
-            g.set_phase(v0, Rational::zero());
-            g.set_phase(v1, -g.phase(v1));
-        }
-    }
-}
-
-/// Version of the pivot rule that doesn't delete vertices
-///
-/// If v0 is next to a boundary, push a Hadamard out when we pivot,
-/// otherwise toggle whether v0 is a spider or a gadget. Similarly
-/// for v1.
-pub fn pivot0_unsafe(g: &mut impl GraphLike, v0: V, v1: V) {
-    let b0 = adj_boundary(g, v0)
-        .unwrap_or_else(|| adj_gadget(g, v0)
-        .unwrap_or_else(|| unfuse(g, v0)));
-    let b1 = adj_boundary(g, v1)
-        .unwrap_or_else(|| adj_gadget(g, v1)
-        .unwrap_or_else(|| unfuse(g, v1)));
-
-    g.toggle_edge_type(v0, b0);
-    g.toggle_edge_type(v1, b1);
-
-    let mut nhd0: Vec<_> = g.neighbors(v0).filter(|&n| n != b0).collect();
-    nhd0.push(v0);
-
-    let mut nhd1: Vec<_> = g.neighbors(v1).filter(|&n| n != b1).collect();
-    nhd1.push(v1);
-
-    for &n0 in &nhd0 {
-        for &n1 in &nhd1 {
-            if (n0 == v0 && n1 == v1) || (n0 == v1 && n1 == v0) { continue; }
-            else { g.add_edge_smart(n0, n1, EType::H); }
-        }
-    }
-
-    simp_pair(g, v0, b0);
-    simp_pair(g, v1, b1);
-}
-
 /// Check [pivot_unsafe] applies
 ///
 /// Both vertices must be Z, have a phase 0 or pi, and be
@@ -387,6 +298,314 @@ pub fn pivot_unsafe(g: &mut impl GraphLike, v0: V, v1: V) {
 
 safe_rule2!(check_pivot, pivot_unsafe, pivot);
 
+/// Check whether v is an interior Z spider with phase 0 or pi
+///
+/// All of its neighbours must be Z spiders connected by H-edges, and
+/// none of them can be the outer vertex of a phase gadget.
+fn is_interior_pauli(g: &impl GraphLike, v: V) -> bool {
+    g.vertex_type(v) == VType::Z &&
+    g.phase(v).is_integer() &&
+    g.incident_edges(v).all(|(w,et)|
+        g.vertex_type(w) == VType::Z && et == EType::H && g.degree(w) > 1)
+}
+
+/// Return the boundary adjacent to v, if there is exactly one
+fn adj_boundary(g: &impl GraphLike, v: V) -> Option<V> {
+    let mut bs = g.neighbors(v).filter(|&n| g.vertex_type(n) == VType::B);
+    match (bs.next(), bs.next()) {
+        (Some(b), None) => Some(b),
+        _ => None,
+    }
+}
+
+/// Check [unfuse_boundary_unsafe] applies
+///
+/// The first vertex must be a Z spider, and the second a boundary
+/// connected to it.
+pub fn check_unfuse_boundary(g: &impl GraphLike, v: V, b: V) -> bool {
+    g.vertex_type(v) == VType::Z &&
+    g.vertex_type(b) == VType::B &&
+    g.connected(v, b)
+}
+
+/// Move the boundary b of v on to a new phase-free spider
+///
+/// The new spider is connected to b by an edge of the opposite type
+/// and to v by an H-edge, so the diagram is unchanged.
+pub fn unfuse_boundary_unsafe(g: &mut impl GraphLike, v: V, b: V) {
+    let et = g.edge_type(v, b);
+    let vd = VData {
+        ty: VType::Z,
+        phase: Rational::zero(),
+        qubit: g.qubit(b),
+        row: g.row(v) };
+    let v1 = g.add_vertex_with_data(vd);
+    g.remove_edge(v, b);
+    g.add_edge_with_type(b, v1, if et == EType::N { EType::H } else { EType::N });
+    g.add_edge_with_type(v, v1, EType::H);
+}
+
+safe_rule2!(check_unfuse_boundary, unfuse_boundary_unsafe, unfuse_boundary);
+
+/// Move the phase of v on to a new phase gadget
+///
+/// Returns the root of the gadget, which is connected to v by an H-edge.
+fn unfuse_gadget(g: &mut impl GraphLike, v: V) -> V {
+    let row = g.row(v);
+    let root = g.add_vertex_with_data(VData {
+        ty: VType::Z,
+        phase: Rational::zero(),
+        qubit: -1,
+        row });
+    let leaf = g.add_vertex_with_data(VData {
+        ty: VType::Z,
+        phase: g.phase(v),
+        qubit: -2,
+        row });
+    g.set_phase(v, Rational::zero());
+    g.add_edge_with_type(v, root, EType::H);
+    g.add_edge_with_type(root, leaf, EType::H);
+
+    root
+}
+
+/// Check [pivot_boundary_unsafe] applies
+///
+/// The first vertex must be an interior Z spider with phase 0 or pi,
+/// not adjacent to any phase gadgets. The second must be a Z spider with
+/// any phase, connected to exactly one boundary, and otherwise surrounded
+/// by H-edges connected to other Z spiders that aren't phase gadgets.
+pub fn check_pivot_boundary(g: &impl GraphLike, v0: V, v1: V) -> bool {
+    is_interior_pauli(g, v0) &&
+    g.vertex_type(v1) == VType::Z &&
+    g.edge_type_opt(v0, v1) == Some(EType::H) &&
+    adj_boundary(g, v1).is_some() &&
+    g.incident_edges(v1).all(|(w,et)|
+        g.vertex_type(w) == VType::B ||
+        (g.vertex_type(w) == VType::Z && et == EType::H && g.degree(w) > 1))
+}
+
+/// Pivot an interior vertex with a vertex on the boundary
+///
+/// The boundary of v1 is first moved on to a new spider, and if v1 has
+/// a non-Pauli phase, this is moved on to a phase gadget. Then both
+/// vertices are removed by [pivot_unsafe].
+pub fn pivot_boundary_unsafe(g: &mut impl GraphLike, v0: V, v1: V) {
+    let b = adj_boundary(g, v1).expect("v1 should be adjacent to a boundary");
+    unfuse_boundary_unsafe(g, v1, b);
+    if !g.phase(v1).is_integer() { unfuse_gadget(g, v1); }
+    pivot_unsafe(g, v0, v1);
+}
+
+safe_rule2!(check_pivot_boundary, pivot_boundary_unsafe, pivot_boundary);
+
+/// Check [pivot_gadget_unsafe] applies
+///
+/// The first vertex must be an interior Z spider with phase 0 or pi,
+/// and the second an interior Z spider with any other phase. Neither
+/// can be adjacent to a phase gadget.
+pub fn check_pivot_gadget(g: &impl GraphLike, v0: V, v1: V) -> bool {
+    is_interior_pauli(g, v0) &&
+    g.vertex_type(v1) == VType::Z &&
+    !g.phase(v1).is_integer() &&
+    g.edge_type_opt(v0, v1) == Some(EType::H) &&
+    g.incident_edges(v1).all(|(w,et)|
+        g.vertex_type(w) == VType::Z && et == EType::H && g.degree(w) > 1)
+}
+
+/// Pivot an interior Pauli vertex with a non-Pauli vertex
+///
+/// The phase of v1 is first moved on to a phase gadget, then both
+/// vertices are removed by [pivot_unsafe]. The result has one fewer
+/// interior spider that isn't part of a phase gadget.
+pub fn pivot_gadget_unsafe(g: &mut impl GraphLike, v0: V, v1: V) {
+    unfuse_gadget(g, v1);
+    pivot_unsafe(g, v0, v1);
+}
+
+safe_rule2!(check_pivot_gadget, pivot_gadget_unsafe, pivot_gadget);
+
+/// Return the leaf of the phase gadget with root v, if there is one
+///
+/// The root must be a Z spider with phase 0 or pi, connected only by
+/// H-edges to other Z spiders, one of which has degree 1.
+fn gadget_leaf(g: &impl GraphLike, v: V) -> Option<V> {
+    if g.vertex_type(v) != VType::Z ||
+       !g.phase(v).is_integer() ||
+       !g.incident_edges(v).all(|(w,et)| g.vertex_type(w) == VType::Z && et == EType::H)
+    {
+        return None;
+    }
+
+    g.neighbors(v).find(|&w| g.degree(w) == 1)
+}
+
+/// Set the phase of a gadget root to 0, returning the phase of the gadget
+///
+/// A gadget whose root has phase pi is equal to a gadget with a phase-free
+/// root and the opposite phase on its leaf, up to a global phase.
+fn normalise_gadget(g: &mut impl GraphLike, root: V, leaf: V) -> Rational {
+    let p = g.phase(leaf);
+    if g.phase(root).is_zero() {
+        p
+    } else {
+        g.set_phase(root, Rational::zero());
+        g.set_phase(leaf, -p);
+        g.scalar_mut().mul_phase(p);
+        -p
+    }
+}
+
+/// Check [gadget_fusion_unsafe] applies
+///
+/// Both vertices must be the roots of phase gadgets, and they must be
+/// connected to the same set of vertices, apart from their leaves.
+pub fn check_gadget_fusion(g: &impl GraphLike, v0: V, v1: V) -> bool {
+    if v0 == v1 || !g.contains_vertex(v0) || !g.contains_vertex(v1) { return false; }
+    if let (Some(l0), Some(l1)) = (gadget_leaf(g, v0), gadget_leaf(g, v1)) {
+        let mut ns0: Vec<V> = g.neighbors(v0).filter(|&w| w != l0).collect();
+        let mut ns1: Vec<V> = g.neighbors(v1).filter(|&w| w != l1).collect();
+        ns0.sort_unstable();
+        ns1.sort_unstable();
+        ns0 == ns1
+    } else {
+        false
+    }
+}
+
+/// Fuse two phase gadgets with the same support
+///
+/// The phase of the second gadget is added to the first, and the second
+/// gadget is removed.
+pub fn gadget_fusion_unsafe(g: &mut impl GraphLike, v0: V, v1: V) {
+    let l0 = gadget_leaf(g, v0).expect("v0 should be the root of a gadget");
+    let l1 = gadget_leaf(g, v1).expect("v1 should be the root of a gadget");
+    let p0 = normalise_gadget(g, v0, l0);
+    let p1 = normalise_gadget(g, v1, l1);
+    let n = g.degree(v0) as i32 - 1;
+
+    g.set_phase(l0, p0 + p1);
+    g.remove_vertex(l1);
+    g.remove_vertex(v1);
+    g.scalar_mut().mul_sqrt2_pow(1 - n);
+}
+
+safe_rule2!(check_gadget_fusion, gadget_fusion_unsafe, gadget_fusion);
+
+/// Check [gadget_to_phase_unsafe] applies
+///
+/// The vertex must be the root of a phase gadget acting on a single
+/// Z spider.
+pub fn check_gadget_to_phase(g: &impl GraphLike, v: V) -> bool {
+    g.degree(v) == 2 && gadget_leaf(g, v).is_some()
+}
+
+/// Fold a phase gadget on a single spider into the phase of that spider
+pub fn gadget_to_phase_unsafe(g: &mut impl GraphLike, v: V) {
+    let l = gadget_leaf(g, v).expect("v should be the root of a gadget");
+    let w = g.neighbors(v).find(|&w| w != l).expect("gadget should have a target");
+    let p = normalise_gadget(g, v, l);
+    g.add_to_phase(w, p);
+    g.remove_vertex(l);
+    g.remove_vertex(v);
+}
+
+safe_rule1!(check_gadget_to_phase, gadget_to_phase_unsafe, gadget_to_phase);
+
+/// Check [hbox_fusion_unsafe] applies
+///
+/// Both vertices must be H-boxes connected by a Hadamard edge, and the
+/// second must have phase pi, i.e. label -1. Any neighbours they share
+/// must be Z spiders connected to both by normal edges.
+pub fn check_hbox_fusion(g: &impl GraphLike, v0: V, v1: V) -> bool {
+    g.vertex_type(v0) == VType::H &&
+    g.vertex_type(v1) == VType::H &&
+    g.edge_type_opt(v0, v1) == Some(EType::H) &&
+    g.phase(v1) == Rational::one() &&
+    g.incident_edges(v1).all(|(w,et)| {
+        w == v0 ||
+        match g.edge_type_opt(v0, w) {
+            Some(et0) => g.vertex_type(w) == VType::Z && et == EType::N && et0 == EType::N,
+            None => true,
+        }
+    })
+}
+
+/// Fuse two H-boxes connected by a Hadamard edge
+///
+/// The legs of v1 are moved on to v0, which keeps its label.
+pub fn hbox_fusion_unsafe(g: &mut impl GraphLike, v0: V, v1: V) {
+    for (w, et) in g.incident_edge_vec(v1) {
+        if w != v0 && !g.connected(v0, w) {
+            g.add_edge_with_type(v0, w, et);
+        }
+    }
+    g.remove_vertex(v1);
+    g.scalar_mut().mul_sqrt2_pow(1);
+}
+
+safe_rule2!(check_hbox_fusion, hbox_fusion_unsafe, hbox_fusion);
+
+/// Check [hbox_to_edge_unsafe] applies
+///
+/// The vertex must be an H-box with two legs and phase pi. If its
+/// neighbours are already connected, they must both be Z or X spiders.
+pub fn check_hbox_to_edge(g: &impl GraphLike, v: V) -> bool {
+    if g.vertex_type(v) != VType::H ||
+       g.degree(v) != 2 ||
+       g.phase(v) != Rational::one()
+    {
+        return false;
+    }
+
+    let ns = g.neighbor_vec(v);
+    !g.connected(ns[0], ns[1]) ||
+    ns.iter().all(|&w| {
+        let t = g.vertex_type(w);
+        t == VType::Z || t == VType::X
+    })
+}
+
+/// Replace an H-box with two legs by a Hadamard edge
+///
+/// Hadamard edges on either leg cancel with the new Hadamard, so the
+/// resulting edge may be a normal edge.
+pub fn hbox_to_edge_unsafe(g: &mut impl GraphLike, v: V) {
+    let es = g.incident_edge_vec(v);
+    let et = if (es[0].1 == EType::H) == (es[1].1 == EType::H) { EType::H } else { EType::N };
+    g.remove_vertex(v);
+    g.add_edge_smart(es[0].0, es[1].0, et);
+    g.scalar_mut().mul_sqrt2_pow(1);
+}
+
+safe_rule1!(check_hbox_to_edge, hbox_to_edge_unsafe, hbox_to_edge);
+
+/// Check [hbox_mult_unsafe] applies
+///
+/// The vertices must be two H-boxes connected to the same Z spiders
+/// by normal edges.
+pub fn check_hbox_mult(g: &impl GraphLike, v0: V, v1: V) -> bool {
+    v0 != v1 &&
+    g.vertex_type(v0) == VType::H &&
+    g.vertex_type(v1) == VType::H &&
+    g.degree(v0) == g.degree(v1) &&
+    g.incident_edges(v0).all(|(w,et)|
+        g.vertex_type(w) == VType::Z && et == EType::N &&
+        g.edge_type_opt(v1, w) == Some(EType::N))
+}
+
+/// Multiply two H-boxes with the same neighbours
+///
+/// The labels multiply, so the phase of v1 is added to v0, and v1 is
+/// removed.
+pub fn hbox_mult_unsafe(g: &mut impl GraphLike, v0: V, v1: V) {
+    let p = g.phase(v1);
+    g.add_to_phase(v0, p);
+    g.remove_vertex(v1);
+}
+
+safe_rule2!(check_hbox_mult, hbox_mult_unsafe, hbox_mult);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -620,4 +839,270 @@ mod tests {
         assert_eq!(g.phase(0), Rational::new(1,1));
         assert_eq!(g.phase(6), Rational::new(1,1));
     }
+
+    #[test]
+    fn pivot_boundary_1() {
+        let mut g = Graph::new();
+
+        for _ in 0..7 { g.add_vertex(VType::Z); }
+        g.set_phase(3, Rational::new(1,1));
+        g.set_phase(4, Rational::new(1,4));
+        g.set_phase(0, Rational::new(1,4));
+        for i in 0..3 { g.add_edge_with_type(i, 3, EType::H); }
+        g.add_edge_with_type(3, 4, EType::H);
+        for i in 5..7 { g.add_edge_with_type(4, i, EType::H); }
+
+        let mut inputs = vec![];
+        for i in 0..3 {
+            let inp = g.add_vertex(VType::B);
+            inputs.push(inp);
+            g.add_edge(i, inp);
+        }
+
+        let out = g.add_vertex(VType::B);
+        g.add_edge_with_type(4, out, EType::H);
+        let mut outputs = vec![out];
+        for i in 5..7 {
+            let outp = g.add_vertex(VType::B);
+            outputs.push(outp);
+            g.add_edge(i, outp);
+        }
+
+        g.set_inputs(inputs);
+        g.set_outputs(outputs);
+
+        assert!(!check_pivot(&g, 3, 4));
+        assert!(!check_pivot_boundary(&g, 4, 3));
+        assert!(!check_pivot_gadget(&g, 3, 4));
+
+        let mut h = g.clone();
+        let success = pivot_boundary(&mut h, 3, 4);
+        assert!(success, "Boundary pivot should match");
+        assert!(!h.contains_vertex(3));
+        assert!(!h.contains_vertex(4));
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+    }
+
+    #[test]
+    fn pivot_gadget_1() {
+        let mut g = Graph::new();
+
+        for _ in 0..7 { g.add_vertex(VType::Z); }
+        g.set_phase(3, Rational::new(1,1));
+        g.set_phase(4, Rational::new(3,4));
+        g.set_phase(6, Rational::new(1,2));
+        for i in 0..3 { g.add_edge_with_type(i, 3, EType::H); }
+        g.add_edge_with_type(3, 4, EType::H);
+        for i in 5..7 { g.add_edge_with_type(4, i, EType::H); }
+        g.add_edge_with_type(0, 5, EType::H);
+
+        let mut inputs = vec![];
+        for i in 0..3 {
+            let inp = g.add_vertex(VType::B);
+            inputs.push(inp);
+            g.add_edge(i, inp);
+        }
+
+        let mut outputs = vec![];
+        for i in 5..7 {
+            let outp = g.add_vertex(VType::B);
+            outputs.push(outp);
+            g.add_edge(i, outp);
+        }
+
+        g.set_inputs(inputs);
+        g.set_outputs(outputs);
+
+        assert!(!check_pivot(&g, 3, 4));
+        assert!(!check_pivot_gadget(&g, 4, 3));
+
+        let mut h = g.clone();
+        let success = pivot_gadget(&mut h, 3, 4);
+        assert!(success, "Gadget pivot should match");
+        assert_eq!(h.num_vertices(), g.num_vertices());
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+
+        // the new gadget isn't a valid target for another gadget pivot
+        for v in h.vertex_vec() {
+            for w in h.vertex_vec() {
+                assert!(!check_pivot_gadget(&h, v, w));
+            }
+        }
+    }
+
+    fn gadget_graph() -> Graph {
+        let mut g = Graph::new();
+        let mut inputs = vec![];
+        let mut outputs = vec![];
+        for _ in 0..3 {
+            let i = g.add_vertex(VType::B);
+            let v = g.add_vertex(VType::Z);
+            let o = g.add_vertex(VType::B);
+            g.add_edge(i, v);
+            g.add_edge(v, o);
+            inputs.push(i);
+            outputs.push(o);
+        }
+        g.set_inputs(inputs);
+        g.set_outputs(outputs);
+        g
+    }
+
+    #[test]
+    fn gadget_fusion_1() {
+        let mut g = gadget_graph();
+        let mut roots = vec![];
+        for &(rp, lp) in &[(0,Rational::new(1,4)), (1,Rational::new(1,2)), (0,Rational::new(3,4))] {
+            let r = g.add_vertex(VType::Z);
+            let l = g.add_vertex(VType::Z);
+            g.set_phase(r, Rational::from_integer(rp));
+            g.set_phase(l, lp);
+            g.add_edge_with_type(r, l, EType::H);
+            for v in &[1, 4, 7] { g.add_edge_with_type(r, *v, EType::H); }
+            roots.push(r);
+        }
+
+        assert!(!check_gadget_fusion(&g, roots[0], roots[0]));
+        assert!(!check_gadget_fusion(&g, roots[0], 1));
+
+        let h = g.clone();
+        assert!(gadget_fusion(&mut g, roots[0], roots[1]));
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+        assert!(gadget_fusion(&mut g, roots[0], roots[2]));
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+        assert_eq!(g.num_vertices(), 11);
+
+        // a second edge out of the root changes the support
+        let r = g.add_vertex(VType::Z);
+        let l = g.add_vertex(VType::Z);
+        g.add_edge_with_type(r, l, EType::H);
+        for v in &[1, 4] { g.add_edge_with_type(r, *v, EType::H); }
+        assert!(!check_gadget_fusion(&g, roots[0], r));
+    }
+
+    #[test]
+    fn gadget_to_phase_1() {
+        let mut g = gadget_graph();
+        let r = g.add_vertex(VType::Z);
+        let l = g.add_vertex(VType::Z);
+        g.set_phase(r, Rational::new(1,1));
+        g.set_phase(l, Rational::new(1,4));
+        g.add_edge_with_type(r, l, EType::H);
+        g.add_edge_with_type(r, 4, EType::H);
+
+        assert!(!check_gadget_to_phase(&g, l));
+        assert!(!check_gadget_to_phase(&g, 4));
+
+        let h = g.clone();
+        assert!(gadget_to_phase(&mut g, r));
+        assert_eq!(g.num_vertices(), 9);
+        assert_eq!(g.phase(4), Rational::new(-1,4));
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+    }
+
+    #[test]
+    fn unfuse_boundary_1() {
+        let mut g = gadget_graph();
+        g.set_phase(4, Rational::new(1,4));
+        assert!(!check_unfuse_boundary(&g, 5, 4));
+        assert!(!check_unfuse_boundary(&g, 4, 0));
+
+        let h = g.clone();
+        assert!(unfuse_boundary(&mut g, 4, 5));
+        assert_eq!(g.num_vertices(), h.num_vertices() + 1);
+        assert!(!g.connected(4, 5));
+        assert_eq!(g.phase(4), Rational::new(1,4));
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+    }
+
+    #[test]
+    fn hbox_fusion_1() {
+        let mut g = gadget_graph();
+        let h0 = g.add_vertex(VType::H);
+        let h1 = g.add_vertex(VType::H);
+        g.set_phase(h0, Rational::new(1,4));
+        g.set_phase(h1, Rational::new(1,1));
+        g.add_edge_with_type(h0, h1, EType::H);
+        g.add_edge(h0, 1);
+        g.add_edge(h0, 4);
+        g.add_edge(h1, 4);
+        g.add_edge_with_type(h1, 7, EType::H);
+
+        assert!(!check_hbox_fusion(&g, h1, h0));
+        assert!(!check_hbox_fusion(&g, h0, 1));
+
+        let h = g.clone();
+        assert!(hbox_fusion(&mut g, h0, h1));
+        assert_eq!(g.degree(h0), 3);
+        assert_eq!(g.edge_type(h0, 7), EType::H);
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+
+        // a shared neighbour with a Hadamard edge can't be merged
+        let mut g = gadget_graph();
+        let h0 = g.add_vertex(VType::H);
+        let h1 = g.add_vertex(VType::H);
+        g.set_phase(h1, Rational::new(1,1));
+        g.add_edge_with_type(h0, h1, EType::H);
+        g.add_edge(h0, 4);
+        g.add_edge_with_type(h1, 4, EType::H);
+        assert!(!check_hbox_fusion(&g, h0, h1));
+    }
+
+    #[test]
+    fn hbox_to_edge_1() {
+        for &(et0, et1) in &[(EType::N, EType::N), (EType::N, EType::H), (EType::H, EType::H)] {
+            let mut g = gadget_graph();
+            let v = g.add_vertex(VType::H);
+            g.set_phase(v, Rational::new(1,1));
+            g.add_edge_with_type(1, v, et0);
+            g.add_edge_with_type(v, 4, et1);
+
+            let h = g.clone();
+            assert!(hbox_to_edge(&mut g, v));
+            assert_eq!(g.num_vertices(), 9);
+            assert_eq!(g.to_tensor4(), h.to_tensor4());
+        }
+
+        // parallel edges are handled by add_edge_smart
+        let mut g = gadget_graph();
+        let v = g.add_vertex(VType::H);
+        g.set_phase(v, Rational::new(1,1));
+        g.add_edge(1, v);
+        g.add_edge(v, 4);
+        g.add_edge_with_type(1, 4, EType::H);
+        let h = g.clone();
+        assert!(hbox_to_edge(&mut g, v));
+        assert!(!g.connected(1, 4));
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+
+        let mut g = gadget_graph();
+        let v = g.add_vertex(VType::H);
+        g.set_phase(v, Rational::new(1,2));
+        g.add_edge(1, v);
+        g.add_edge(v, 4);
+        assert!(!check_hbox_to_edge(&g, v));
+    }
+
+    #[test]
+    fn hbox_mult_1() {
+        let mut g = gadget_graph();
+        let h0 = g.add_vertex(VType::H);
+        let h1 = g.add_vertex(VType::H);
+        g.set_phase(h0, Rational::new(1,4));
+        g.set_phase(h1, Rational::new(1,2));
+        for &v in &[1, 7] {
+            g.add_edge(h0, v);
+            g.add_edge(h1, v);
+        }
+
+        let h = g.clone();
+        assert!(hbox_mult(&mut g, h0, h1));
+        assert_eq!(g.phase(h0), Rational::new(3,4));
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+
+        let h2 = g.add_vertex(VType::H);
+        g.add_edge(h2, 1);
+        g.add_edge(h2, 4);
+        assert!(!check_hbox_mult(&g, h0, h2));
+    }
 }
@@ -7,8 +7,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         if let Some(f) = e?.path().to_str() {
             let time = Instant::now();
             println!("{}", f);
-            Circuit::from_file(f).expect(&format!("circuit failed to parse: {}", f));
+            let c = Circuit::from_file(f).expect(&format!("circuit failed to parse: {}", f));
             println!("...done in {:.2?}", time.elapsed());
+            println!("{}", c.stats());
         }
     }
 
@@ -0,0 +1,49 @@
+//! Check whether two circuit files implement the same unitary
+//!
+//! Usage: verify_equality CIRCUIT1 CIRCUIT2
+//!
+//! Exits with status 0 if the circuits are equal, 1 if they differ, 2 if the
+//! check was inconclusive, and 3 on errors.
+
+use std::env;
+use std::process;
+use std::time::Instant;
+use quizx::circuit::Circuit;
+use quizx::verify::*;
+
+fn read_circuit(f: &str) -> Circuit {
+    match Circuit::from_file(f) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}: {}", f, e);
+            process::exit(3);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} CIRCUIT1 CIRCUIT2", args[0]);
+        process::exit(3);
+    }
+
+    let c1 = read_circuit(&args[1]);
+    let c2 = read_circuit(&args[2]);
+    let time = Instant::now();
+    let result = match verify_equality(&c1, &c2) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(3);
+        }
+    };
+    println!("{}", result);
+    println!("...done in {:.2?}", time.elapsed());
+
+    process::exit(match result {
+        Equality::Equal(_) => 0,
+        Equality::NotEqual(_) => 1,
+        Equality::Unknown => 2,
+    });
+}
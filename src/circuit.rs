@@ -17,12 +17,14 @@
 use std::fmt;
 use std::str;
 use num::{Rational,Zero};
-use regex::Regex;
 use std::fs::File;
 use std::io::prelude::*;
 use crate::scalar::Mod2;
 use crate::gate::*;
 use crate::graph::*;
+pub use crate::qasm::ReadError;
+
+pub mod optimize;
 
 /// A type for quantum circuits
 #[derive(PartialEq,Eq,Clone,Debug)]
@@ -45,7 +47,7 @@ impl Gate {
     pub fn to_qasm(&self) -> String {
         let mut s = String::from(self.qasm_name());
 
-        if let ZPhase | XPhase = self.t {
+        if let ZPhase | XPhase | ParityPhase = self.t {
             s += &format!("({}*pi)", self.phase);
         }
 
@@ -57,6 +59,28 @@ impl Gate {
         s
     }
 
+    /// The gate as an OpenQASM 3 statement, without the semicolon
+    ///
+    /// This only covers gates which are a single statement, i.e. not parity phases or XCX.
+    pub fn to_qasm3(&self) -> String {
+        let mut s = String::from(if self.t == CCZ { "ctrl(2) @ z" } else { self.qasm_name() });
+
+        if let ZPhase | XPhase | ParityPhase = self.t {
+            let (n, d) = (*self.phase.numer(), *self.phase.denom());
+            let pi = match n { 0 => String::from("0"), 1 => String::from("pi"), -1 => String::from("-pi"),
+                               _ => format!("{}*pi", n) };
+            if d == 1 || n == 0 { s += &format!("({})", pi); }
+            else { s += &format!("({}/{})", pi, d); }
+        }
+
+        s += " ";
+        let qs: Vec<String> = self.qs.iter()
+            .map(|i| format!("q[{}]", i)).collect();
+        s += &qs.join(", ");
+
+        s
+    }
+
     pub fn adjoint(&mut self) {
         match self.t {
             ZPhase | XPhase | ParityPhase => {
@@ -118,151 +142,65 @@ impl Circuit {
             &self.to_string()
     }
 
-    fn parse_phase(p: &str) -> Option<Rational> {
-        let spc = Regex::new(r#"\s*"#).unwrap();
-        let starts_pi = Regex::new(r#"^(-?)pi"#).unwrap();
-        let has_pi = Regex::new(r#"\*?pi"#).unwrap();
-
-        // strip whitespace
-        let p1 = spc.replace_all(p, "");
-
-        if has_pi.is_match(p) {
-            // replace (-)pi with (-)1 at the beginning of the string
-            let p1 = starts_pi.replace(&p1, "${1}1");
-            // remove any other occurance of (*)pi
-            let p1 = has_pi.replace(&p1, "");
-
-            // println!("p1 = '{}'", p1);
-
-            if let Ok(r) = p1.parse::<Rational>() { Some(r) }
-            else if let Ok(f) = p1.parse::<f32>() { Rational::approximate_float(f) }
-            else { None }
-        } else {
-            if let Ok(f) = p1.parse::<f32>() {
-                let f1: f32 = f / std::f32::consts::PI;
-                Rational::approximate_float(f1)
-            } else { None }
+    /// Write the circuit as an OpenQASM 3 program, using the gates in `stdgates.inc`
+    ///
+    /// Parity phases and XCX gates are decomposed. The pyzx-specific `init_anc` and
+    /// `post_sel` gates have no equivalent in OpenQASM 3, so they are written as they
+    /// are, and can only be read back by [Circuit::from_qasm].
+    pub fn to_qasm3(&self) -> String {
+        let mut s = format!("OPENQASM 3;\ninclude \"stdgates.inc\";\nqubit[{}] q;\n", self.num_qubits());
+        for g in &self.gates {
+            let gs = match g.t {
+                ParityPhase => g.to_basic_gates(),
+                XCX => vec![
+                    Gate::new(HAD, vec![g.qs[0]]), Gate::new(HAD, vec![g.qs[1]]),
+                    Gate::new(CZ, g.qs.clone()),
+                    Gate::new(HAD, vec![g.qs[0]]), Gate::new(HAD, vec![g.qs[1]]),
+                ],
+                _ => vec![g.clone()],
+            };
+            for g1 in gs { s += &format!("{};\n", g1.to_qasm3()); }
         }
+        s
     }
 
-    pub fn from_qasm(source: &str) -> Result<Circuit, String> {
-        let lines = source.split(';');
-
-        // pattern matching a qreg declaration
-        let qreg: Regex = Regex::new(r#"^qreg\s+([a-zA-Z0-9_]+)\s*\[\s*([0-9]+)\s*\]"#).unwrap();
-
-        // the circuit we are building
-        let mut c = Circuit::new(0);
-
-        // a mapping from named registers to qubit offset and size. Note a vec
-        // with linear lookups seems better than hashing for ~15 or fewer
-        // registers.
-        let mut reg: Vec<(String,usize,usize)> = Vec::new();
-
-        let mut first = true;
-
-        for line in lines {
-            let line = line.trim_start();
-            if line.is_empty() { continue; }
-            if first && line.starts_with("OPENQASM") { first = false; continue; }
-
-            if line.starts_with("include") { continue; }
-
-            if line.starts_with("qreg") {
-                if let Some(caps) = qreg.captures(&line) {
-                    let rname = caps[1].to_owned();
-                    let sz = caps[2].parse::<usize>().unwrap();
-                    // println!("rname = {}, sz = {}", rname, sz);
-                    if reg.iter().any(|(s,_,_)| s == &rname) {
-                        return Err(format!("Re-declaration of qreg: {}", line));
-                    }
-
-                    reg.push((rname, c.nqubits, sz));
-                    c.nqubits += sz as usize;
-                }
-            } else {
-                // strip comments and look for a phase
-                let mut parts = line.splitn(2, "//").next().unwrap().splitn(2, '(');
-
-                // if a phase is found, this first part of the split is the
-                // gate name. If no phase is found, this is the whole command.
-                let mut name = parts.next().unwrap().trim_end();
-
-                // continue if this line only contains a comment
-                if name.is_empty() { continue; }
-
-                // parsed from argument gate has an argument, otherwise
-                // set to 0.
-                let phase;
-
-                // save the rest of the command which isn't gate name or arg
-                let rest;
-
-                if let Some(arg) = parts.next() {
-                    let mut parts = arg.splitn(2, ')');
-                    let arg = parts.next().unwrap();
-                    if let Some(p) = Circuit::parse_phase(arg) {
-                        if let Some(r) = parts.next() {
-                            rest = r;
-                        } else {
-                            return Err(format!("Bad gate application: {}", line));
-                        }
-                        phase = p.mod2()
-                    } else {
-                        return Err(format!("Bad phase: {}", line));
-                    }
-                } else {
-                    let mut parts = name.splitn(2, |c| c==' ' || c == '\t');
-                    name = parts.next().unwrap();
-                    if let Some(r) = parts.next() {
-                        rest = r;
-                    } else {
-                        return Err(format!("Bad gate application: {}", line));
-                    }
-                    phase = Rational::zero();
-                }
-
-                let t = GType::from_qasm_name(&name);
-                if t == UnknownGate {
-                    return Err(format!("Unknown gate: {}", line));
-                }
-
-                let mut qs: Vec<usize> = Vec::new();
-
-                for loc_str in rest.split(",") {
-                    let parts = loc_str.trim().splitn(2, "[").collect::<Vec<_>>();
-
-                    if parts.len() == 2 || parts[1].ends_with("]") {
-                        let rname = parts[0].to_owned();
-                        let q_str = &parts[1][0..parts[1].len()-1];
-                        if let Ok(q) = q_str.parse::<usize>() {
-                            if let Some(&(_,offset,sz)) = reg.iter().find(|(s,_,_)| s == &rname) {
-                                if q < sz {
-                                    qs.push(offset + q as usize);
-                                } else { return Err(format!("Index out of bounds: {}", line)); }
-                            } else { return Err(format!("Undeclared register: {}", line)); }
-                        } else { return Err(format!("Expected numeric qubit index: {}", line)); }
-                    } else { return Err(format!("Bad qubit location: {}", line)); }
-                }
-
-                if let Some(numq) = t.num_qubits() {
-                    if numq != qs.len() {
-                        return Err(format!("Wrong number of qubits for gate: {}", line));
-                    }
-                }
-
-                c.push(Gate { t, qs, phase });
-            }
-        }
+    /// Parse an OpenQASM 2.0 or 3 program
+    ///
+    /// See [crate::qasm] for what is supported.
+    pub fn from_qasm(source: &str) -> Result<Circuit, ReadError> {
+        crate::qasm::parse(source)
+    }
+
+    /// Parse a program in the `.qc` format
+    ///
+    /// See [crate::qc] for what is supported.
+    pub fn from_qc(source: &str) -> Result<Circuit, ReadError> {
+        crate::qc::parse(source)
+    }
 
-        Ok(c)
+    /// Parse a program in Quipper's ASCII format
+    ///
+    /// See [crate::quipper] for what is supported.
+    pub fn from_quipper(source: &str) -> Result<Circuit, ReadError> {
+        crate::quipper::parse(source)
     }
 
-    pub fn from_file(name: &str) -> Result<Circuit, String> {
-        let mut f = File::open(name).map_err(|e| e.to_string())?;
+    /// Read a circuit from a file
+    ///
+    /// Files ending in `.qc` are read with [Circuit::from_qc], files ending in `.quipper`
+    /// or `.quip` with [Circuit::from_quipper], and anything else as QASM. If the file
+    /// can't be read, this returns [ReadError::Io].
+    pub fn from_file(name: &str) -> Result<Circuit, ReadError> {
+        let mut f = File::open(name)?;
         let mut source = String::new();
-        f.read_to_string(&mut source).map_err(|e| e.to_string())?;
-        Circuit::from_qasm(&source)
+        f.read_to_string(&mut source)?;
+        if name.ends_with(".qc") {
+            Circuit::from_qc(&source)
+        } else if name.ends_with(".quipper") || name.ends_with(".quip") {
+            Circuit::from_quipper(&source)
+        } else {
+            Circuit::from_qasm(&source)
+        }
         // let r = BufReader::new(f);
         // let it = r.lines().map(|ln| ln.expect("IO error reading file"));
         // let it = r.split(b';').map(|x|
@@ -272,6 +210,53 @@ impl Circuit {
         // Circuit::from_lines(source.split(';'))
     }
 
+    /// the number of layers of gates, where each gate goes in the layer after the last gate
+    /// on any of its qubits
+    pub fn depth(&self) -> usize {
+        let mut layer = vec![0; self.nqubits];
+        let mut depth = 0;
+        for g in &self.gates {
+            let d = g.qs.iter().map(|&q| layer[q]).max().unwrap_or(0) + 1;
+            for &q in &g.qs { layer[q] = d; }
+            depth = depth.max(d);
+        }
+        depth
+    }
+
+    /// gate counts and depth of the circuit
+    ///
+    /// The result can be printed, giving a summary in the same style as PyZX's `stats()`.
+    pub fn stats(&self) -> CircuitStats {
+        let mut st = CircuitStats {
+            qubits: self.nqubits,
+            total: self.gates.len(),
+            depth: self.depth(),
+            ..CircuitStats::default()
+        };
+
+        for g in &self.gates {
+            st.tcount += g.tcount();
+            if g.is_clifford() { st.clifford += 1; }
+            if g.qs.len() == 2 { st.two_qubit += 1; }
+            match g.t {
+                CNOT => st.cnot += 1,
+                HAD => st.hadamard += 1,
+                _ => {},
+            }
+            // non-Clifford phase gates are only counted towards the T-count
+            if !g.is_clifford() && !matches!(g.t, T | Tdg | ZPhase | XPhase) {
+                st.other += 1;
+            }
+
+            match st.gate_counts.iter_mut().find(|(t, _)| *t == g.t) {
+                Some((_, n)) => *n += 1,
+                None => st.gate_counts.push((g.t, 1)),
+            }
+        }
+
+        st
+    }
+
     /// returns a copy of the circuit, decomposed into 1- and 2-qubit Clifford +
     /// phase gates.
     pub fn to_basic_gates(&self) -> Circuit {
@@ -284,6 +269,14 @@ impl Circuit {
     }
 
     pub fn to_graph<G: GraphLike>(&self) -> G {
+        self.to_graph_with_options(false)
+    }
+
+    /// Convert the circuit to a ZX-diagram
+    ///
+    /// If `phase_gadgets` is true, CCZ, TOFF and ParityPhase gates are added as phase gadgets
+    /// rather than decomposed into 1- and 2-qubit gates. See [Gate::add_to_graph_with_options].
+    pub fn to_graph_with_options<G: GraphLike>(&self, phase_gadgets: bool) -> G {
         let mut graph = G::new();
         let mut qs = Vec::with_capacity(self.nqubits);
         let mut inputs = Vec::with_capacity(self.nqubits);
@@ -301,7 +294,7 @@ impl Circuit {
         graph.set_inputs(inputs);
 
         for g in &self.gates {
-            g.add_to_graph(&mut graph, &mut qs);
+            g.add_to_graph_with_options(&mut graph, &mut qs, phase_gadgets);
         }
 
         let last_row = qs.iter()
@@ -328,6 +321,54 @@ impl Circuit {
     }
 }
 
+/// Gate counts and depth of a circuit, as returned by [Circuit::stats]
+///
+/// The T-count and Clifford count are as defined by [Gate::tcount] and
+/// [Gate::is_clifford]. Gates which are neither Clifford nor single-qubit phase
+/// gates, such as CCZ and TOFF, are counted in `other`.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+pub struct CircuitStats {
+    pub qubits: usize,
+    pub total: usize,
+    pub depth: usize,
+    pub tcount: usize,
+    pub clifford: usize,
+    /// gates acting on exactly two qubits
+    pub two_qubit: usize,
+    pub cnot: usize,
+    pub hadamard: usize,
+    pub other: usize,
+    /// the number of gates of each type, in the order they first appear
+    pub gate_counts: Vec<(GType, usize)>,
+}
+
+impl CircuitStats {
+    /// the number of gates of the given type
+    pub fn count(&self, t: GType) -> usize {
+        self.gate_counts.iter().find(|(t1, _)| *t1 == t).map_or(0, |&(_, n)| n)
+    }
+}
+
+impl fmt::Display for CircuitStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Circuit on {} qubits with {} gates and depth {}.", self.qubits, self.total, self.depth)?;
+        writeln!(f, "        {} is the T-count", self.tcount)?;
+        writeln!(f, "        {} Cliffords among which", self.clifford)?;
+        writeln!(f, "        {} 2-qubit gates ({} CNOT, {} other) and", self.two_qubit, self.cnot, self.two_qubit - self.cnot)?;
+        write!(f, "        {} Hadamard gates.", self.hadamard)?;
+        if self.other > 0 {
+            write!(f, "\nThere are {} gates of a different type", self.other)?;
+        }
+        if !self.gate_counts.is_empty() {
+            let counts: Vec<String> = self.gate_counts.iter()
+                .map(|(t, n)| format!("{}: {}", t.qasm_name(), n))
+                .collect();
+            write!(f, "\nGates: {}", counts.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Display for Circuit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "qreg q[{}];\n", self.num_qubits())?;
@@ -387,7 +428,7 @@ mod tests {
         "#;
 
         let c1 = Circuit::from_qasm(qasm);
-        assert_eq!(c1, Ok(c));
+        assert_eq!(c1.unwrap(), c);
     }
 
     #[test]
@@ -423,7 +464,7 @@ mod tests {
         "#;
 
         let c1 = Circuit::from_qasm(qasm);
-        assert_eq!(c1, Ok(c));
+        assert_eq!(c1.unwrap(), c);
     }
 
     #[test]
@@ -446,7 +487,55 @@ mod tests {
         "#;
 
         let c1 = Circuit::from_qasm(qasm);
-        assert_eq!(c1, Ok(c));
+        assert_eq!(c1.unwrap(), c);
+    }
+
+    #[test]
+    fn stats() {
+        let c = Circuit::from_file("circuits/tof_3.qasm").unwrap();
+        let st = c.stats();
+        assert_eq!(st.qubits, 5);
+        assert_eq!(st.total, 15);
+        assert_eq!(st.tcount, 21);
+        assert_eq!(st.clifford, 12);
+        assert_eq!(st.hadamard, 12);
+        assert_eq!(st.other, 3);
+        assert_eq!(st.depth, 11);
+        assert_eq!(st.gate_counts, vec![(HAD, 12), (TOFF, 3)]);
+
+        let c = Circuit::from_qasm(r#"
+            qreg q[3];
+            rz(pi/2) q[0];
+            rz(pi/3) q[1];
+            t q[2];
+            cx q[0], q[1];
+            cz q[1], q[2];
+            swap q[0], q[2];
+            x q[0];
+        "#).unwrap();
+        let st = c.stats();
+        assert_eq!(st.tcount, 2);
+        assert_eq!(st.clifford, 5);
+        assert_eq!(st.two_qubit, 3);
+        assert_eq!(st.cnot, 1);
+        assert_eq!(st.other, 0);
+        assert_eq!(st.depth, 5);
+        assert_eq!(st.count(ZPhase), 2);
+        assert_eq!(st.count(CCZ), 0);
+        assert_eq!(st.to_string(), "\
+Circuit on 3 qubits with 7 gates and depth 5.
+        2 is the T-count
+        5 Cliffords among which
+        3 2-qubit gates (1 CNOT, 2 other) and
+        0 Hadamard gates.
+Gates: rz: 2, t: 1, cx: 1, cz: 1, swap: 1, x: 1");
+    }
+
+    #[test]
+    fn from_file_errors() {
+        let e = Circuit::from_file("circuits/no_such_circuit.qasm").unwrap_err();
+        assert!(matches!(e, ReadError::Io(_)));
+        assert_eq!(e.position(), None);
     }
 
     #[test]
@@ -517,4 +606,41 @@ mod tests {
         let g: Graph = c.to_graph();
         assert_eq!(c.to_tensor4(), g.to_tensor4());
     }
+
+    #[test]
+    fn tograph_gadgets() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[4];
+            h q[1];
+            ccx q[0], q[1], q[2];
+            ccz q[1], q[2], q[3];
+            h q[2];
+            z q[0];
+        "#).unwrap();
+
+        let g: Graph = c.to_graph();
+        let h: Graph = c.to_graph_with_options(true);
+        assert!(h.num_vertices() < g.num_vertices());
+        assert_eq!(c.to_tensor4(), h.to_tensor4());
+
+        let mut c = Circuit::new(3);
+        c.push(Gate::new_with_phase(ParityPhase, vec![0, 2], Rational::new(1,4)));
+        c.push(Gate::new(HAD, vec![1]));
+        c.push(Gate::new_with_phase(ParityPhase, vec![0, 1, 2], Rational::new(3,4)));
+        c.push(Gate::new_with_phase(ParityPhase, vec![1], Rational::new(1,2)));
+
+        let h: Graph = c.to_graph_with_options(true);
+        assert_eq!(h.num_vertices(), 17);
+        assert_eq!(c.to_basic_gates().to_tensor4(), h.to_tensor4());
+
+        // too wide for the support to fit in the bits of a usize
+        let n = 70;
+        let mut c = Circuit::new(n);
+        c.push(Gate::new_with_phase(ParityPhase, (0..n).collect(), Rational::new(1,4)));
+        let h: Graph = c.to_graph_with_options(true);
+        assert_eq!(h.num_vertices(), 3 * n + 2);
+        let leaf = h.vertices().find(|&v| h.phase(v) == Rational::new(1,4)).unwrap();
+        let root = h.neighbors(leaf).next().unwrap();
+        assert_eq!(h.degree(root), n + 1);
+    }
 }
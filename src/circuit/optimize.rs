@@ -0,0 +1,310 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cheap peephole optimisations on circuits
+//!
+//! These play the role of PyZX's `basic_optimization`, and are meant to be run before
+//! turning a circuit into a ZX-diagram and after extracting it again. Every pass gives
+//! a circuit which is exactly equal to the original, including the global phase.
+//!
+//! Gates are moved past each other using the fact that each gate in [GType] (apart from
+//! HAD, SWAP and the non-unitary gates) is diagonal in the Z basis on some of its qubits
+//! and in the X basis on the others. For example, a CNOT is Z-like on its control and
+//! X-like on its target, so a T gate can pass through the control of a CNOT, and an
+//! XPhase through its target. Two such gates commute if they are alike on every qubit
+//! they share.
+
+use num::{Rational,Zero,One};
+use crate::circuit::Circuit;
+use crate::gate::*;
+use crate::scalar::Mod2;
+
+#[derive(PartialEq,Eq,Clone,Copy,Debug)]
+enum Basis { Z, X }
+
+/// The basis a gate is diagonal in on the given qubit, if any
+fn basis(g: &Gate, q: usize) -> Option<Basis> {
+    match g.t {
+        ZPhase | Z | S | T | Sdg | Tdg | CZ | CCZ | ParityPhase => Some(Basis::Z),
+        XPhase | NOT | XCX => Some(Basis::X),
+        CNOT | TOFF => Some(if g.qs.last() == Some(&q) { Basis::X } else { Basis::Z }),
+        _ => None,
+    }
+}
+
+/// Whether two gates commute, going by the bases they are diagonal in
+fn commutes(g: &Gate, h: &Gate) -> bool {
+    g.qs.iter().all(|&q| {
+        !h.qs.contains(&q) || (basis(g, q).is_some() && basis(g, q) == basis(h, q))
+    })
+}
+
+/// The phase of a single-qubit Z rotation
+fn z_phase(g: &Gate) -> Option<Rational> {
+    match g.t {
+        ZPhase => Some(g.phase),
+        Z => Some(Rational::one()),
+        S => Some(Rational::new(1, 2)),
+        Sdg => Some(Rational::new(-1, 2)),
+        T => Some(Rational::new(1, 4)),
+        Tdg => Some(Rational::new(-1, 4)),
+        _ => None,
+    }
+}
+
+/// The phase of a single-qubit X rotation
+fn x_phase(g: &Gate) -> Option<Rational> {
+    match g.t {
+        XPhase => Some(g.phase),
+        NOT => Some(Rational::one()),
+        _ => None,
+    }
+}
+
+/// A Z rotation, using a named gate if there is one
+fn z_rotation(q: usize, phase: Rational) -> Option<Gate> {
+    let phase = phase.mod2();
+    let t = if phase.is_zero() { return None; }
+        else if phase == Rational::one() { Z }
+        else if phase == Rational::new(1, 2) { S }
+        else if phase == Rational::new(-1, 2) { Sdg }
+        else if phase == Rational::new(1, 4) { T }
+        else if phase == Rational::new(-1, 4) { Tdg }
+        else { return Some(Gate::new_with_phase(ZPhase, vec![q], phase)); };
+    Some(Gate::new(t, vec![q]))
+}
+
+/// An X rotation, using NOT if possible
+fn x_rotation(q: usize, phase: Rational) -> Option<Gate> {
+    let phase = phase.mod2();
+    if phase.is_zero() { None }
+    else if phase == Rational::one() { Some(Gate::new(NOT, vec![q])) }
+    else { Some(Gate::new_with_phase(XPhase, vec![q], phase)) }
+}
+
+fn same_set(a: &[usize], b: &[usize]) -> bool {
+    a.len() == b.len() && a.iter().all(|q| b.contains(q))
+}
+
+/// Try to combine the gate `g` with a gate `h` just before it
+///
+/// This returns None if they can't be combined, `Some(None)` if they cancel, and
+/// otherwise the gate they combine to.
+fn merge(h: &Gate, g: &Gate) -> Option<Option<Gate>> {
+    if let (Some(p), Some(p1)) = (z_phase(h), z_phase(g)) {
+        return if h.qs == g.qs { Some(z_rotation(g.qs[0], p + p1)) } else { None };
+    }
+    if let (Some(p), Some(p1)) = (x_phase(h), x_phase(g)) {
+        return if h.qs == g.qs { Some(x_rotation(g.qs[0], p + p1)) } else { None };
+    }
+    if h.t != g.t { return None; }
+    match g.t {
+        HAD | CNOT if h.qs == g.qs => Some(None),
+        CZ | CCZ | SWAP | XCX if same_set(&h.qs, &g.qs) => Some(None),
+        TOFF if h.qs[2] == g.qs[2] && same_set(&h.qs[..2], &g.qs[..2]) => Some(None),
+        ParityPhase if same_set(&h.qs, &g.qs) => {
+            let phase = (h.phase + g.phase).mod2();
+            if phase.is_zero() { Some(None) }
+            else { Some(Some(Gate::new_with_phase(ParityPhase, h.qs.clone(), phase))) }
+        },
+        _ => None,
+    }
+}
+
+/// A list of gates where gates can be removed or replaced in place, with the gates on
+/// each qubit indexed for quick access
+struct GateList {
+    gates: Vec<Option<Gate>>,
+    /// the positions in `gates` of the gates on each qubit, in order
+    on_qubit: Vec<Vec<usize>>,
+    nqubits: usize,
+}
+
+impl GateList {
+    fn new(nqubits: usize) -> GateList {
+        GateList { gates: Vec::new(), on_qubit: vec![Vec::new(); nqubits], nqubits }
+    }
+
+    fn from_circuit(c: &Circuit) -> GateList {
+        let mut gl = GateList::new(c.num_qubits());
+        for g in &c.gates { gl.push(g.clone()); }
+        gl
+    }
+
+    fn push(&mut self, g: Gate) {
+        for &q in &g.qs { self.on_qubit[q].push(self.gates.len()); }
+        self.gates.push(Some(g));
+    }
+
+    /// the positions of the gates remaining on the qubit
+    fn live_on(&self, q: usize) -> Vec<usize> {
+        self.on_qubit[q].iter().copied().filter(|&i| self.gates[i].is_some()).collect()
+    }
+
+    /// Add a gate to the end, after trying to commute it backwards to a gate it cancels
+    /// or merges with
+    fn add(&mut self, g: Gate) {
+        // on each qubit, look for the last gate which doesn't commute with g. This
+        // should be the same gate on every qubit, and one that g merges with.
+        let mut target = None;
+        for (k, &q) in g.qs.iter().enumerate() {
+            let mut found = None;
+            for &i in self.on_qubit[q].iter().rev() {
+                let h = match &self.gates[i] { Some(h) => h, None => continue };
+                if merge(h, &g).is_some() { found = Some(i); break; }
+                if !commutes(h, &g) { break; }
+            }
+            if k == 0 { target = found; }
+            if found.is_none() || found != target { target = None; break; }
+        }
+
+        match target {
+            Some(i) => {
+                let h = self.gates[i].take().unwrap();
+                // the merged gate acts on the same qubits as h, so the index is unchanged
+                self.gates[i] = merge(&h, &g).unwrap();
+            },
+            None => self.push(g),
+        }
+    }
+
+    fn into_circuit(self) -> Circuit {
+        let mut c = Circuit::new(self.nqubits);
+        for g in self.gates.into_iter().flatten() { c.push(g); }
+        c
+    }
+}
+
+/// Cancel pairs of gates which are inverse to each other, and merge rotations
+///
+/// Gates are commuted past each other where possible to find pairs like H·H, CNOT·CNOT
+/// or S·Sdg, and rotations about the same axis on the same qubit, like T·T = S. Merged Z
+/// rotations use the named gates Z, S, Sdg, T and Tdg when possible.
+pub fn cancel_gates(c: &Circuit) -> Circuit {
+    let mut gl = GateList::new(c.num_qubits());
+    for g in &c.gates { gl.add(g.clone()); }
+    gl.into_circuit()
+}
+
+/// Remove pairs of Hadamard gates around other gates
+///
+/// This uses H·P(a)·H = XPhase(a) for Z rotations (and the other way round for X
+/// rotations), and H·CNOT·H = CZ for a Hadamard on both sides of the target of a CNOT
+/// (and the other way round for CZ). With [cancel_gates], this also turns a CNOT with
+/// Hadamards on all four sides into a CNOT going the other way.
+pub fn reduce_hadamards(c: &Circuit) -> Circuit {
+    let mut gl = GateList::from_circuit(c);
+    for q in 0..gl.nqubits {
+        let live = gl.live_on(q);
+        let mut a = 0;
+        while a + 2 < live.len() {
+            let (i, j, k) = (live[a], live[a+1], live[a+2]);
+            let is_had = |i: usize| matches!(&gl.gates[i], Some(g) if g.t == HAD);
+            if !is_had(i) || !is_had(k) { a += 1; continue; }
+
+            let g = gl.gates[j].as_ref().unwrap();
+            let replacement = if let Some(p) = z_phase(g) {
+                x_rotation(q, p)
+            } else if let Some(p) = x_phase(g) {
+                z_rotation(q, p)
+            } else if g.t == CNOT && g.qs[1] == q {
+                Some(Gate::new(CZ, g.qs.clone()))
+            } else if g.t == CZ {
+                let c = if g.qs[0] == q { g.qs[1] } else { g.qs[0] };
+                Some(Gate::new(CNOT, vec![c, q]))
+            } else {
+                a += 1;
+                continue;
+            };
+
+            gl.gates[i] = None;
+            gl.gates[j] = replacement;
+            gl.gates[k] = None;
+            a += 3;
+        }
+    }
+    gl.into_circuit()
+}
+
+/// Run [cancel_gates] and [reduce_hadamards] until the number of gates stops going down
+pub fn basic_optimization(c: &Circuit) -> Circuit {
+    let mut c = cancel_gates(c);
+    loop {
+        let c1 = cancel_gates(&reduce_hadamards(&c));
+        if c1.num_gates() >= c.num_gates() { return c; }
+        c = c1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+    use crate::test_util::check_benchmarks;
+
+    fn optimizes_to(source: &str, expected: &str, f: fn(&Circuit) -> Circuit) {
+        let c = Circuit::from_qasm(source).unwrap();
+        let c1 = f(&c);
+        assert_eq!(c1, Circuit::from_qasm(expected).unwrap(), "{}", source);
+        assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10), "{}", source);
+    }
+
+    #[test]
+    fn cancellation() {
+        optimizes_to("qreg q[2]; h q[0]; h q[0];", "qreg q[2];", cancel_gates);
+        optimizes_to("qreg q[2]; cx q[0], q[1]; cx q[0], q[1];", "qreg q[2];", cancel_gates);
+        optimizes_to("qreg q[2]; s q[1]; sdg q[1];", "qreg q[2];", cancel_gates);
+        optimizes_to("qreg q[2]; cz q[0], q[1]; cz q[1], q[0];", "qreg q[2];", cancel_gates);
+        optimizes_to("qreg q[2]; h q[0]; x q[0]; x q[0]; h q[0];", "qreg q[2];", cancel_gates);
+        optimizes_to("qreg q[3]; ccx q[0], q[1], q[2]; t q[0]; ccx q[1], q[0], q[2];",
+                     "qreg q[3]; t q[0];", cancel_gates);
+        // CNOTs going opposite ways don't cancel
+        optimizes_to("qreg q[2]; cx q[0], q[1]; cx q[1], q[0];",
+                     "qreg q[2]; cx q[0], q[1]; cx q[1], q[0];", cancel_gates);
+    }
+
+    #[test]
+    fn rotation_merging() {
+        optimizes_to("qreg q[2]; t q[0]; cx q[0], q[1]; t q[0];",
+                     "qreg q[2]; s q[0]; cx q[0], q[1];", cancel_gates);
+        optimizes_to("qreg q[2]; rx(pi/4) q[1]; cx q[0], q[1]; x q[1];",
+                     "qreg q[2]; rx(5*pi/4) q[1]; cx q[0], q[1];", cancel_gates);
+        optimizes_to("qreg q[2]; rz(pi/3) q[0]; cz q[0], q[1]; rz(pi/6) q[0]; s q[0];",
+                     "qreg q[2]; z q[0]; cz q[0], q[1];", cancel_gates);
+        // a Z rotation can't pass through the target of a CNOT, or a Hadamard
+        optimizes_to("qreg q[2]; t q[1]; cx q[0], q[1]; t q[1];",
+                     "qreg q[2]; t q[1]; cx q[0], q[1]; t q[1];", cancel_gates);
+        optimizes_to("qreg q[1]; t q[0]; h q[0]; t q[0];",
+                     "qreg q[1]; t q[0]; h q[0]; t q[0];", cancel_gates);
+    }
+
+    #[test]
+    fn hadamard_reduction() {
+        optimizes_to("qreg q[1]; h q[0]; s q[0]; h q[0];", "qreg q[1]; rx(pi/2) q[0];", reduce_hadamards);
+        optimizes_to("qreg q[1]; h q[0]; x q[0]; h q[0];", "qreg q[1]; z q[0];", reduce_hadamards);
+        optimizes_to("qreg q[2]; h q[1]; cx q[0], q[1]; h q[1];", "qreg q[2]; cz q[0], q[1];", reduce_hadamards);
+        optimizes_to("qreg q[2]; h q[0]; cz q[0], q[1]; h q[0];", "qreg q[2]; cx q[1], q[0];", reduce_hadamards);
+        optimizes_to("qreg q[2]; h q[0]; h q[1]; cx q[0], q[1]; h q[0]; h q[1];",
+                     "qreg q[2]; cx q[1], q[0];", basic_optimization);
+    }
+
+    #[test]
+    fn benchmarks() {
+        for (f, c, c1) in check_benchmarks(basic_optimization) {
+            assert!(c1.num_gates() < c.num_gates(), "{}", f);
+        }
+    }
+}
@@ -1,85 +1,687 @@
 use crate::circuit::*;
 use crate::gate::*;
 use crate::graph::*;
+use crate::linalg::*;
+use crate::architecture::*;
+use crate::basic_rules::*;
 use num::{Rational, Zero};
 use rustc_hash::FxHashMap;
-use std::iter::FromIterator;
+use std::fmt;
 
-/// Extraction couldn't finish. Returns a message, a
-/// partially-extracted circuit, and the remainder of
-/// the graph.
-pub type ExtractError<G> = (String, Circuit, G);
+/// Extraction couldn't finish
+///
+/// Each variant carries the part of the circuit extracted so far, which goes
+/// between the remaining graph and the outputs, and the remaining graph itself.
+/// If the graph was rejected before extraction started, the circuit is empty.
+#[derive(Debug,Clone)]
+pub enum ExtractError<G> {
+    /// The graph has different numbers of inputs and outputs
+    BoundaryMismatch { inputs: usize, outputs: usize, circuit: Circuit, graph: G },
+    /// A boundary vertex doesn't have exactly one neighbour
+    BadBoundary { vertex: V, degree: usize, circuit: Circuit, graph: G },
+    /// A vertex is neither a boundary nor a Z spider
+    NotGraphLike { vertex: V, ty: VType, circuit: Circuit, graph: G },
+    /// Two spiders are connected by a plain edge instead of a Hadamard edge
+    NonHadamardEdge { edge: (V,V), circuit: Circuit, graph: G },
+    /// None of the spiders on the frontier, which sit on the given qubits, could
+    /// be extracted, so the graph has no gflow
+    NoGflow { qubits: Vec<usize>, circuit: Circuit, graph: G },
+    /// Once all the spiders were extracted, the given output (on the given qubit)
+    /// was not connected to an input
+    Disconnected { output: V, qubit: usize, circuit: Circuit, graph: G },
+    /// The architecture to route the circuit on has the wrong number of qubits
+    ArchitectureMismatch { qubits: usize, architecture_qubits: usize, circuit: Circuit, graph: G },
+    /// The architecture to route the circuit on is not connected
+    DisconnectedArchitecture { circuit: Circuit, graph: G },
+}
+
+impl<G> ExtractError<G> {
+    /// The circuit extracted before the error
+    pub fn circuit(&self) -> &Circuit {
+        use ExtractError::*;
+        match self {
+            BoundaryMismatch { circuit, .. } | BadBoundary { circuit, .. } |
+            NotGraphLike { circuit, .. } | NonHadamardEdge { circuit, .. } |
+            NoGflow { circuit, .. } | Disconnected { circuit, .. } |
+            ArchitectureMismatch { circuit, .. } | DisconnectedArchitecture { circuit, .. } => circuit,
+        }
+    }
+
+    /// The part of the graph that wasn't extracted
+    pub fn graph(&self) -> &G {
+        use ExtractError::*;
+        match self {
+            BoundaryMismatch { graph, .. } | BadBoundary { graph, .. } |
+            NotGraphLike { graph, .. } | NonHadamardEdge { graph, .. } |
+            NoGflow { graph, .. } | Disconnected { graph, .. } |
+            ArchitectureMismatch { graph, .. } | DisconnectedArchitecture { graph, .. } => graph,
+        }
+    }
+
+    /// Take the partially extracted circuit and the remaining graph
+    pub fn into_parts(self) -> (Circuit, G) {
+        use ExtractError::*;
+        match self {
+            BoundaryMismatch { circuit, graph, .. } | BadBoundary { circuit, graph, .. } |
+            NotGraphLike { circuit, graph, .. } | NonHadamardEdge { circuit, graph, .. } |
+            NoGflow { circuit, graph, .. } | Disconnected { circuit, graph, .. } |
+            ArchitectureMismatch { circuit, graph, .. } |
+            DisconnectedArchitecture { circuit, graph } => (circuit, graph),
+        }
+    }
+}
+
+impl<G> fmt::Display for ExtractError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ExtractError::*;
+        match self {
+            BoundaryMismatch { inputs, outputs, .. } =>
+                write!(f, "Cannot extract a circuit with {} inputs and {} outputs", inputs, outputs),
+            BadBoundary { vertex, degree, .. } =>
+                write!(f, "Boundary vertex {} should have degree 1, but has degree {}", vertex, degree),
+            NotGraphLike { vertex, ty, .. } =>
+                write!(f, "Graph is not graph-like: vertex {} has type {:?}", vertex, ty),
+            NonHadamardEdge { edge: (v, w), .. } =>
+                write!(f, "Graph is not graph-like: edge {}-{} should be a Hadamard edge", v, w),
+            NoGflow { qubits, .. } =>
+                write!(f, "No extractable vertex found on qubits {:?}, graph has no gflow", qubits),
+            Disconnected { output, qubit, .. } =>
+                write!(f, "Output {} on qubit {} is not connected to an input after extraction", output, qubit),
+            ArchitectureMismatch { qubits, architecture_qubits, .. } =>
+                write!(f, "Cannot route a circuit on {} qubits to an architecture with {} qubits", qubits, architecture_qubits),
+            DisconnectedArchitecture { .. } =>
+                write!(f, "Cannot route a circuit to a disconnected architecture"),
+        }
+    }
+}
+
+impl<G: fmt::Debug> std::error::Error for ExtractError<G> {}
 
-trait ToCircuit: Clone {
+/// Convert a ZX-diagram back into a circuit
+///
+/// This is the circuit extraction algorithm from PyZX. It works from the
+/// outputs to the inputs, pulling gates off a "frontier" of spiders next to
+/// the outputs, and succeeds whenever the diagram has generalised flow.
+///
+/// The diagram must be graph-like, i.e. all spiders are Z spiders
+/// connected by Hadamard edges, and each boundary is connected to exactly
+/// one vertex. Extraction does not keep track of the global scalar, so the
+/// resulting circuit is only equal to the diagram up to a non-zero scalar.
+pub trait ToCircuit: Clone {
     fn into_circuit(self) -> Result<Circuit, ExtractError<Self>>;
     fn to_circuit(&self) -> Result<Circuit, ExtractError<Self>> {
         self.clone().into_circuit()
     }
+
+    /// Extract a circuit whose 2-qubit gates only act on qubits coupled in the
+    /// given architecture
+    ///
+    /// This works the same way as [ToCircuit::into_circuit], but the CNOTs from
+    /// each round of Gaussian elimination, CZs between distant qubits and the final
+    /// permutation are synthesised with [steiner_gauss].
+    fn into_circuit_routed(self, arch: &Architecture) -> Result<Circuit, ExtractError<Self>>;
+    fn to_circuit_routed(&self, arch: &Architecture) -> Result<Circuit, ExtractError<Self>> {
+        self.clone().into_circuit_routed(arch)
+    }
 }
 
-impl<G: GraphLike + Clone> ToCircuit for G {
-    fn into_circuit(mut self) -> Result<Circuit, ExtractError<G>> {
-        use GType::*;
-        let mut c = Circuit::new(self.outputs().len());
-        let mut gadgets = FxHashMap::default();
-        let mut qubit_map = FxHashMap::default();
-
-        for v in self.vertices() {
-            if self.degree(v) == 1 &&
-               !self.inputs().contains(&v) &&
-               !self.outputs().contains(&v)
-            {
-                let n = self.neighbors(v).next().unwrap();
-                gadgets.insert(n, v);
+/// Records the row operations performed during Gaussian elimination, so
+/// they can be turned into CNOT gates.
+struct RowOps(Vec<(usize,usize)>);
+
+impl RowColOps for RowOps {
+    fn row_add(&mut self, r0: usize, r1: usize) { self.0.push((r0, r1)); }
+    fn col_add(&mut self, _: usize, _: usize) {}
+    fn row_swap(&mut self, _: usize, _: usize) {}
+    fn col_swap(&mut self, _: usize, _: usize) {}
+}
+
+/// Push CNOTs, given in circuit order, on to a circuit which is being extracted
+/// backwards
+///
+/// If there is an architecture and some of the CNOTs are on qubits which aren't coupled,
+/// they are replaced by a circuit with the same parity matrix from [steiner_gauss].
+fn push_cnots(c: &mut Circuit, cnots: &[(usize,usize)], arch: Option<&Architecture>) {
+    match arch {
+        Some(arch) if cnots.iter().any(|&(q0, q1)| !arch.connected(q0, q1)) => {
+            let mut m = Mat2::id(c.num_qubits());
+            for &(q0, q1) in cnots { m.row_add(q0, q1); }
+            let routed = steiner_gauss(&m, arch).expect("Architecture should be connected");
+            for g in routed.gates.into_iter().rev() { c.push(g); }
+        },
+        _ => {
+            for &(q0, q1) in cnots.iter().rev() { c.push(Gate::new(CNOT, vec![q0, q1])); }
+        },
+    }
+}
+
+/// Push a CZ on to a circuit which is being extracted backwards, turning it into a
+/// routed CNOT between Hadamards if the qubits aren't coupled
+fn push_cz(c: &mut Circuit, q0: usize, q1: usize, arch: Option<&Architecture>) {
+    match arch {
+        Some(arch) if !arch.connected(q0, q1) => {
+            c.push(Gate::new(HAD, vec![q1]));
+            push_cnots(c, &[(q0, q1)], Some(arch));
+            c.push(Gate::new(HAD, vec![q1]));
+        },
+        _ => c.push(Gate::new(CZ, vec![q0, q1])),
+    }
+}
+
+/// Replace the SWAPs from [permutation_circuit] by CNOTs on coupled qubits, if
+/// there is an architecture and some SWAP is on qubits which aren't coupled
+fn route_permutation(perm: Circuit, arch: Option<&Architecture>) -> Circuit {
+    let arch = match arch {
+        Some(arch) if perm.gates.iter().any(|g| g.t == SWAP && !arch.connected(g.qs[0], g.qs[1])) => arch,
+        _ => return perm,
+    };
+
+    // the Hadamards all come before the SWAPs
+    let mut c = Circuit::new(perm.num_qubits());
+    let mut m = Mat2::id(perm.num_qubits());
+    for g in perm.gates {
+        if g.t == SWAP { m.row_swap(g.qs[0], g.qs[1]); }
+        else { c.push(g); }
+    }
+    c + steiner_gauss(&m, arch).expect("Architecture should be connected")
+}
+
+/// Check the graph can be extracted, handing it back if so
+fn check_graph_like<G: GraphLike>(g: G) -> Result<G, ExtractError<G>> {
+    let circuit = Circuit::new(g.outputs().len());
+    if g.inputs().len() != g.outputs().len() {
+        let (inputs, outputs) = (g.inputs().len(), g.outputs().len());
+        return Err(ExtractError::BoundaryMismatch { inputs, outputs, circuit, graph: g });
+    }
+
+    for v in g.vertices() {
+        match g.vertex_type(v) {
+            VType::B => {
+                let degree = g.degree(v);
+                if degree != 1 {
+                    return Err(ExtractError::BadBoundary { vertex: v, degree, circuit, graph: g });
+                }
+            },
+            VType::Z => {
+                let plain = g.incident_edges(v).find(|&(w, et)| g.vertex_type(w) != VType::B && et != EType::H);
+                if let Some((w, _)) = plain {
+                    return Err(ExtractError::NonHadamardEdge { edge: (v, w), circuit, graph: g });
+                }
+            },
+            ty => return Err(ExtractError::NotGraphLike { vertex: v, ty, circuit, graph: g }),
+        }
+    }
+
+    Ok(g)
+}
+
+/// Look for a phase gadget among the neighbours of the frontier, and if
+/// there is one, pivot it into the frontier
+///
+/// The frontier vertex v is first unfused from its output, so the pivot
+/// deletes v and w, and the new spider takes over v's qubit.
+fn remove_gadget(g: &mut impl GraphLike, frontier: &mut [V],
+                 qubit_map: &mut FxHashMap<V,usize>, neighbours: &[V]) -> bool
+{
+    for &w in neighbours {
+        let is_gadget = g.phase(w).is_integer() &&
+            g.incident_edges(w).all(|(n,et)| g.vertex_type(n) == VType::Z && et == EType::H) &&
+            g.neighbors(w).any(|n| g.degree(n) == 1);
+        if !is_gadget { continue; }
+
+        if let Some(i) = frontier.iter().position(|&v| g.connected(v, w)) {
+            let v = frontier[i];
+            let b = g.neighbors(v)
+                .find(|n| g.outputs().contains(n))
+                .expect("Frontier vertex should be next to an output");
+            let vd = VData {
+                ty: VType::Z,
+                phase: Rational::zero(),
+                qubit: g.qubit(v),
+                row: g.row(v) };
+            let x = g.add_vertex_with_data(vd);
+            g.remove_edge(v, b);
+            g.add_edge_with_type(x, b, EType::H);
+            g.add_edge_with_type(x, v, EType::H);
+            pivot_unsafe(g, w, v);
+
+            frontier[i] = x;
+            let q = qubit_map[&v];
+            qubit_map.insert(x, q);
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Turn the wires remaining after extraction into Hadamards and SWAPs
+///
+/// Once all the spiders have been extracted, each output is connected to
+/// an input either directly or via a phase-free spider. The returned circuit
+/// applies any Hadamards on the inputs, then permutes the qubits. If some
+/// output is not connected to an input, this returns the output and its qubit.
+fn permutation_circuit(g: &impl GraphLike) -> Result<Circuit, (V, usize)> {
+    let n = g.outputs().len();
+    let mut c = Circuit::new(n);
+
+    // perm[i] is the input connected to output i
+    let mut perm = Vec::with_capacity(n);
+    for (q, &o) in g.outputs().iter().enumerate() {
+        let v = g.neighbors(o).next().expect("Output should have a neighbour");
+        let mut et = g.edge_type(o, v);
+        let mut w = v;
+        if g.vertex_type(v) == VType::Z && g.degree(v) == 2 && g.phase(v).is_zero() {
+            w = g.neighbors(v).find(|&n| n != o).unwrap();
+            if g.edge_type(v, w) == EType::H {
+                et = if et == EType::H { EType::N } else { EType::H };
             }
         }
 
-        let mut frontier = Vec::new();
-        for (i,&o) in self.outputs().iter().enumerate() {
-            if let Some(v) = self.neighbors(o).next() {
-                if self.inputs().contains(&v) { continue; }
-                frontier.push(v);
-                qubit_map.insert(v, i);
-            } else {
-                return Err((format!("Bad output vertex {}", o), c, self));
+        if let Some(j) = g.inputs().iter().position(|&i| i == w) {
+            if et == EType::H { c.push(Gate::new(HAD, vec![j])); }
+            perm.push(j);
+        } else {
+            return Err((o, q));
+        }
+    }
+
+    // loc[j] is the current position of input j, at[k] is the input at
+    // position k
+    let mut loc: Vec<usize> = (0..n).collect();
+    let mut at: Vec<usize> = (0..n).collect();
+    for (i, &j) in perm.iter().enumerate() {
+        let k = loc[j];
+        if k != i {
+            c.push(Gate::new(SWAP, vec![i, k]));
+            let j1 = at[i];
+            at.swap(i, k);
+            loc[j] = i;
+            loc[j1] = k;
+        }
+    }
+
+    Ok(c)
+}
+
+/// Extract a circuit, optionally routing its 2-qubit gates to fit an architecture
+fn extract<G: GraphLike + Clone>(g: G, arch: Option<&Architecture>) -> Result<Circuit, ExtractError<G>> {
+    use GType::*;
+    let mut g = check_graph_like(g)?;
+    let mut c = Circuit::new(g.outputs().len());
+    if let Some(arch) = arch {
+        if arch.num_qubits() != c.num_qubits() {
+            let (qubits, architecture_qubits) = (c.num_qubits(), arch.num_qubits());
+            return Err(ExtractError::ArchitectureMismatch { qubits, architecture_qubits, circuit: c, graph: g });
+        }
+        if !arch.is_connected() {
+            return Err(ExtractError::DisconnectedArchitecture { circuit: c, graph: g });
+        }
+    }
+    let mut qubit_map = FxHashMap::default();
+
+    let mut frontier = Vec::new();
+    for (i,o) in g.outputs().clone().into_iter().enumerate() {
+        if let Some(mut v) = g.neighbors(o).next() {
+            if g.inputs().contains(&v) { continue; }
+
+            // if v is already on the frontier, split it from this output
+            // with a phase-free spider
+            if frontier.contains(&v) {
+                let et = g.edge_type(v, o);
+                let vd = VData {
+                    ty: VType::Z,
+                    phase: Rational::zero(),
+                    qubit: g.qubit(o),
+                    row: g.row(o) };
+                let x = g.add_vertex_with_data(vd);
+                g.remove_edge(v, o);
+                g.add_edge_with_type(o, x,
+                    if et == EType::H { EType::N } else { EType::H });
+                g.add_edge_with_type(x, v, EType::H);
+                v = x;
+            }
+
+            frontier.push(v);
+            qubit_map.insert(v, i);
+        } else {
+            return Err(ExtractError::BadBoundary { vertex: o, degree: 0, circuit: c, graph: g });
+        }
+    }
+
+    // Gates are pushed on to c from the outputs to the inputs, so c is
+    // reversed at the end.
+    loop {
+        for &v in &frontier {
+            let q = qubit_map[&v];
+            let b = g.neighbors(v)
+                .find(|w| g.outputs().contains(w))
+                .unwrap(); // frontier should be next to an output
+            let et = g.edge_type(v,b);
+            if et == EType::H {
+                c.push(Gate::new(HAD, vec![q]));
+                g.set_edge_type(v, b, EType::N);
+            }
+
+            let p = g.phase(v);
+            if !p.is_zero() {
+                c.push(Gate::new_with_phase(ZPhase, vec![q], p));
+                g.set_phase(v, Rational::zero());
             }
         }
 
-        loop {
-            for &v in &frontier {
-                let q = qubit_map[&v];
-                let b = self.neighbors(v)
-                    .filter(|w| self.outputs().contains(w))
-                    .next()
-                    .unwrap(); // frontier should be next to an output
-                let et = self.edge_type(v,b);
-                if et == EType::H {
-                    c.push(Gate::new(HAD, vec![q]));
-                    self.set_edge_type(v, b, EType::N);
+        // TODO: CZ optimisation (maybe)
+        for &v in &frontier {
+            for w in g.neighbor_vec(v) {
+                if frontier.contains(&w) {
+                    g.remove_edge(v, w);
+                    push_cz(&mut c, qubit_map[&v], qubit_map[&w], arch);
                 }
+            }
+        }
+
+        // Frontier vertices whose only other neighbour is an input are
+        // finished. Otherwise, any inputs are split off with a fresh
+        // spider, so every neighbour of the frontier is a Z spider.
+        let mut neighbours = Vec::new();
+        for v in frontier.clone() {
+            let ns: Vec<V> = g.neighbors(v)
+                .filter(|w| !g.outputs().contains(w))
+                .collect();
+            if ns.len() == 1 && g.inputs().contains(&ns[0]) {
+                frontier.retain(|&w| w != v);
+                continue;
+            }
 
-                let p = self.phase(v);
-                if !p.is_zero() {
-                    c.push(Gate::new_with_phase(ZPhase, vec![q], p));
-                    self.set_phase(v, Rational::zero());
+            for w in ns {
+                if g.inputs().contains(&w) {
+                    let et = g.edge_type(v, w);
+                    let vd = VData {
+                        ty: VType::Z,
+                        phase: Rational::zero(),
+                        qubit: g.qubit(w),
+                        row: g.row(w) + 1 };
+                    let x = g.add_vertex_with_data(vd);
+                    g.remove_edge(v, w);
+                    g.add_edge_with_type(w, x,
+                        if et == EType::H { EType::N } else { EType::H });
+                    g.add_edge_with_type(x, v, EType::H);
+                    neighbours.push(x);
+                } else {
+                    neighbours.push(w);
                 }
             }
+        }
+
+        if frontier.is_empty() { break; }
+
+        neighbours.sort_unstable();
+        neighbours.dedup();
+
+        if remove_gadget(&mut g, &mut frontier, &mut qubit_map, &neighbours) {
+            continue;
+        }
 
-            // TODO: CZ optimisation (maybe)
-            for &v in &frontier {
-                for w in Vec::from_iter(self.neighbors(v)) {
-                    if frontier.contains(&w) {
-                        self.remove_edge(v, w);
-                        c.push(Gate::new(CZ, vec![v,w]));
-                    }
+        // Row-reduce the biadjacency matrix of the frontier and its
+        // neighbours. Each row operation is a CNOT on the frontier.
+        let mut m = Mat2::build(frontier.len(), neighbours.len(),
+            |i,j| g.connected(frontier[i], neighbours[j]));
+        let mut ops = RowOps(Vec::new());
+        m.gauss_aux(true, 3, &mut ops);
+
+        let cnots: Vec<(usize,usize)> = ops.0.iter().rev()
+            .map(|&(r0, r1)| (qubit_map[&frontier[r1]], qubit_map[&frontier[r0]]))
+            .collect();
+        push_cnots(&mut c, &cnots, arch);
+
+        for (i, &v) in frontier.iter().enumerate() {
+            for (j, &w) in neighbours.iter().enumerate() {
+                let connected = g.connected(v, w);
+                if m[(i,j)] == 1 && !connected {
+                    g.add_edge_with_type(v, w, EType::H);
+                } else if m[(i,j)] == 0 && connected {
+                    g.remove_edge(v, w);
                 }
             }
+        }
 
-            // TODO: deal correctly with inputs
+        // Any frontier vertex with a single neighbour can now be
+        // extracted as a Hadamard, and its neighbour joins the frontier.
+        let mut extracted = false;
+        for i in 0..frontier.len() {
+            if m[i].iter().sum::<u32>() != 1 { continue; }
+            let v = frontier[i];
+            let w = neighbours[m[i].iter().position(|&x| x == 1).unwrap()];
+            let q = qubit_map[&v];
+            let b = g.neighbors(v)
+                .find(|n| g.outputs().contains(n))
+                .unwrap();
+            c.push(Gate::new(HAD, vec![q]));
+            g.remove_vertex(v);
+            g.add_edge(w, b);
+            frontier[i] = w;
+            qubit_map.insert(w, q);
+            extracted = true;
+        }
 
-            break; // TODO: finish!
+        if !extracted {
+            c.gates.reverse();
+            let qubits = frontier.iter().map(|v| qubit_map[v]).collect();
+            return Err(ExtractError::NoGflow { qubits, circuit: c, graph: g });
         }
+    }
+
+    c.gates.reverse();
+    match permutation_circuit(&g) {
+        Ok(perm) => Ok(route_permutation(perm, arch) + &c),
+        Err((output, qubit)) => Err(ExtractError::Disconnected { output, qubit, circuit: c, graph: g }),
+    }
+}
+
+impl<G: GraphLike + Clone> ToCircuit for G {
+    fn into_circuit(self) -> Result<Circuit, ExtractError<G>> {
+        extract(self, None)
+    }
+
+    fn into_circuit_routed(self, arch: &Architecture) -> Result<Circuit, ExtractError<G>> {
+        extract(self, Some(arch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scalar::*;
+    use crate::tensor::*;
+    use crate::simplify::*;
+    use crate::vec_graph::Graph;
+
+    fn simplified_graph(c: &Circuit) -> Graph {
+        let mut g: Graph = c.to_graph();
+        clifford_simp(&mut g);
+        g
+    }
+
+    fn assert_equal_up_to_scalar(t0: &Tensor<Scalar4>, t1: &Tensor<Scalar4>) {
+        assert_eq!(t0.shape(), t1.shape());
+        let (i, s0) = t0.indexed_iter()
+            .find(|(_,x)| !x.is_zero())
+            .expect("Tensor should be non-zero");
+        let s1 = t1[&i];
+        assert!(!s1.is_zero(), "Tensors are not proportional");
+        for (j, x) in t0.indexed_iter() {
+            assert_eq!(*x * s1, t1[&j] * *s0, "Tensors differ at {:?}", j);
+        }
+    }
+
+    #[test]
+    fn extract_wires() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[3];
+            swap q[0], q[1];
+            h q[2];
+            swap q[1], q[2];
+        "#).unwrap();
+
+        let g = simplified_graph(&c);
+        let c1 = g.to_circuit().expect("Extraction should succeed");
+        assert_equal_up_to_scalar(&c.to_tensor4(), &c1.to_tensor4());
+    }
+
+    #[test]
+    fn extract_clifford() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[4];
+            cx q[0], q[1];
+            h q[1];
+            s q[2];
+            cz q[1], q[2];
+            cx q[3], q[0];
+            h q[3];
+            x q[2];
+            cx q[2], q[3];
+            sdg q[0];
+            h q[0];
+            cz q[0], q[3];
+            cx q[1], q[0];
+            h q[2];
+        "#).unwrap();
+
+        let g = simplified_graph(&c);
+        let c1 = g.to_circuit().expect("Extraction should succeed");
+        assert_equal_up_to_scalar(&c.to_tensor4(), &c1.to_tensor4());
+    }
+
+    #[test]
+    fn extract_toffolis() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[4];
+            h q[3];
+            ccx q[0], q[1], q[3];
+            h q[1];
+            ccx q[2], q[3], q[1];
+            t q[0];
+            cx q[1], q[2];
+            ccz q[0], q[2], q[3];
+            h q[0];
+        "#).unwrap();
+
+        let g = simplified_graph(&c);
+        let c1 = g.to_circuit().expect("Extraction should succeed");
+        assert_equal_up_to_scalar(&c.to_tensor4(), &c1.to_tensor4());
+    }
+
+    fn assert_routed(c: &Circuit, arch: &Architecture) {
+        let c1 = simplified_graph(c).to_circuit_routed(arch).expect("Extraction should succeed");
+        for g in &c1.gates {
+            assert!(g.qs.len() == 1 || (g.qs.len() == 2 && arch.connected(g.qs[0], g.qs[1])),
+                    "Gate {} is not allowed by the architecture", g.to_qasm());
+        }
+        assert_equal_up_to_scalar(&c.to_tensor4(), &c1.to_tensor4());
+    }
+
+    #[test]
+    fn extract_routed() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[5];
+            cx q[0], q[4];
+            h q[1];
+            t q[4];
+            cz q[1], q[3];
+            ccx q[0], q[2], q[3];
+            swap q[0], q[4];
+            cx q[2], q[0];
+            s q[2];
+            h q[4];
+            cx q[4], q[1];
+            tdg q[1];
+            cz q[0], q[3];
+            h q[2];
+        "#).unwrap();
+
+        assert_routed(&c, &Architecture::line(5));
+        assert_routed(&c, &Architecture::from_edges(5, &[(2,0), (2,1), (2,3), (2,4)]));
+        assert_routed(&c, &Architecture::grid(1, 5));
+        assert_routed(&Circuit::from_qasm("qreg q[3]; swap q[0], q[2];").unwrap(), &Architecture::line(3));
+    }
+
+    #[test]
+    fn extract_routed_errors() {
+        let g = simplified_graph(&Circuit::from_qasm("qreg q[3]; cx q[0], q[2];").unwrap());
+        let e = g.to_circuit_routed(&Architecture::line(4)).unwrap_err();
+        assert_eq!(e.to_string(), "Cannot route a circuit on 3 qubits to an architecture with 4 qubits");
+        let e = g.to_circuit_routed(&Architecture::from_edges(3, &[(0,1)])).unwrap_err();
+        assert!(matches!(e, ExtractError::DisconnectedArchitecture { .. }));
+    }
+
+    #[test]
+    fn extract_shared_output() {
+        // a spider on two outputs gets split, but this map isn't unitary, so
+        // extraction gets stuck later on
+        let mut g = Graph::new();
+        let i0 = g.add_vertex(VType::B);
+        let i1 = g.add_vertex(VType::B);
+        let o0 = g.add_vertex(VType::B);
+        let o1 = g.add_vertex(VType::B);
+        let v = g.add_vertex(VType::Z);
+        let w = g.add_vertex(VType::Z);
+        g.add_edge(i0, v);
+        g.add_edge(i1, w);
+        g.add_edge_with_type(v, w, EType::H);
+        g.add_edge(v, o0);
+        g.add_edge_with_type(v, o1, EType::H);
+        g.set_inputs(vec![i0, i1]);
+        g.set_outputs(vec![o0, o1]);
+
+        let h = match g.to_circuit() {
+            Err(ExtractError::NoGflow { graph, .. }) => graph,
+            r => panic!("Expected NoGflow, got {:?}", r),
+        };
+        let n0: Vec<V> = h.neighbors(o0).collect();
+        let n1: Vec<V> = h.neighbors(o1).collect();
+        assert_eq!(n0, vec![v]);
+        assert_eq!(n1.len(), 1);
+        assert_ne!(n1[0], v);
+        assert_eq!(h.vertex_type(n1[0]), VType::Z);
+    }
+
+    #[test]
+    fn extract_no_gflow() {
+        // |0><+| has no gflow, so it can't be extracted
+        let mut g = Graph::new();
+        let i = g.add_vertex(VType::B);
+        let o = g.add_vertex(VType::B);
+        let v0 = g.add_vertex(VType::Z);
+        let v1 = g.add_vertex(VType::Z);
+        g.add_edge(i, v0);
+        g.add_edge(v1, o);
+        g.set_inputs(vec![i]);
+        g.set_outputs(vec![o]);
+
+        match g.to_circuit() {
+            Err(ExtractError::NoGflow { qubits, circuit, .. }) => {
+                assert_eq!(qubits, vec![0]);
+                assert_eq!(circuit.num_gates(), 0);
+            },
+            r => panic!("Expected NoGflow, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn extract_not_graph_like() {
+        let mut g = Graph::new();
+        let i = g.add_vertex(VType::B);
+        let o = g.add_vertex(VType::B);
+        let v = g.add_vertex(VType::X);
+        g.add_edge(i, v);
+        g.add_edge(v, o);
+        g.set_inputs(vec![i]);
+        g.set_outputs(vec![o]);
 
-        Ok(c)
+        let e = g.to_circuit().unwrap_err();
+        assert_eq!(e.to_string(), format!("Graph is not graph-like: vertex {} has type X", v));
+        let (c, g1) = e.into_parts();
+        assert_eq!(c.num_gates(), 0);
+        assert_eq!(g1.num_vertices(), 3);
     }
 }
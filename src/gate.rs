@@ -1,6 +1,7 @@
 use std::cmp::max;
-use num::{Rational,Zero};
+use num::{Rational,Zero,One};
 use crate::graph::*;
+use crate::scalar::Mod2;
 
 #[derive(PartialEq,Eq,Clone,Copy,Debug)]
 pub enum GType {
@@ -112,6 +113,29 @@ impl Gate {
         Gate { t, qs, phase }
     }
 
+    /// push gates applying a phase of `phase * pi` when all of the given qubits are 1
+    ///
+    /// This is a single Z, CZ or CCZ gate when the phase is pi and there are at most 3 qubits.
+    /// Otherwise, it uses the fact that x_1 ... x_k is 1/2^(k-1) times the sum of
+    /// (-1)^(|S|+1) (XOR_{i in S} x_i) over non-empty subsets S, giving 2^k - 1 parity phases.
+    pub fn push_controlled_phase(gs: &mut Vec<Gate>, qs: &[usize], phase: Rational) {
+        let k = qs.len();
+        let phase = phase.mod2();
+        if k == 0 || phase.is_zero() { return; }
+        if phase == Rational::one() && k <= 3 {
+            gs.push(Gate::new([Z, CZ, CCZ][k-1], qs.to_vec()));
+            return;
+        }
+
+        let unit = phase / (1isize << (k-1));
+        for mask in 1usize..(1 << k) {
+            let support: Vec<usize> = (0..k).filter(|i| mask & (1 << i) != 0).map(|i| qs[i]).collect();
+            let p = if support.len() % 2 == 1 { unit } else { -unit };
+            let t = if support.len() == 1 { ZPhase } else { ParityPhase };
+            gs.push(Gate::new_with_phase(t, support, p.mod2()));
+        }
+    }
+
     fn push_ccz_decomp(gs: &mut Vec<Gate>, qs: &Vec<usize>) {
         gs.push(Gate::new(CNOT, vec![qs[1], qs[2]]));
         gs.push(Gate::new(Tdg, vec![qs[2]]));
@@ -128,6 +152,28 @@ impl Gate {
         gs.push(Gate::new(CNOT, vec![qs[0], qs[1]]));
     }
 
+    /// number of T gates needed to realise this gate
+    ///
+    /// As in PyZX, a phase gate with any non-Clifford phase counts as one T gate, and CCZ
+    /// and TOFF count as 7.
+    pub fn tcount(&self) -> usize {
+        match self.t {
+            T | Tdg => 1,
+            ZPhase | XPhase | ParityPhase if self.phase.denom() > &2 => 1,
+            CCZ | TOFF => 7,
+            _ => 0,
+        }
+    }
+
+    /// whether the gate is in the Clifford group
+    pub fn is_clifford(&self) -> bool {
+        match self.t {
+            NOT | Z | S | Sdg | HAD | CNOT | CZ | XCX | SWAP => true,
+            ZPhase | XPhase | ParityPhase => self.phase.denom() <= &2,
+            _ => false,
+        }
+    }
+
     /// number of 1- and 2-qubit Clifford + phase gates needed to realise this gate
     pub fn num_basic_gates(&self) -> usize {
         match self.t {
@@ -187,11 +233,56 @@ impl Gate {
         }
     }
 
+    /// add phases on parities of the given qubits using phase gadgets
+    ///
+    /// Each qubit gets a new Z spider, then for each pair `(support, phase)`, the phase is applied
+    /// to the parity of the qubits at the positions in `support`. Parities of a single qubit are
+    /// added to the phase of its spider, and all the others become phase gadgets.
+    fn add_parity_phases<G: GraphLike>(graph: &mut G, qs: &mut Vec<Option<usize>>, qubits: &[usize],
+                         phases: &[(Vec<usize>, Rational)])
+    {
+        let vs: Vec<Option<usize>> = qubits.iter()
+            .map(|&q| Gate::add_spider(graph, qs, q, VType::Z, EType::N, Rational::zero()))
+            .collect();
+        if vs.iter().any(|v| v.is_none()) { return; }
+        let vs: Vec<usize> = vs.into_iter().flatten().collect();
+
+        let row = vs.iter().map(|&v| graph.row(v)).max().unwrap_or(0);
+        for &v in &vs { graph.set_row(v, row); }
+
+        for (support, phase) in phases {
+            let support: Vec<usize> = support.iter().map(|&i| vs[i]).collect();
+            if support.len() == 1 {
+                graph.add_to_phase(support[0], *phase);
+            } else if support.len() > 1 {
+                let root = graph.add_vertex_with_data(VData { ty: VType::Z, phase: Rational::zero(), qubit: -1, row });
+                let leaf = graph.add_vertex_with_data(VData { ty: VType::Z, phase: *phase, qubit: -2, row });
+                graph.add_edge_with_type(root, leaf, EType::H);
+                for &v in &support { graph.add_edge_with_type(root, v, EType::H); }
+                graph.scalar_mut().mul_sqrt2_pow(support.len() as i32 - 1);
+            }
+        }
+    }
+
     /// add the gate to the given graph using spiders
     ///
     /// This method takes mutable parameters for the graph being built, and a vec `qs` mapping qubit
     /// number to the most recent vertex in that spot.
     pub fn add_to_graph(&self, graph: &mut impl GraphLike, qs: &mut Vec<Option<usize>>) {
+        self.add_to_graph_with_options(graph, qs, false);
+    }
+
+    /// add the gate to the given graph, optionally using phase gadgets
+    ///
+    /// If `phase_gadgets` is true, CCZ and TOFF become a network of 7 phase gadgets and spider
+    /// phases, and ParityPhase becomes a single phase gadget, rather than being decomposed into
+    /// basic gates. Otherwise, this is the same as [Gate::add_to_graph].
+    pub fn add_to_graph_with_options(&self, graph: &mut impl GraphLike, qs: &mut Vec<Option<usize>>,
+                                     phase_gadgets: bool)
+    {
+        // gates involving a post-selected qubit are quietly ignored
+        if self.qs.iter().any(|&q| qs[q].is_none()) { return; }
+
         match self.t {
             ZPhase => { Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, self.phase); },
             Z      => { Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, Rational::new(1,1)); },
@@ -199,8 +290,8 @@ impl Gate {
             Sdg    => { Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, Rational::new(-1,2)); },
             T      => { Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, Rational::new(1,4)); },
             Tdg    => { Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, Rational::new(-1,4)); },
-            XPhase => { Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, self.phase); },
-            NOT    => { Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, Rational::new(1,1)); },
+            XPhase => { Gate::add_spider(graph, qs, self.qs[0], VType::X, EType::N, self.phase); },
+            NOT    => { Gate::add_spider(graph, qs, self.qs[0], VType::X, EType::N, Rational::new(1,1)); },
             HAD    => { Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::H, Rational::zero()); },
             CNOT => {
                 if let (Some(v1), Some(v2)) =
@@ -273,6 +364,19 @@ impl Gate {
                 // all later gates involving this qubit are quietly ignored
                 qs[self.qs[0]] = None;
             },
+            CCZ | TOFF if phase_gadgets => {
+                let t = self.qs[2];
+                if self.t == TOFF { Gate::new(HAD, vec![t]).add_to_graph(graph, qs); }
+                let p = Rational::new(1,4);
+                Gate::add_parity_phases(graph, qs, &self.qs,
+                    &[(vec![0], p), (vec![1], p), (vec![2], p),
+                      (vec![0,1], -p), (vec![0,2], -p), (vec![1,2], -p), (vec![0,1,2], p)]);
+                if self.t == TOFF { Gate::new(HAD, vec![t]).add_to_graph(graph, qs); }
+            },
+            ParityPhase if phase_gadgets => {
+                let support = (0..self.qs.len()).collect();
+                Gate::add_parity_phases(graph, qs, &self.qs, &[(support, self.phase)]);
+            },
             CCZ | TOFF | ParityPhase => {
                 for g in self.to_basic_gates() {
                     g.add_to_graph(graph, qs);
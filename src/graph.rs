@@ -16,6 +16,7 @@
 
 use crate::scalar::*;
 use num::rational::Rational;
+use rustc_hash::FxHashMap;
 use std::iter::FromIterator;
 
 pub type V = usize;
@@ -199,6 +200,83 @@ impl<'a> Iterator for IncidentEdgeIter<'a> {
 
 impl<'a> ExactSizeIterator for IncidentEdgeIter<'a> {}
 
+/// A change to a graph, recorded so that it can be undone
+///
+/// Each variant holds what is needed to reverse the change, e.g. the data
+/// of a removed vertex, or the old type of an edge.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Undo {
+    AddVertex(V),
+    RemoveVertex(V, VData),
+    AddEdge(V, V),
+    RemoveEdge(V, V, EType),
+    SetEdgeType(V, V, EType),
+    SetVData(V, VData),
+}
+
+/// A point to return to with [GraphLike::rollback]
+///
+/// As well as the position in the graph's [Journal], this holds the scalar
+/// and boundaries at the time it was taken, since these can be changed
+/// directly through [GraphLike::scalar_mut] and friends.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Checkpoint {
+    depth: usize,
+    len: usize,
+    pub scalar: ScalarN,
+    pub inputs: Vec<V>,
+    pub outputs: Vec<V>,
+}
+
+/// The changes made to a graph while some [Checkpoint] is open
+///
+/// Graphs keep a journal so they can be rolled back without cloning. It only
+/// records anything between a call to [GraphLike::checkpoint] and the matching
+/// [GraphLike::commit] or [GraphLike::rollback], so it costs nothing otherwise.
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct Journal {
+    changes: Vec<Undo>,
+    depth: usize,
+}
+
+impl Journal {
+    pub fn is_recording(&self) -> bool { self.depth > 0 }
+
+    /// Record a change, if a checkpoint is open
+    pub fn record(&mut self, u: Undo) {
+        if self.depth > 0 { self.changes.push(u); }
+    }
+
+    pub fn checkpoint(&mut self, scalar: ScalarN, inputs: Vec<V>, outputs: Vec<V>) -> Checkpoint {
+        self.depth += 1;
+        Checkpoint { depth: self.depth, len: self.changes.len(), scalar, inputs, outputs }
+    }
+
+    fn close(&mut self, cp: &Checkpoint) {
+        if cp.depth == 0 || cp.depth > self.depth || cp.len > self.changes.len() {
+            panic!("Checkpoint is no longer open");
+        }
+        self.depth = cp.depth - 1;
+    }
+
+    /// Close a checkpoint, and any opened after it, keeping the changes
+    ///
+    /// If an outer checkpoint is still open, it can still undo these changes.
+    pub fn commit(&mut self, cp: &Checkpoint) {
+        self.close(cp);
+        if self.depth == 0 { self.changes.clear(); }
+    }
+
+    /// Close a checkpoint, and any opened after it, returning the changes
+    /// made since, most recent first
+    pub fn rollback(&mut self, cp: &Checkpoint) -> Vec<Undo> {
+        self.close(cp);
+        let mut changes = self.changes.split_off(cp.len);
+        changes.reverse();
+        changes
+    }
+}
+
 pub trait GraphLike {
     fn new() -> Self;
     fn num_vertices(&self) -> usize;
@@ -214,6 +292,7 @@ pub trait GraphLike {
     fn add_vertex(&mut self, ty: VType) -> V;
     fn add_vertex_with_data(&mut self, d: VData) -> V;
     fn remove_vertex(&mut self, v: V);
+    fn contains_vertex(&self, v: V) -> bool;
     fn add_edge_with_type(&mut self, s: V, t: V, ety: EType);
     fn remove_edge(&mut self, s: V, t: V);
     fn set_phase(&mut self, v: V, phase: Rational);
@@ -224,16 +303,28 @@ pub trait GraphLike {
     fn set_edge_type(&mut self, s: V, t: V, ety: EType);
     fn edge_type_opt(&self, s: V, t: V) -> Option<EType>;
     fn set_coord(&mut self, v: V, coord: (i32,i32));
-    fn coord(&mut self, v: V) -> (i32,i32);
+    fn coord(&self, v: V) -> (i32,i32);
     fn set_qubit(&mut self, v: V, qubit: i32);
-    fn qubit(&mut self, v: V) -> i32;
+    fn qubit(&self, v: V) -> i32;
     fn set_row(&mut self, v: V, row: i32);
-    fn row(&mut self, v: V) -> i32;
+    fn row(&self, v: V) -> i32;
     fn neighbors(&self, v: V) -> NeighborIter;
     fn incident_edges(&self, v: V) -> IncidentEdgeIter;
     fn degree(&self, v: V) -> usize;
     fn scalar(&self) -> &ScalarN;
     fn scalar_mut(&mut self) -> &mut ScalarN;
+    /// Start recording changes to the graph, so they can be undone cheaply
+    ///
+    /// Checkpoints can be nested, and each should be closed by [GraphLike::rollback]
+    /// or [GraphLike::commit]. Closing a checkpoint also closes any taken after it.
+    fn checkpoint(&mut self) -> Checkpoint;
+    /// Undo all changes since the checkpoint was taken
+    ///
+    /// The vertices, edges, scalar and boundaries are restored, and vertex
+    /// names are as before, but neighbours may be listed in a different order.
+    fn rollback(&mut self, cp: Checkpoint);
+    /// Keep the changes made since the checkpoint was taken
+    fn commit(&mut self, cp: Checkpoint);
     fn find_edge<F>(&self, f: F) -> Option<(V,V,EType)>
         where F : Fn(V,V,EType) -> bool;
     fn find_vertex<F>(&self, f: F) -> Option<V>
@@ -362,13 +453,131 @@ pub trait GraphLike {
 
         dot
     }
+
+    /// Plug the outputs of this graph into the inputs of `other`
+    ///
+    /// The result is the sequential composition: this graph followed by
+    /// `other`, as for appending circuits. The boundaries that meet are
+    /// removed, and the spiders on either side get connected directly.
+    fn compose<G: GraphLike>(&mut self, other: &G) where Self: Sized {
+        if self.outputs().len() != other.inputs().len() {
+            panic!("Cannot compose a graph with {} outputs with a graph with {} inputs",
+                   self.outputs().len(), other.inputs().len());
+        }
+
+        let row0 = self.vertices().map(|v| self.row(v)).max().unwrap_or(0);
+        let row1 = other.vertices().map(|v| other.row(v)).min().unwrap_or(0);
+        let vmap = copy_graph(self, other, 0, row0 - row1);
+
+        let outputs = self.outputs().clone();
+        for (&o, &i) in outputs.iter().zip(other.inputs()) {
+            let i = vmap[&i];
+            let (n0, et0) = boundary_edge(self, o);
+            let (n1, et1) = boundary_edge(self, i);
+            self.remove_vertex(o);
+            self.remove_vertex(i);
+
+            if n0 == i {
+                // a loop, which contributes a trace of the identity or of H
+                if et0 == EType::N { self.scalar_mut().mul_sqrt2_pow(2); }
+                else { *self.scalar_mut() = ScalarN::zero(); }
+            } else {
+                let et = if et0 == et1 { EType::N } else { EType::H };
+                self.add_edge_smart(n0, n1, et);
+            }
+        }
+
+        self.set_outputs(other.outputs().iter().map(|v| vmap[v]).collect());
+    }
+
+    /// Put `other` alongside this graph
+    ///
+    /// The inputs and outputs of `other` come after those of this graph, and
+    /// its qubit indices are shifted to start after the last qubit of this one.
+    fn tensor<G: GraphLike>(&mut self, other: &G) where Self: Sized {
+        let q0 = self.vertices().map(|v| self.qubit(v) + 1).max().unwrap_or(0);
+        let q1 = other.vertices().map(|v| other.qubit(v)).min().unwrap_or(0);
+        let vmap = copy_graph(self, other, q0 - q1, 0);
+        self.inputs_mut().extend(other.inputs().iter().map(|v| vmap[v]));
+        self.outputs_mut().extend(other.outputs().iter().map(|v| vmap[v]));
+    }
+
+    /// Replace the graph by its adjoint
+    ///
+    /// This swaps inputs and outputs, negates all phases, conjugates the scalar
+    /// and mirrors the rows, so the graph still reads left-to-right.
+    fn adjoint(&mut self) {
+        let max_row = self.vertices().map(|v| self.row(v)).max().unwrap_or(0);
+        for v in Vec::from_iter(self.vertices()) {
+            let p = self.phase(v);
+            self.set_phase(v, -p);
+            let r = self.row(v);
+            self.set_row(v, max_row - r);
+        }
+
+        let inputs = self.inputs().clone();
+        let outputs = self.outputs().clone();
+        self.set_inputs(outputs);
+        self.set_outputs(inputs);
+        *self.scalar_mut() = self.scalar().conj();
+    }
+
+    /// Evaluate a diagram without boundaries to a scalar. See [crate::simulate::eval_scalar].
+    fn eval_scalar(&self) -> ScalarN where Self: Sized + Clone {
+        crate::simulate::eval_scalar(self)
+    }
+
+    /// Write the graph in PyZX's JSON format. See [crate::json].
+    fn to_json(&self) -> String where Self: Sized {
+        crate::json::graph_to_json(self)
+    }
+
+    /// Read a graph in PyZX's JSON format. See [crate::json].
+    fn from_json(source: &str) -> Result<Self, String> where Self: Sized {
+        crate::json::json_to_graph(source)
+    }
+}
+
+/// Copy the vertices and edges of `other` into `g`, and multiply in its scalar
+///
+/// Qubits and rows are shifted by the given offsets. Returns a map from the
+/// vertices of `other` to their copies. Inputs and outputs are left unchanged.
+fn copy_graph<G: GraphLike, H: GraphLike>(g: &mut G, other: &H,
+                                          qubit_offset: i32, row_offset: i32) -> FxHashMap<V,V>
+{
+    let mut vmap = FxHashMap::default();
+    for v in other.vertices() {
+        let w = g.add_vertex_with_data(VData {
+            ty: other.vertex_type(v),
+            phase: other.phase(v),
+            qubit: other.qubit(v) + qubit_offset,
+            row: other.row(v) + row_offset,
+        });
+        vmap.insert(v, w);
+    }
+
+    for (s, t, et) in other.edges() {
+        g.add_edge_with_type(vmap[&s], vmap[&t], et);
+    }
+
+    *g.scalar_mut() *= other.scalar();
+    vmap
+}
+
+/// Return the unique neighbour of a boundary vertex and the type of edge to it
+fn boundary_edge<G: GraphLike>(g: &G, b: V) -> (V, EType) {
+    if g.degree(b) != 1 {
+        panic!("Boundary vertex {} should have exactly one neighbour", b);
+    }
+    g.incident_edges(b).next().unwrap()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::vec_graph::Graph;
-    use crate::tensor::ToTensor;
+    use crate::circuit::Circuit;
+    use crate::tensor::*;
     #[test]
     fn smart_edges() {
        let mut g = Graph::new();
@@ -403,4 +612,185 @@ mod tests {
        println!("\n\nth =\n{}", th);
        assert_eq!(tg, th);
     }
+
+    fn circuits() -> (Circuit, Circuit) {
+        let c1 = Circuit::from_qasm(r#"
+            qreg q[3];
+            h q[0];
+            t q[0];
+            cx q[0], q[2];
+            s q[1];
+            ccz q[0], q[1], q[2];
+        "#).unwrap();
+        let c2 = Circuit::from_qasm(r#"
+            qreg q[3];
+            cx q[2], q[1];
+            tdg q[1];
+            h q[2];
+            swap q[0], q[2];
+            rz(0.25*pi) q[0];
+        "#).unwrap();
+        (c1, c2)
+    }
+
+    #[test]
+    fn compose() {
+        let (c1, c2) = circuits();
+        let c = &c1 + c2.clone();
+        let g1: Graph = c1.to_graph();
+        let g2: Graph = c2.to_graph();
+        let g = &g1 + g2.clone();
+        assert_eq!(g.inputs().len(), 3);
+        assert_eq!(g.outputs().len(), 3);
+        assert_eq!(g.to_tensor4(), c.to_tensor4());
+
+        // composing across graph types
+        let mut h: crate::hash_graph::Graph = c1.to_graph();
+        h.compose(&g2);
+        assert_eq!(h.to_tensor4(), c.to_tensor4());
+
+        // a miter of a circuit with itself is the identity
+        let mut g2a = g2.clone();
+        g2a.adjoint();
+        let m = g2 + g2a;
+        assert_eq!(m.to_tensor4(), Circuit::new(3).to_tensor4());
+    }
+
+    #[test]
+    fn compose_loops() {
+        for &(et, s) in &[(EType::N, 2), (EType::H, 0)] {
+            let mut g = Graph::new();
+            let o0 = g.add_vertex(VType::B);
+            let o1 = g.add_vertex(VType::B);
+            g.add_edge_with_type(o0, o1, et);
+            g.set_outputs(vec![o0, o1]);
+
+            let mut h = Graph::new();
+            let i0 = h.add_vertex(VType::B);
+            let i1 = h.add_vertex(VType::B);
+            h.add_edge(i0, i1);
+            h.set_inputs(vec![i0, i1]);
+
+            let gh = g + h;
+            assert_eq!(gh.num_vertices(), 0);
+            assert_eq!(*gh.scalar(), ScalarN::from_int_coeffs(&[s]));
+        }
+    }
+
+    #[test]
+    fn tensor() {
+        let (c1, c2) = circuits();
+        let mut c = Circuit::new(6);
+        for g in c1.gates.iter().cloned() { c.push(g); }
+        for mut g in c2.gates.iter().cloned() {
+            for q in g.qs.iter_mut() { *q += 3; }
+            c.push(g);
+        }
+
+        let mut g: crate::hash_graph::Graph = c1.to_graph();
+        let g2: Graph = c2.to_graph();
+        g.tensor(&g2);
+        assert_eq!(g.inputs().len(), 6);
+        assert!(g.vertices().all(|v| g.qubit(v) < 6));
+        assert!(compare_tensors(&g.to_tensorf(), &c.to_tensorf(), 1e-10));
+    }
+
+    #[test]
+    fn adjoint() {
+        let (c1, c2) = circuits();
+        for c in &[c1, c2] {
+            let mut g: Graph = c.to_graph();
+            *g.scalar_mut() *= ScalarN::from_phase(Rational::new(1,4));
+            g.adjoint();
+            let mut h: Graph = c.to_adjoint().to_graph();
+            *h.scalar_mut() *= ScalarN::from_phase(Rational::new(-1,4));
+            assert_eq!(g.to_tensor4(), h.to_tensor4());
+        }
+    }
+
+    type Contents = (Vec<(V,VType,Rational,i32,i32)>, Vec<(V,V,EType)>);
+
+    /// The vertices with their data, and the edges, in a canonical order
+    fn contents<G: GraphLike>(g: &G) -> Contents {
+        let mut vs: Vec<_> = g.vertices()
+            .map(|v| (v, g.vertex_type(v), g.phase(v), g.qubit(v), g.row(v)))
+            .collect();
+        vs.sort();
+        let mut es: Vec<_> = g.edges().map(|(s,t,et)| (s.min(t), s.max(t), et)).collect();
+        es.sort();
+        (vs, es)
+    }
+
+    fn assert_same<G: GraphLike>(g: &G, h: &G) {
+        assert_eq!(contents(g), contents(h));
+        assert_eq!(g.num_vertices(), h.num_vertices());
+        assert_eq!(g.num_edges(), h.num_edges());
+        assert_eq!(g.scalar(), h.scalar());
+        assert_eq!(g.inputs(), h.inputs());
+        assert_eq!(g.outputs(), h.outputs());
+    }
+
+    fn checkpoints<G: GraphLike + Clone + PartialEq + std::fmt::Debug>() {
+        let (c1, c2) = circuits();
+        let mut g: G = (&c1 + c2).to_graph();
+        let g0 = g.clone();
+
+        // an open checkpoint doesn't affect equality
+        let cp = g.checkpoint();
+        assert_eq!(g, g0);
+        crate::simplify::full_reduce(&mut g);
+        let g1 = g.clone();
+        assert!(g1.num_vertices() < g0.num_vertices());
+        let mut h = g0.clone();
+        crate::simplify::full_reduce(&mut h);
+        assert_eq!(g, h);
+
+        // commit an inner checkpoint, and roll back another
+        let cp1 = g.checkpoint();
+        let v = g.add_vertex(VType::X);
+        g.add_edge_with_type(v, g1.inputs()[0], EType::H);
+        g.commit(cp1);
+        let g2 = g.clone();
+        let cp2 = g.checkpoint();
+        g.adjoint();
+        g.remove_vertex(v);
+        let w = g.vertex_vec()[0];
+        g.set_phase(w, Rational::new(1,3));
+        g.rollback(cp2);
+        assert_same(&g, &g2);
+
+        g.rollback(cp);
+        assert_same(&g, &g0);
+        assert!(compare_tensors(&g.to_tensorf(), &g0.to_tensorf(), 1e-10));
+
+        // vertex names are reused in the same way as before
+        crate::simplify::full_reduce(&mut g);
+        assert_same(&g, &g1);
+
+        // nothing is recorded without a checkpoint
+        let cp = g.checkpoint();
+        g.commit(cp);
+        g.remove_vertex(g.vertex_vec()[0]);
+        let g3 = g.clone();
+        let cp = g.checkpoint();
+        g.rollback(cp);
+        assert_same(&g, &g3);
+    }
+
+    #[test]
+    fn checkpoint_rollback() {
+        checkpoints::<Graph>();
+        checkpoints::<crate::hash_graph::Graph>();
+    }
+
+    #[test]
+    #[should_panic]
+    fn closed_checkpoint() {
+        let mut g = Graph::new();
+        let cp0 = g.checkpoint();
+        let cp1 = g.checkpoint();
+        g.add_vertex(VType::Z);
+        g.rollback(cp0);
+        g.commit(cp1);
+    }
 }
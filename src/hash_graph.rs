@@ -22,7 +22,7 @@ use std::iter::FromIterator;
 
 pub type VTab<T> = FxHashMap<V,T>;
 
-#[derive(Debug,Clone,PartialEq)]
+#[derive(Debug,Clone)]
 pub struct Graph {
     vdata: VTab<VData>,
     edata: VTab<VTab<EType>>,
@@ -32,6 +32,7 @@ pub struct Graph {
     nume: usize,
     freshv: V,
     pub scalar: ScalarN,
+    journal: Journal,
 }
 
 pub struct EdgeIter<'a> {
@@ -69,6 +70,47 @@ impl Graph {
             .expect("Vertex not found")
             .remove(&t);
     }
+
+    /// Returns the data of a vertex for updating, and records the old data
+    /// in the journal
+    fn vdata_mut(&mut self, v: V) -> &mut VData {
+        let d = self.vdata.get_mut(&v).expect("Vertex not found");
+        self.journal.record(Undo::SetVData(v, *d));
+        d
+    }
+
+    /// Reverse a change recorded in the journal
+    fn undo(&mut self, u: Undo) {
+        match u {
+            Undo::AddVertex(v) => {
+                self.remove_vertex(v);
+                if v + 1 == self.freshv { self.freshv -= 1; }
+            }
+            Undo::RemoveVertex(v, d) => {
+                self.numv += 1;
+                self.vdata.insert(v, d);
+                self.edata.insert(v, FxHashMap::default());
+            }
+            Undo::AddEdge(s, t) => self.remove_edge(s, t),
+            Undo::RemoveEdge(s, t, ety) => self.add_edge_with_type(s, t, ety),
+            Undo::SetEdgeType(s, t, ety) => self.set_edge_type(s, t, ety),
+            Undo::SetVData(v, d) => { self.vdata.insert(v, d); }
+        }
+    }
+}
+
+// graphs are compared by their contents, so the journal is left out
+impl PartialEq for Graph {
+    fn eq(&self, other: &Graph) -> bool {
+        self.vdata == other.vdata &&
+        self.edata == other.edata &&
+        self.inputs == other.inputs &&
+        self.outputs == other.outputs &&
+        self.numv == other.numv &&
+        self.nume == other.nume &&
+        self.freshv == other.freshv &&
+        self.scalar == other.scalar
+    }
 }
 
 impl GraphLike for Graph {
@@ -82,6 +124,7 @@ impl GraphLike for Graph {
             nume: 0,
             freshv: 0,
             scalar: Scalar::one(),
+            journal: Journal::default(),
         }
     }
 
@@ -118,10 +161,19 @@ impl GraphLike for Graph {
         self.numv += 1;
         self.vdata.insert(v, d);
         self.edata.insert(v, FxHashMap::default());
+        self.journal.record(Undo::AddVertex(v));
         v
     }
 
     fn remove_vertex(&mut self, v: V) {
+        if self.journal.is_recording() {
+            for (v1, et) in self.incident_edge_vec(v) {
+                self.journal.record(Undo::RemoveEdge(v, v1, et));
+            }
+            let d = *self.vdata.get(&v).expect("Vertex not found");
+            self.journal.record(Undo::RemoveVertex(v, d));
+        }
+
         self.numv -= 1;
 
         for v1 in Vec::from_iter(self.neighbors(v)) {
@@ -133,8 +185,13 @@ impl GraphLike for Graph {
         self.edata.remove(&v);
     }
 
+    fn contains_vertex(&self, v: V) -> bool {
+        self.vdata.contains_key(&v)
+    }
+
     fn add_edge_with_type(&mut self, s: V, t: V, ety: EType) {
         self.nume += 1;
+        self.journal.record(Undo::AddEdge(s, t));
 
         self.edata.get_mut(&s)
             .expect("Source vertex not found")
@@ -146,15 +203,18 @@ impl GraphLike for Graph {
 
 
     fn remove_edge(&mut self, s: V, t: V) {
+        if self.journal.is_recording() {
+            let ety = self.edge_type_opt(s, t).expect("Edge not found");
+            self.journal.record(Undo::RemoveEdge(s, t, ety));
+        }
+
         self.nume -= 1;
         self.remove_half_edge(s,t);
         self.remove_half_edge(t,s);
     }
 
     fn set_phase(&mut self, v: V, phase: Rational) {
-        self.vdata.get_mut(&v)
-            .expect("Vertex not found")
-            .phase = phase.mod2();
+        self.vdata_mut(v).phase = phase.mod2();
     }
 
     fn phase(&self, v: V) -> Rational {
@@ -164,17 +224,12 @@ impl GraphLike for Graph {
     }
 
     fn add_to_phase(&mut self, v: V, phase: Rational) {
-        if let Some(d) = self.vdata.get_mut(&v) {
-            d.phase = (d.phase + phase).mod2();
-        } else {
-            panic!("Vertex not found");
-        }
+        let d = self.vdata_mut(v);
+        d.phase = (d.phase + phase).mod2();
     }
 
     fn set_vertex_type(&mut self, v: V, ty: VType) {
-        self.vdata.get_mut(&v)
-            .expect("Vertex not found")
-            .ty = ty;
+        self.vdata_mut(v).ty = ty;
     }
 
     fn vertex_type(&self, v: V) -> VType {
@@ -184,6 +239,11 @@ impl GraphLike for Graph {
     }
 
     fn set_edge_type(&mut self, s: V, t: V, ety: EType) {
+        if self.journal.is_recording() {
+            let old = self.edge_type_opt(s, t).expect("Edge not found");
+            self.journal.record(Undo::SetEdgeType(s, t, old));
+        }
+
         *self.edata.get_mut(&s)
             .expect("Source vertex not found")
             .get_mut(&t)
@@ -202,32 +262,30 @@ impl GraphLike for Graph {
     }
 
     fn set_coord(&mut self, v: V, coord: (i32,i32)) {
-        let d = self.vdata.get_mut(&v).expect("Vertex not found");
+        let d = self.vdata_mut(v);
         d.qubit = coord.0;
         d.row = coord.1;
     }
 
-    fn coord(&mut self, v: V) -> (i32,i32) {
+    fn coord(&self, v: V) -> (i32,i32) {
         let d = self.vdata.get(&v).expect("Vertex not found");
         (d.qubit, d.row)
     }
 
     fn set_qubit(&mut self, v: V, qubit: i32) {
-        self.vdata.get_mut(&v)
-            .expect("Vertex not found").qubit = qubit;
+        self.vdata_mut(v).qubit = qubit;
     }
 
-    fn qubit(&mut self, v: V) -> i32 {
+    fn qubit(&self, v: V) -> i32 {
         self.vdata.get(&v)
             .expect("Vertex not found").qubit
     }
 
     fn set_row(&mut self, v: V, row: i32) {
-        self.vdata.get_mut(&v)
-            .expect("Vertex not found").row = row;
+        self.vdata_mut(v).row = row;
     }
 
-    fn row(&mut self, v: V) -> i32 {
+    fn row(&self, v: V) -> i32 {
         self.vdata.get(&v)
             .expect("Vertex not found").row
     }
@@ -255,6 +313,25 @@ impl GraphLike for Graph {
     fn scalar(&self) -> &ScalarN { &self.scalar }
     fn scalar_mut(&mut self) -> &mut ScalarN { &mut self.scalar }
 
+    fn checkpoint(&mut self) -> Checkpoint {
+        self.journal.checkpoint(self.scalar.clone(), self.inputs.clone(), self.outputs.clone())
+    }
+
+    fn rollback(&mut self, cp: Checkpoint) {
+        let changes = self.journal.rollback(&cp);
+        // undoing shouldn't be recorded by any outer checkpoint
+        let journal = std::mem::take(&mut self.journal);
+        for u in changes { self.undo(u); }
+        self.journal = journal;
+        self.scalar = cp.scalar;
+        self.inputs = cp.inputs;
+        self.outputs = cp.outputs;
+    }
+
+    fn commit(&mut self, cp: Checkpoint) {
+        self.journal.commit(&cp);
+    }
+
     fn find_edge<F>(&self, f: F) -> Option<(V,V,EType)>
         where F : Fn(V,V,EType) -> bool
     {
@@ -278,6 +355,22 @@ impl GraphLike for Graph {
     }
 }
 
+impl std::ops::Add<&Graph> for Graph {
+    type Output = Graph;
+    fn add(mut self, rhs: &Graph) -> Self::Output {
+        self.compose(rhs);
+        self
+    }
+}
+
+impl std::ops::Add<Graph> for Graph {
+    type Output = Graph;
+    fn add(self, rhs: Graph) -> Self::Output { self.add(&rhs) } }
+
+impl std::ops::Add<Graph> for &Graph {
+    type Output = Graph;
+    fn add(self, rhs: Graph) -> Self::Output { self.clone().add(&rhs) } }
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,736 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reading and writing graphs in PyZX's JSON format
+//!
+//! This is the format of `.qgraph` files, and of `Graph.to_json` and
+//! `Graph.from_json` in PyZX, so diagrams can be passed back and forth between the
+//! two. As in PyZX, a vertex at qubit `q` and row `r` has coordinates `[r, -q]`,
+//! Hadamard edges are written as a pair of plain edges to an extra `hadamard` node
+//! with `is_edge` set, and the scalar is stored as a string holding a JSON object.
+//!
+//! PyZX stores a scalar as a power of sqrt(2) times a phase, which is exact for
+//! most scalars coming from simplification. Scalars not of this form are written
+//! as a floating-point `floatfactor`.
+
+use num::{Rational,Zero,One,Integer};
+use num::complex::Complex;
+use rustc_hash::FxHashMap;
+use std::f64::consts::PI;
+use crate::graph::*;
+use crate::scalar::*;
+
+/// A JSON value. Objects keep their keys in order, as PyZX relies on the order of
+/// boundary vertices to find the inputs and outputs.
+#[derive(Debug,Clone,PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(String,Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Obj(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn entries(&self) -> &[(String,Json)] {
+        match self {
+            Json::Obj(entries) => entries,
+            _ => &[],
+        }
+    }
+
+    /// Write the value, with each entry of the outer two levels of objects on its own
+    /// line, so each vertex and edge gets a line
+    fn write(&self, out: &mut String, indent: usize) {
+        match self {
+            Json::Null => *out += "null",
+            Json::Bool(b) => *out += if *b { "true" } else { "false" },
+            Json::Num(f) => {
+                if f.fract() == 0.0 && f.abs() < 1e15 { *out += &format!("{}", *f as i64); }
+                else { *out += &format!("{}", f); }
+            },
+            Json::Str(s) => write_str(out, s),
+            Json::Arr(vs) => {
+                *out += "[";
+                for (i, v) in vs.iter().enumerate() {
+                    if i != 0 { *out += ", "; }
+                    v.write(out, indent);
+                }
+                *out += "]";
+            },
+            Json::Obj(entries) if entries.is_empty() || indent >= 4 => *out += &self.to_compact(),
+            Json::Obj(entries) => {
+                *out += "{\n";
+                for (i, (k, v)) in entries.iter().enumerate() {
+                    *out += &" ".repeat(indent + 2);
+                    write_str(out, k);
+                    *out += ": ";
+                    v.write(out, indent + 2);
+                    if i + 1 != entries.len() { *out += ","; }
+                    *out += "\n";
+                }
+                *out += &" ".repeat(indent);
+                *out += "}";
+            },
+        }
+    }
+
+    /// Write the value on a single line
+    fn to_compact(&self) -> String {
+        match self {
+            Json::Obj(entries) => {
+                let es: Vec<String> = entries.iter().map(|(k, v)| {
+                    let mut s = String::new();
+                    write_str(&mut s, k);
+                    s + ": " + &v.to_compact()
+                }).collect();
+                format!("{{{}}}", es.join(", "))
+            },
+            _ => {
+                let mut s = String::new();
+                self.write(&mut s, 4);
+                s
+            },
+        }
+    }
+}
+
+fn write_str(out: &mut String, s: &str) {
+    *out += "\"";
+    for c in s.chars() {
+        match c {
+            '"' => *out += "\\\"",
+            '\\' => *out += "\\\\",
+            '\n' => *out += "\\n",
+            '\t' => *out += "\\t",
+            '\r' => *out += "\\r",
+            c if (c as u32) < 0x20 => *out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    *out += "\"";
+}
+
+/// A recursive-descent JSON parser
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Reader {
+    fn err<T>(&self, msg: &str) -> Result<T, String> {
+        let line = self.chars[..self.pos].iter().filter(|&&c| c == '\n').count() + 1;
+        let col = self.chars[..self.pos].iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        Err(format!("Invalid JSON at line {}, column {}: {}", line, col, msg))
+    }
+
+    fn skip_ws(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() { self.pos += 1; }
+    }
+
+    fn peek(&self) -> Option<char> { self.chars.get(self.pos).copied() }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_ws();
+        if self.peek() == Some(c) { self.pos += 1; Ok(()) }
+        else { self.err(&format!("expected '{}'", c)) }
+    }
+
+    fn keyword(&mut self, kw: &str, v: Json) -> Result<Json, String> {
+        let end = self.pos + kw.len();
+        if end <= self.chars.len() && self.chars[self.pos..end].iter().copied().eq(kw.chars()) {
+            self.pos = end;
+            Ok(v)
+        } else {
+            self.err("unexpected character")
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_ws();
+        match self.peek() {
+            None => self.err("unexpected end of input"),
+            Some('{') => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                self.skip_ws();
+                if self.peek() == Some('}') { self.pos += 1; return Ok(Json::Obj(entries)); }
+                loop {
+                    self.skip_ws();
+                    if self.peek() != Some('"') { return self.err("expected a string key"); }
+                    let k = self.string()?;
+                    self.expect(':')?;
+                    entries.push((k, self.value()?));
+                    self.skip_ws();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some('}') => { self.pos += 1; return Ok(Json::Obj(entries)); },
+                        _ => return self.err("expected ',' or '}'"),
+                    }
+                }
+            },
+            Some('[') => {
+                self.pos += 1;
+                let mut vs = Vec::new();
+                self.skip_ws();
+                if self.peek() == Some(']') { self.pos += 1; return Ok(Json::Arr(vs)); }
+                loop {
+                    vs.push(self.value()?);
+                    self.skip_ws();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some(']') => { self.pos += 1; return Ok(Json::Arr(vs)); },
+                        _ => return self.err("expected ',' or ']'"),
+                    }
+                }
+            },
+            Some('"') => Ok(Json::Str(self.string()?)),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+                    self.pos += 1;
+                }
+                let s: String = self.chars[start..self.pos].iter().collect();
+                match s.parse::<f64>() {
+                    Ok(f) => Ok(Json::Num(f)),
+                    Err(_) => { self.pos = start; self.err("bad number") },
+                }
+            },
+            Some(_) => self.err("unexpected character"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                None => return self.err("unterminated string"),
+                Some('"') => { self.pos += 1; return Ok(s); },
+                Some('\\') => {
+                    self.pos += 1;
+                    let c = match self.peek() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex: String = self.chars[self.pos+1..(self.pos+5).min(self.chars.len())].iter().collect();
+                            let code = u32::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 4);
+                            match code.and_then(char::from_u32) {
+                                Some(c) => { self.pos += 4; c },
+                                None => return self.err("bad unicode escape"),
+                            }
+                        },
+                        Some(c) => c,
+                        None => return self.err("unterminated string"),
+                    };
+                    s.push(c);
+                    self.pos += 1;
+                },
+                Some(c) => { s.push(c); self.pos += 1; },
+            }
+        }
+    }
+}
+
+fn parse_json(source: &str) -> Result<Json, String> {
+    let mut r = Reader { chars: source.chars().collect(), pos: 0 };
+    let v = r.value()?;
+    r.skip_ws();
+    if r.pos != r.chars.len() { return r.err("trailing characters"); }
+    Ok(v)
+}
+
+/// Write a phase the way PyZX does, e.g. `3\pi/4`, or the empty string for 0
+fn phase_to_json(p: Rational) -> String {
+    if p.is_zero() { return String::new(); }
+    let n = match *p.numer() { 1 => String::new(), -1 => String::from("-"), n => n.to_string() };
+    if *p.denom() == 1 { format!("{}\\pi", n) }
+    else { format!("{}\\pi/{}", n, p.denom()) }
+}
+
+/// Parse a fraction such as `-3/4`, or a decimal number
+fn parse_fraction(s: &str) -> Result<Rational, String> {
+    let s = s.trim();
+    let err = || format!("Bad number: '{}'", s);
+    if let Some((n, d)) = s.split_once('/') {
+        let n = n.trim().parse::<isize>().map_err(|_| err())?;
+        let d = d.trim().parse::<isize>().map_err(|_| err())?;
+        if d == 0 { return Err(err()); }
+        Ok(Rational::new(n, d))
+    } else if let Ok(n) = s.parse::<isize>() {
+        Ok(Rational::from_integer(n))
+    } else {
+        let f = s.parse::<f64>().map_err(|_| err())?;
+        Rational::approximate_float(f).ok_or_else(err)
+    }
+}
+
+/// Parse a phase, in units of pi, written as `3\pi/4`, `3π/4`, `3/4` or `0.75`
+fn phase_from_json(v: &Json) -> Result<Rational, String> {
+    let s = match v {
+        Json::Num(f) => return Rational::approximate_float(*f).ok_or_else(|| format!("Bad phase: {}", f)),
+        Json::Str(s) => s.trim(),
+        _ => return Err(String::from("Phase should be a string or a number")),
+    };
+    if s.is_empty() { return Ok(Rational::zero()); }
+
+    for pi in ["\\pi", "π", "pi"].iter() {
+        if s.contains(pi) {
+            let mut r = s.replacen(pi, "", 1).replace('*', "");
+            if r.starts_with('-') && (r.len() == 1 || r[1..].starts_with('/')) { r.insert(1, '1'); }
+            if r.is_empty() || r.starts_with('/') { r.insert(0, '1'); }
+            return parse_fraction(&r).map_err(|_| format!("Bad phase: '{}'", s));
+        }
+    }
+    parse_fraction(s).map_err(|_| format!("Bad phase: '{}'", s))
+}
+
+/// Write a scalar as a PyZX scalar object
+fn scalar_to_json(s: &ScalarN) -> Json {
+    let z = s.float_value();
+    if s.is_zero() || (z.re == 0.0 && z.im == 0.0) {
+        return Json::Obj(vec![
+            (String::from("power2"), Json::Num(0.0)),
+            (String::from("phase"), Json::Str(String::from("0"))),
+            (String::from("is_zero"), Json::Bool(true)),
+        ]);
+    }
+
+    // look for an exact representation sqrt(2)^p e^(i pi phase)
+    if let Scalar::Exact(_, coeffs) = s {
+        let d = coeffs.len().lcm(&4) as isize;
+        let p = (2.0 * z.norm().log2()).round() as i32;
+        let k = (z.arg() / PI * d as f64).round() as isize;
+        let phase = Rational::new(k, d).mod2();
+        if ScalarN::sqrt2_pow(p) * ScalarN::from_phase(phase) == *s {
+            return Json::Obj(vec![
+                (String::from("power2"), Json::Num(p as f64)),
+                (String::from("phase"), Json::Str(phase.to_string())),
+            ]);
+        }
+    }
+
+    Json::Obj(vec![
+        (String::from("power2"), Json::Num(0.0)),
+        (String::from("phase"), Json::Str(String::from("0"))),
+        (String::from("floatfactor"), Json::Str(format!("({}{:+}j)", z.re, z.im))),
+    ])
+}
+
+/// Parse a complex number in Python's notation, e.g. `(0.5-1j)`
+fn parse_complex(s: &str) -> Result<Complex<f64>, String> {
+    let err = || format!("Bad complex number: '{}'", s);
+    let t = s.trim().trim_start_matches('(').trim_end_matches(')').trim();
+    if let Some(t) = t.strip_suffix('j') {
+        // split before the last sign that isn't part of an exponent
+        let split = t.char_indices().rev()
+            .find(|&(i, c)| i > 0 && (c == '+' || c == '-') && !t[..i].ends_with(['e', 'E']))
+            .map(|(i, _)| i);
+        let (re, im) = match split {
+            Some(i) => (t[..i].parse::<f64>().map_err(|_| err())?, &t[i..]),
+            None => (0.0, t),
+        };
+        let im = match im {
+            "" | "+" => 1.0,
+            "-" => -1.0,
+            _ => im.parse::<f64>().map_err(|_| err())?,
+        };
+        Ok(Complex::new(re, im))
+    } else {
+        Ok(Complex::new(t.parse::<f64>().map_err(|_| err())?, 0.0))
+    }
+}
+
+/// Read a PyZX scalar object, or a string containing one
+fn scalar_from_json(j: &Json) -> Result<ScalarN, String> {
+    let j = match j {
+        Json::Str(s) => parse_json(s)?,
+        _ => j.clone(),
+    };
+    if j.get("is_zero") == Some(&Json::Bool(true)) { return Ok(ScalarN::zero()); }
+
+    let mut s = ScalarN::one();
+    match j.get("power2") {
+        Some(Json::Num(p)) => s.mul_sqrt2_pow(*p as i32),
+        None => {},
+        _ => return Err(String::from("Scalar power2 should be a number")),
+    }
+    if let Some(p) = j.get("phase") { s.mul_phase(phase_from_json(p)?); }
+    if let Some(Json::Arr(ps)) = j.get("phasenodes") {
+        for p in ps { s *= ScalarN::one_plus_phase(phase_from_json(p)?); }
+    }
+    match j.get("floatfactor") {
+        Some(Json::Str(f)) => { let c = parse_complex(f)?; if c != Complex::one() { s *= Scalar::Float(c); } },
+        Some(Json::Num(f)) if *f != 1.0 => s *= ScalarN::real(*f),
+        _ => {},
+    }
+    Ok(s)
+}
+
+/// Read the qubit and row from a `[row, -qubit]` coordinate
+fn coord_from_json(attr: &Json) -> (i32, i32) {
+    match attr.get("annotation").and_then(|a| a.get("coord")) {
+        Some(Json::Arr(c)) if c.len() == 2 => {
+            let x = if let Json::Num(x) = c[0] { x } else { 0.0 };
+            let y = if let Json::Num(y) = c[1] { y } else { 0.0 };
+            ((-y).round() as i32, x.round() as i32)
+        },
+        _ => (0, 0),
+    }
+}
+
+fn is_true(v: Option<&Json>) -> bool {
+    match v {
+        Some(Json::Bool(b)) => *b,
+        Some(Json::Str(s)) => s == "true",
+        _ => false,
+    }
+}
+
+/// Add an edge, merging it with an existing one between spiders
+fn add_json_edge<G: GraphLike>(g: &mut G, s: V, t: V, et: EType) -> Result<(), String> {
+    if s == t || g.connected(s, t) {
+        let ok = |ty| ty == VType::Z || ty == VType::X;
+        if !ok(g.vertex_type(s)) || !ok(g.vertex_type(t)) {
+            return Err(String::from("Parallel edges and self-loops are only supported on Z and X spiders"));
+        }
+        g.add_edge_smart(s, t, et);
+    } else {
+        g.add_edge_with_type(s, t, et);
+    }
+    Ok(())
+}
+
+/// Write a graph in PyZX's JSON format
+///
+/// Boundary vertices are called `b<v>` and all others `v<v>`, where `<v>` is the
+/// vertex index in `g`.
+pub fn graph_to_json<G: GraphLike>(g: &G) -> String {
+    let coord = |v: V| Json::Arr(vec![Json::Num(g.row(v) as f64), Json::Num(-g.qubit(v) as f64)]);
+    let name = |v: V| if g.vertex_type(v) == VType::B { format!("b{}", v) } else { format!("v{}", v) };
+
+    // inputs and outputs come first, in order, so PyZX reads them back the same way
+    let mut boundaries: Vec<V> = g.inputs().iter().chain(g.outputs().iter()).copied().collect();
+    for v in g.vertices() {
+        if g.vertex_type(v) == VType::B && !boundaries.contains(&v) { boundaries.push(v); }
+    }
+    let mut seen = rustc_hash::FxHashSet::default();
+    boundaries.retain(|&v| seen.insert(v));
+
+    let wire_vs = boundaries.iter().map(|&v| {
+        (name(v), Json::Obj(vec![(String::from("annotation"), Json::Obj(vec![
+            (String::from("boundary"), Json::Bool(true)),
+            (String::from("coord"), coord(v)),
+            (String::from("input"), Json::Bool(g.inputs().contains(&v))),
+            (String::from("output"), Json::Bool(g.outputs().contains(&v))),
+        ]))]))
+    }).collect();
+
+    let mut node_vs = Vec::new();
+    for v in g.vertices() {
+        let ty = g.vertex_type(v);
+        if ty == VType::B { continue; }
+        let mut data = vec![(String::from("type"), Json::Str(String::from(match ty {
+            VType::X => "X",
+            VType::H => "hadamard",
+            _ => "Z",
+        })))];
+        if ty == VType::H { data.push((String::from("is_edge"), Json::Str(String::from("false")))); }
+        let p = g.phase(v);
+        if !p.is_zero() { data.push((String::from("value"), Json::Str(phase_to_json(p)))); }
+        node_vs.push((name(v), Json::Obj(vec![
+            (String::from("annotation"), Json::Obj(vec![(String::from("coord"), coord(v))])),
+            (String::from("data"), Json::Obj(data)),
+        ])));
+    }
+
+    let mut edges = Vec::new();
+    let edge = |s: String, t: String| Json::Obj(vec![
+        (String::from("src"), Json::Str(s)),
+        (String::from("tgt"), Json::Str(t)),
+    ]);
+    let mut num_hadamards = 0;
+    for (s, t, et) in g.edges() {
+        if et == EType::N {
+            edges.push((format!("e{}", edges.len()), edge(name(s), name(t))));
+        } else {
+            let h = format!("h{}", num_hadamards);
+            num_hadamards += 1;
+            let mid = Json::Arr(vec![
+                Json::Num((g.row(s) + g.row(t)) as f64 / 2.0),
+                Json::Num(-(g.qubit(s) + g.qubit(t)) as f64 / 2.0),
+            ]);
+            node_vs.push((h.clone(), Json::Obj(vec![
+                (String::from("annotation"), Json::Obj(vec![(String::from("coord"), mid)])),
+                (String::from("data"), Json::Obj(vec![
+                    (String::from("type"), Json::Str(String::from("hadamard"))),
+                    (String::from("is_edge"), Json::Str(String::from("true"))),
+                ])),
+            ])));
+            edges.push((format!("e{}", edges.len()), edge(name(s), h.clone())));
+            edges.push((format!("e{}", edges.len()), edge(name(t), h)));
+        }
+    }
+
+    let j = Json::Obj(vec![
+        (String::from("wire_vertices"), Json::Obj(wire_vs)),
+        (String::from("node_vertices"), Json::Obj(node_vs)),
+        (String::from("undir_edges"), Json::Obj(edges)),
+        (String::from("variable_types"), Json::Obj(Vec::new())),
+        (String::from("scalar"), Json::Str(scalar_to_json(g.scalar()).to_compact())),
+    ]);
+    let mut out = String::new();
+    j.write(&mut out, 0);
+    out.push('\n');
+    out
+}
+
+/// Read a graph in PyZX's JSON format
+///
+/// Inputs and outputs are read in the order their boundary vertices appear, unless
+/// they are marked with an index instead of `true`, in which case they are sorted by it.
+pub fn json_to_graph<G: GraphLike>(source: &str) -> Result<G, String> {
+    let j = parse_json(source)?;
+    let mut g = G::new();
+    let mut names: FxHashMap<&str, V> = FxHashMap::default();
+    let mut hadamards: FxHashMap<&str, Vec<V>> = FxHashMap::default();
+
+    for (name, attr) in j.get("node_vertices").map_or(&[][..], |n| n.entries()) {
+        let data = attr.get("data");
+        let ty = match data.and_then(|d| d.get("type")) {
+            None => "Z",
+            Some(Json::Str(t)) => t.as_str(),
+            _ => return Err(format!("Bad vertex type for {}", name)),
+        };
+        if ty == "hadamard" && is_true(data.and_then(|d| d.get("is_edge"))) {
+            hadamards.insert(name, Vec::new());
+            continue;
+        }
+
+        let ty = match ty {
+            "Z" => VType::Z,
+            "X" => VType::X,
+            "hadamard" => VType::H,
+            _ => return Err(format!("Unsupported vertex type '{}' for {}", ty, name)),
+        };
+        let phase = match data.and_then(|d| d.get("value")) {
+            Some(p) => phase_from_json(p)?.mod2(),
+            None => Rational::zero(),
+        };
+        let (qubit, row) = coord_from_json(attr);
+        names.insert(name, g.add_vertex_with_data(VData { ty, phase, qubit, row }));
+    }
+
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    for (i, (name, attr)) in j.get("wire_vertices").map_or(&[][..], |n| n.entries()).iter().enumerate() {
+        let (qubit, row) = coord_from_json(attr);
+        let v = g.add_vertex_with_data(VData { ty: VType::B, phase: Rational::zero(), qubit, row });
+        names.insert(name, v);
+        let ann = attr.get("annotation");
+        for (key, io) in [("input", &mut inputs), ("output", &mut outputs)] {
+            match ann.and_then(|a| a.get(key)) {
+                Some(Json::Num(k)) => io.push((*k, i, v)),
+                b if is_true(b) => io.push((f64::INFINITY, i, v)),
+                _ => {},
+            }
+        }
+    }
+    for io in [&mut inputs, &mut outputs] {
+        io.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    }
+    g.set_inputs(inputs.iter().map(|x| x.2).collect());
+    g.set_outputs(outputs.iter().map(|x| x.2).collect());
+
+    for (name, e) in j.get("undir_edges").map_or(&[][..], |n| n.entries()) {
+        let end = |key: &str| match e.get(key) {
+            Some(Json::Str(s)) => Ok(s.as_str()),
+            _ => Err(format!("Edge {} is missing '{}'", name, key)),
+        };
+        let (s, t) = (end("src")?, end("tgt")?);
+        for n in [s, t] {
+            if !names.contains_key(n) && !hadamards.contains_key(n) {
+                return Err(format!("Edge {} refers to unknown vertex {}", name, n));
+            }
+        }
+        match (hadamards.contains_key(s), hadamards.contains_key(t)) {
+            (true, true) => {
+                // two Hadamards in a row, as PyZX does, put a spider in between
+                let v = g.add_vertex(VType::Z);
+                hadamards.get_mut(s).unwrap().push(v);
+                hadamards.get_mut(t).unwrap().push(v);
+            },
+            (true, false) => hadamards.get_mut(s).unwrap().push(names[t]),
+            (false, true) => hadamards.get_mut(t).unwrap().push(names[s]),
+            (false, false) => add_json_edge(&mut g, names[s], names[t], EType::N)?,
+        }
+    }
+
+    let mut hs: Vec<(&str, Vec<V>)> = hadamards.into_iter().collect();
+    hs.sort();
+    for (name, vs) in hs {
+        if vs.len() != 2 {
+            return Err(format!("Hadamard edge {} should have exactly 2 neighbours", name));
+        }
+        add_json_edge(&mut g, vs[0], vs[1], EType::H)?;
+    }
+
+    if let Some(s) = j.get("scalar") {
+        *g.scalar_mut() = scalar_from_json(s)?;
+    }
+    Ok(g)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec_graph::Graph;
+    use crate::circuit::Circuit;
+    use crate::tensor::*;
+    use crate::simplify::*;
+
+    #[test]
+    fn round_trip() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[3];
+            h q[0];
+            cx q[0], q[1];
+            ccz q[0], q[1], q[2];
+            rz(3*pi/4) q[2];
+            rx(-pi/2) q[1];
+        "#).unwrap();
+        let mut g: Graph = c.to_graph();
+        g.scalar_mut().mul_sqrt2_pow(-3);
+        g.scalar_mut().mul_phase(Rational::new(1,4));
+        let h = g.to_json();
+        let g1: Graph = Graph::from_json(&h).unwrap();
+
+        assert_eq!(g.num_vertices(), g1.num_vertices());
+        assert_eq!(g.num_edges(), g1.num_edges());
+        assert_eq!(g.scalar(), g1.scalar());
+        assert_eq!(g1.inputs().len(), 3);
+        for (&v, &v1) in g.inputs().iter().zip(g1.inputs()) {
+            assert_eq!(g.qubit(v), g1.qubit(v1));
+        }
+        assert_eq!(g.to_tensor4(), g1.to_tensor4());
+
+        // an H-box, and a graph after simplification
+        let h = g.add_vertex_with_data(VData { ty: VType::H, phase: Rational::new(1,2), qubit: 3, row: 1 });
+        g.add_edge(h, g.inputs()[0]);
+        let g1: Graph = Graph::from_json(&g.to_json()).unwrap();
+        assert_eq!(g1.vertex_type(g1.neighbor_vec(g1.inputs()[0])[0]), VType::H);
+        assert_eq!(g.to_tensor4(), g1.to_tensor4());
+
+        let mut g: Graph = c.to_graph();
+        full_reduce(&mut g);
+        let g1: Graph = Graph::from_json(&g.to_json()).unwrap();
+        assert_eq!(g.scalar(), g1.scalar());
+        assert_eq!(g.to_tensor4(), g1.to_tensor4());
+    }
+
+    #[test]
+    fn read_pyzx() {
+        // written by PyZX 0.7 for a CZ with a phase, with Hadamard edges
+        let g: Graph = Graph::from_json(r#"{
+            "wire_vertices": {
+                "b0": {"annotation": {"boundary": true, "coord": [0, 0], "input": true, "output": false}},
+                "b1": {"annotation": {"boundary": true, "coord": [0, -1], "input": true, "output": false}},
+                "b2": {"annotation": {"boundary": true, "coord": [3, 0], "input": false, "output": true}},
+                "b3": {"annotation": {"boundary": true, "coord": [3, -1], "input": false, "output": true}}
+            },
+            "node_vertices": {
+                "v0": {"annotation": {"coord": [1, 0]}, "data": {"type": "Z", "value": "\\pi/2"}},
+                "v1": {"annotation": {"coord": [1, -1]}, "data": {"type": "Z"}},
+                "v2": {"annotation": {"coord": [1.0, -0.5]}, "data": {"type": "hadamard", "is_edge": "true"}}
+            },
+            "undir_edges": {
+                "e0": {"src": "b0", "tgt": "v0"},
+                "e1": {"src": "b1", "tgt": "v1"},
+                "e2": {"src": "v0", "tgt": "v2"},
+                "e3": {"src": "v1", "tgt": "v2"},
+                "e4": {"src": "v0", "tgt": "b2"},
+                "e5": {"src": "v1", "tgt": "b3"}
+            },
+            "variable_types": {},
+            "scalar": "{\"power2\": 1, \"phase\": \"0\"}"
+        }"#).unwrap();
+
+        assert_eq!(g.num_vertices(), 6);
+        assert_eq!(g.num_edges(), 5);
+        let (v0, v1) = (g.neighbor_vec(g.inputs()[0])[0], g.neighbor_vec(g.inputs()[1])[0]);
+        assert_eq!(g.phase(v0), Rational::new(1,2));
+        assert_eq!(g.qubit(v1), 1);
+        assert_eq!(g.edge_type(v0, v1), EType::H);
+        assert_eq!(*g.scalar(), ScalarN::sqrt2());
+
+        let c = Circuit::from_qasm("qreg q[2]; cz q[0], q[1]; s q[0];").unwrap();
+        assert_eq!(g.to_tensor4(), c.to_tensor4());
+    }
+
+    #[test]
+    fn phases_and_scalars() {
+        for (s, p) in [("", (0,1)), ("\\pi", (1,1)), ("-\\pi", (-1,1)), ("3\\pi/4", (3,4)),
+                       ("-\\pi/2", (-1,2)), ("π/4", (1,4)), ("-3π/2", (-3,2)), ("1/3", (1,3)),
+                       ("0.25", (1,4))].iter() {
+            assert_eq!(phase_from_json(&Json::Str(String::from(*s))), Ok(Rational::new(p.0, p.1)), "{}", s);
+        }
+        for p in [Rational::new(1,1), Rational::new(-1,2), Rational::new(7,4)].iter() {
+            assert_eq!(phase_from_json(&Json::Str(phase_to_json(*p))), Ok(*p));
+        }
+        assert!(phase_from_json(&Json::Str(String::from("x\\pi"))).is_err());
+
+        let mut s = ScalarN::sqrt2_pow(-5);
+        s.mul_phase(Rational::new(3,4));
+        let j = scalar_to_json(&s);
+        assert_eq!(j.to_compact(), r#"{"power2": -5, "phase": "3/4"}"#);
+        assert_eq!(scalar_from_json(&j), Ok(s));
+
+        let s = ScalarN::one_plus_phase(Rational::new(1,3));
+        let s1 = scalar_from_json(&scalar_to_json(&s)).unwrap();
+        assert!((s.float_value() - s1.float_value()).norm() < 1e-10);
+        assert_eq!(scalar_from_json(&scalar_to_json(&ScalarN::zero())), Ok(ScalarN::zero()));
+
+        let s = scalar_from_json(&Json::Str(String::from(
+            r#"{"power2": 2, "phase": "1/2", "phasenodes": ["1/2"], "floatfactor": "(0.5-0j)"}"#))).unwrap();
+        assert!((s.float_value() - Complex::new(-1.0, 1.0)).norm() < 1e-10);
+        assert_eq!(parse_complex("1e-3-2.5e+2j"), Ok(Complex::new(1e-3, -250.0)));
+        assert_eq!(parse_complex("-1j"), Ok(Complex::new(0.0, -1.0)));
+    }
+
+    #[test]
+    fn errors() {
+        let e = Graph::from_json("{\"node_vertices\": {\"v0\": {\"data\": {\"type\": \"W\"}}}}").unwrap_err();
+        assert_eq!(e, "Unsupported vertex type 'W' for v0");
+        let e = Graph::from_json("{\"undir_edges\": {\"e0\": {\"src\": \"a\", \"tgt\": \"b\"}}}").unwrap_err();
+        assert_eq!(e, "Edge e0 refers to unknown vertex a");
+        let e = Graph::from_json("{\n  \"wire_vertices\": [1, 2,]\n}").unwrap_err();
+        assert_eq!(e, "Invalid JSON at line 2, column 26: unexpected character");
+    }
+}
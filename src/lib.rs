@@ -19,9 +19,23 @@ pub mod vec_graph;
 pub mod hash_graph;
 pub mod gate;
 pub mod circuit;
+pub mod qasm;
+pub mod qc;
+pub mod quipper;
+pub mod json;
 pub mod scalar;
 pub mod tensor;
 pub mod linalg;
+pub mod architecture;
+pub mod phase_poly;
+pub mod tableau;
+pub mod simulate;
+pub mod verify;
 pub mod extract;
 pub mod basic_rules;
+pub mod rewrite_log;
+pub mod simplify;
+
+#[cfg(test)]
+mod test_util;
 
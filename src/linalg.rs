@@ -19,6 +19,8 @@
 use std::fmt;
 use std::cmp::min;
 use rustc_hash::FxHashMap;
+use crate::circuit::Circuit;
+use crate::gate::*;
 
 /// A type for matrices over F2
 #[derive(PartialEq,Eq,Clone,Debug)]
@@ -180,7 +182,7 @@ impl Mat2 {
 
         let rank = pivot_row;
 
-        if full_reduce {
+        if full_reduce && rank > 0 {
             pivot_row -= 1;
             let mut pivot_cols1 = pivot_cols.clone();
 
@@ -202,20 +204,17 @@ impl Mat2 {
                     }
                 }
 
-                loop {
-                    if let Some(pcol) = pivot_cols1.pop() {
-                        if i0 > pcol || pcol >= i1 { break; }
-                        for r in 0..pivot_row {
-                            if self.d[r][pcol] != 0 {
-                                self.row_add(pivot_row, r);
-                                x.row_add(pivot_row, r);
-                                y.col_add(r, pivot_row);
-                            }
+                while let Some(&pcol) = pivot_cols1.last() {
+                    if i0 > pcol || pcol >= i1 { break; }
+                    pivot_cols1.pop();
+                    for r in 0..pivot_row {
+                        if self.d[r][pcol] != 0 {
+                            self.row_add(pivot_row, r);
+                            x.row_add(pivot_row, r);
+                            y.col_add(r, pivot_row);
                         }
-                        if pivot_row > 0 { pivot_row -= 1; }
-                    } else {
-                        break;
                     }
+                    if pivot_row > 0 { pivot_row -= 1; }
                 }
             }
         }
@@ -235,6 +234,28 @@ impl Mat2 {
         m.gauss(false)
     }
 
+    /// Synthesise a CNOT circuit whose parity matrix is this one
+    ///
+    /// Row i of the matrix gives the parity of the inputs that ends up on qubit i. The circuit
+    /// comes from the row operations of Gaussian elimination, using the Patel/Markov/Hayes
+    /// blocks of the given size, so a blocksize around log2 of the number of qubits gives
+    /// asymptotically fewer CNOTs. Returns None if the matrix is not invertible.
+    pub fn to_cnots(&self, blocksize: usize) -> Option<Circuit> {
+        let n = self.num_rows();
+        if n != self.num_cols() { return None; }
+
+        let mut m = self.clone();
+        let mut ops = CNOTMaker(Circuit::new(n));
+        if m.gauss_helper(true, blocksize, &mut ops, &mut (), &mut vec![]) < n {
+            return None;
+        }
+
+        // the row operations reduce the matrix to the identity, so they give its inverse,
+        // and the matrix itself is the same CNOTs in reverse order
+        ops.0.gates.reverse();
+        Some(ops.0)
+    }
+
     pub fn inverse(&self) -> Option<Mat2> {
         if self.num_rows() != self.num_cols() {
             return None;
@@ -276,6 +297,17 @@ impl RowColOps for Mat2 {
     }
 }
 
+/// Records row operations as CNOT gates, where adding row r0 to row r1 is a CNOT with
+/// control r0 and target r1
+struct CNOTMaker(Circuit);
+
+impl RowColOps for CNOTMaker {
+    fn row_add(&mut self, r0: usize, r1: usize) { self.0.push(Gate::new(CNOT, vec![r0, r1])); }
+    fn col_add(&mut self, _: usize, _: usize) {}
+    fn row_swap(&mut self, _: usize, _: usize) {}
+    fn col_swap(&mut self, _: usize, _: usize) {}
+}
+
 impl fmt::Display for Mat2 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for row in &self.d {
@@ -489,5 +521,43 @@ mod tests {
         ]);
         assert_eq!(vi_exp, vi);
     }
+
+    #[test]
+    fn cnot_synthesis() {
+        use crate::tensor::*;
+        use crate::test_util::cnot_circuit;
+
+        let (c, m) = cnot_circuit(5, 20);
+
+        for &blocksize in &[1, 2, 3, 5] {
+            let c1 = m.to_cnots(blocksize).expect("m should be invertible");
+            assert!(c1.gates.iter().all(|g| g.t == CNOT));
+            assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+        }
+
+        assert_eq!(Mat2::id(3).to_cnots(2), Some(Circuit::new(3)));
+        assert_eq!(Mat2::ones(3, 3).to_cnots(2), None);
+        assert_eq!(Mat2::ones(2, 3).to_cnots(2), None);
+    }
+
+    #[test]
+    fn full_reduce_blocks() {
+        // pivots span several blocks of columns
+        let mut v = Mat2::new(vec![
+            vec![1, 1, 1, 1, 1],
+            vec![0, 1, 1, 1, 1],
+            vec![0, 0, 1, 1, 1],
+            vec![0, 0, 0, 1, 1],
+        ]);
+        assert_eq!(v.gauss(true), 4);
+
+        let v_exp = Mat2::new(vec![
+            vec![1, 0, 0, 0, 0],
+            vec![0, 1, 0, 0, 0],
+            vec![0, 0, 1, 0, 0],
+            vec![0, 0, 0, 1, 1],
+        ]);
+        assert_eq!(v, v_exp);
+    }
 }
 
@@ -0,0 +1,318 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Phase polynomials of CNOT+phase circuits, and resynthesis using Gray-synth
+//!
+//! A circuit made of CNOTs and Z phase gates acts on computational basis states as
+//! |x> ↦ e^(iπ f(x)) |Ax>, where A is an invertible matrix over F2 and f is a sum of
+//! terms α (y·x), for parities y. Since the phases all commute, the terms can be
+//! synthesised in any order, which often lets T gates merge and saves CNOTs.
+
+use num::{Rational,Zero};
+use rustc_hash::FxHashMap;
+use crate::circuit::Circuit;
+use crate::gate::*;
+use crate::linalg::*;
+use crate::scalar::Mod2;
+
+/// The phase polynomial and parity matrix of a CNOT+phase circuit
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub struct PhasePoly {
+    nqubits: usize,
+    /// parities, given as 0/1 vectors over the input qubits, with their phases
+    pub terms: Vec<(Vec<u32>,Rational)>,
+    /// the parity of the inputs on each output qubit, given by its rows
+    pub parity: Mat2,
+}
+
+impl PhasePoly {
+    /// The phase polynomial of a circuit made only of CNOT, SWAP, CZ, ParityPhase and
+    /// Z phase gates, or None if the circuit has any other kind of gate
+    ///
+    /// Terms with the same parity are combined, and terms whose phase adds up to zero
+    /// are dropped.
+    pub fn from_circuit(c: &Circuit) -> Option<PhasePoly> {
+        let n = c.num_qubits();
+        let mut parity = Mat2::id(n);
+        let mut terms: Vec<(Vec<u32>,Rational)> = Vec::new();
+        let mut index: FxHashMap<Vec<u32>,usize> = FxHashMap::default();
+        let mut add_term = |y: Vec<u32>, phase: Rational| {
+            if let Some(&i) = index.get(&y) {
+                terms[i].1 += phase;
+            } else {
+                index.insert(y.clone(), terms.len());
+                terms.push((y, phase));
+            }
+        };
+
+        for g in &c.gates {
+            match g.t {
+                CNOT => parity.row_add(g.qs[0], g.qs[1]),
+                SWAP => parity.row_swap(g.qs[0], g.qs[1]),
+                ZPhase => add_term(parity[g.qs[0]].clone(), g.phase),
+                Z => add_term(parity[g.qs[0]].clone(), Rational::new(1,1)),
+                S => add_term(parity[g.qs[0]].clone(), Rational::new(1,2)),
+                Sdg => add_term(parity[g.qs[0]].clone(), Rational::new(-1,2)),
+                T => add_term(parity[g.qs[0]].clone(), Rational::new(1,4)),
+                Tdg => add_term(parity[g.qs[0]].clone(), Rational::new(-1,4)),
+                CZ => {
+                    // x0 x1 = (x0 + x1 - (x0 XOR x1)) / 2
+                    let (y0, y1) = (parity[g.qs[0]].clone(), parity[g.qs[1]].clone());
+                    let y01 = y0.iter().zip(&y1).map(|(a, b)| a ^ b).collect();
+                    add_term(y0, Rational::new(1,2));
+                    add_term(y1, Rational::new(1,2));
+                    add_term(y01, Rational::new(-1,2));
+                },
+                ParityPhase => {
+                    let mut y = vec![0; n];
+                    for &q in &g.qs {
+                        for (a, b) in y.iter_mut().zip(&parity[q]) { *a ^= b; }
+                    }
+                    add_term(y, g.phase);
+                },
+                _ => return None,
+            }
+        }
+
+        let terms = terms.into_iter()
+            .map(|(y, phase)| (y, phase.mod2()))
+            .filter(|(y, phase)| !phase.is_zero() && y.contains(&1))
+            .collect();
+        Some(PhasePoly { nqubits: n, terms, parity })
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.nqubits
+    }
+
+    /// Synthesise a circuit for the phase polynomial using Gray-synth
+    ///
+    /// This is the algorithm from:
+    ///
+    /// M. Amy, P. Azimzadeh, M. Mosca. On the CNOT-complexity of CNOT-phase circuits.
+    /// Quantum Sci. Technol. 2018
+    ///
+    /// It recursively splits the terms on the value of one qubit at a time, applying CNOTs
+    /// so the parities in each group share as much as possible, and each term becomes a
+    /// ZPhase gate as soon as its parity sits on a single qubit. The CNOTs needed to fix the
+    /// parity matrix at the end are synthesised with [Mat2::to_cnots].
+    pub fn to_circuit(&self) -> Circuit {
+        let n = self.nqubits;
+        let mut c = Circuit::new(n);
+
+        // the current parity on each qubit, and each term written as a sum of these
+        let mut parity = Mat2::id(n);
+        let mut terms: Vec<Vec<u32>> = self.terms.iter().map(|(y, _)| y.clone()).collect();
+        let mut done = vec![false; terms.len()];
+        self.emit_ready(&mut c, &terms, &mut done);
+
+        let mut stack = vec![((0..terms.len()).collect::<Vec<_>>(), (0..n).collect::<Vec<_>>(), None)];
+        while let Some((s, qs, target)) = stack.pop() {
+            let mut s: Vec<usize> = s.into_iter().filter(|&t| !done[t]).collect();
+            if s.is_empty() { continue; }
+
+            if let Some(i) = target {
+                // while every term in s involves both qubit i and some other qubit j, add j to i
+                while !s.is_empty() {
+                    let j = match (0..n).find(|&j| j != i &&
+                        s.iter().all(|&t| terms[t][i] == 1 && terms[t][j] == 1))
+                    {
+                        Some(j) => j,
+                        None => break,
+                    };
+                    c.push(Gate::new(CNOT, vec![j, i]));
+                    parity.row_add(j, i);
+                    for y in terms.iter_mut() { y[j] ^= y[i]; }
+                    self.emit_ready(&mut c, &terms, &mut done);
+                    s.retain(|&t| !done[t]);
+                }
+                if s.is_empty() { continue; }
+            }
+
+            if qs.is_empty() { continue; }
+            let j = *qs.iter().max_by_key(|&&j| {
+                let ones = s.iter().filter(|&&t| terms[t][j] == 1).count();
+                ones.max(s.len() - ones)
+            }).unwrap();
+            let (s1, s0): (Vec<usize>, Vec<usize>) = s.into_iter().partition(|&t| terms[t][j] == 1);
+            let qs1: Vec<usize> = qs.into_iter().filter(|&q| q != j).collect();
+            stack.push((s0, qs1.clone(), target));
+            stack.push((s1, qs1, target.or(Some(j))));
+        }
+
+        // anything left over gets synthesised on its own, by collecting its parity on one qubit
+        for t in 0..terms.len() {
+            if done[t] { continue; }
+            let i = terms[t].iter().position(|&a| a == 1).unwrap();
+            for j in i+1..n {
+                if terms[t][j] == 1 {
+                    c.push(Gate::new(CNOT, vec![j, i]));
+                    parity.row_add(j, i);
+                    for y in terms.iter_mut() { y[j] ^= y[i]; }
+                }
+            }
+            self.emit_ready(&mut c, &terms, &mut done);
+        }
+
+        // the CNOTs so far take the identity to `parity`, so finish with `self.parity * parity^-1`
+        let fix = &self.parity * parity.inverse().expect("CNOT circuits are invertible");
+        let blocksize = ((n as f64).log2() as usize).max(1);
+        c + fix.to_cnots(blocksize).expect("parity matrices are invertible")
+    }
+
+    /// Add a ZPhase gate for every term whose parity is currently on a single qubit
+    fn emit_ready(&self, c: &mut Circuit, terms: &[Vec<u32>], done: &mut [bool]) {
+        for (t, y) in terms.iter().enumerate() {
+            if done[t] || y.iter().sum::<u32>() != 1 { continue; }
+            let q = y.iter().position(|&a| a == 1).unwrap();
+            c.push(Gate::new_with_phase(ZPhase, vec![q], self.terms[t].1));
+            done[t] = true;
+        }
+    }
+}
+
+fn is_phase_poly_gate(g: &Gate) -> bool {
+    matches!(g.t, CNOT | SWAP | CZ | ParityPhase | ZPhase | Z | S | Sdg | T | Tdg)
+}
+
+/// Split a circuit into blocks of CNOT+phase gates and blocks of other gates
+///
+/// The blocks alternate, and composing them gives a circuit equal to the original. A gate
+/// of another kind only ends the current CNOT+phase block on its own qubits, so CNOT+phase
+/// gates on other qubits can still be moved into the block. The CNOT+phase blocks are the ones
+/// where [PhasePoly::from_circuit] succeeds.
+pub fn phase_poly_blocks(c: &Circuit) -> Vec<Circuit> {
+    let n = c.num_qubits();
+    let mut blocks = Vec::new();
+    let mut block = Circuit::new(n);
+    let mut after = Circuit::new(n);
+    let mut closed = vec![false; n];
+
+    for g in &c.gates {
+        if !is_phase_poly_gate(g) {
+            for &q in &g.qs { closed[q] = true; }
+            after.push(g.clone());
+        } else if g.qs.iter().all(|&q| !closed[q]) {
+            // g is on different qubits to all the gates in `after`, so commutes past them
+            block.push(g.clone());
+        } else {
+            if block.num_gates() != 0 { blocks.push(block); }
+            blocks.push(after);
+            block = Circuit::new(n);
+            after = Circuit::new(n);
+            closed = vec![false; n];
+            block.push(g.clone());
+        }
+    }
+
+    if block.num_gates() != 0 { blocks.push(block); }
+    if after.num_gates() != 0 { blocks.push(after); }
+    blocks
+}
+
+/// Resynthesise each CNOT+phase block of the circuit from its phase polynomial
+///
+/// The new block is only used if it has a lower T-count, or the same T-count and fewer
+/// gates.
+pub fn resynthesize(c: &Circuit) -> Circuit {
+    let cost = |c: &Circuit| (c.gates.iter().map(|g| g.tcount()).sum::<usize>(), c.num_gates());
+    let mut result = Circuit::new(c.num_qubits());
+    for block in phase_poly_blocks(c) {
+        match PhasePoly::from_circuit(&block) {
+            Some(poly) => {
+                let block1 = poly.to_circuit();
+                result = result + if cost(&block1) < cost(&block) { block1 } else { block };
+            },
+            None => result = result + block,
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+    use crate::test_util::check_benchmarks;
+
+    #[test]
+    fn phase_poly() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[3];
+            t q[0];
+            cx q[0], q[1];
+            t q[1];
+            cx q[1], q[2];
+            tdg q[2];
+            swap q[0], q[2];
+            cz q[0], q[1];
+            cx q[0], q[1];
+            t q[1];
+        "#).unwrap();
+        let p = PhasePoly::from_circuit(&c).unwrap();
+
+        // after the swap, q0 holds x0+x1+x2 and q1 holds x0+x1, so the CZ adds 1/2 to both
+        // of these and -1/2 to x2, and the last t adds 1/4 to x2 again
+        assert_eq!(p.terms, vec![
+            (vec![1,0,0], Rational::new(1,4)),
+            (vec![1,1,0], Rational::new(3,4)),
+            (vec![1,1,1], Rational::new(1,4)),
+            (vec![0,0,1], Rational::new(-1,4)),
+        ]);
+        assert_eq!(p.parity, Mat2::new(vec![vec![1,1,1], vec![0,0,1], vec![1,0,0]]));
+
+        let c1 = p.to_circuit();
+        assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+        assert_eq!(c1.gates.iter().filter(|g| g.t == ZPhase).count(), 4);
+
+        let h = Circuit::from_qasm("qreg q[1]; h q[0];").unwrap();
+        assert_eq!(PhasePoly::from_circuit(&h), None);
+    }
+
+    #[test]
+    fn gray_synth() {
+        // every parity of 4 qubits gets a different phase
+        let mut c = Circuit::new(4);
+        for mask in 1usize..16 {
+            let qs: Vec<usize> = (0..4).filter(|i| mask & (1 << i) != 0).collect();
+            c.push(Gate::new_with_phase(ParityPhase, qs, Rational::new(mask as isize, 16)));
+        }
+        let c1 = PhasePoly::from_circuit(&c).unwrap().to_circuit();
+        assert_eq!(c1.gates.iter().filter(|g| g.t == ZPhase).count(), 15);
+        assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+    }
+
+    #[test]
+    fn blocks() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[3];
+            cx q[0], q[1];
+            h q[2];
+            t q[1];
+            cx q[1], q[2];
+            s q[0];
+        "#).unwrap();
+        let blocks = phase_poly_blocks(&c);
+        let gates: Vec<Vec<GType>> = blocks.iter()
+            .map(|b| b.gates.iter().map(|g| g.t).collect()).collect();
+        assert_eq!(gates, vec![vec![CNOT, T], vec![HAD], vec![CNOT, S]]);
+    }
+
+    #[test]
+    fn resynthesis() {
+        check_benchmarks(resynthesize);
+    }
+}
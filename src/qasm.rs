@@ -0,0 +1,1615 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A parser for OpenQASM 2.0 and 3
+//!
+//! This supports the full gate definition language of OpenQASM 2.0, including
+//! user-defined gates, parameter expressions, and applying gates to whole registers
+//! at once. The gates in `qelib1.inc` are always available, whether or not it is
+//! included. Classical registers and measurements are accepted, but ignored, as
+//! [Circuit] has no classical part.
+//!
+//! Programs starting with `OPENQASM 3;` are read as OpenQASM 3. This covers the
+//! gate-level part of the language: `qubit` and `bit` declarations, gate definitions,
+//! the gates in `stdgates.inc`, `gphase`, constant `angle`, `float` and `int`
+//! declarations, and the gate modifiers `ctrl @`, `negctrl @`, `inv @` and `pow(k) @`.
+//! Modifiers are lowered to gates in [GType], so a controlled gate is only accepted
+//! if the controlled version of each gate it expands to is available, e.g.
+//! `ctrl @ x` becomes a CNOT and `ctrl(2) @ z` a CCZ, but `ctrl(3) @ x` is an error.
+//! Likewise, `pow(k) @` needs an integer `k` of size at most 1024, unless the gate is
+//! a single rotation.
+//! Classical control flow and subroutines are not supported.
+
+use num::{Rational,Zero,One,ToPrimitive};
+use num::traits::{CheckedAdd,CheckedMul,CheckedDiv,Signed};
+use rustc_hash::FxHashMap;
+use std::f64::consts::PI;
+use std::fmt;
+use crate::circuit::Circuit;
+use crate::gate::*;
+use crate::scalar::Mod2;
+
+/// An error from reading a circuit
+///
+/// This is returned by the parsers for OpenQASM, `.qc` and Quipper circuits, and by
+/// [Circuit::from_file]. Apart from [ReadError::Io], each variant records the line and
+/// column in the source where the problem was found, counting from 1.
+#[derive(Debug)]
+pub enum ReadError {
+    /// The program is malformed, e.g. a missing `;` or an undefined parameter
+    Parse { line: usize, column: usize, message: String },
+    /// A gate that is not defined
+    UnknownGate { line: usize, column: usize, name: String },
+    /// A gate that is defined, but can't be applied in the way it is used, e.g. a gate
+    /// with no controlled version under `ctrl @`
+    UnsupportedGate { line: usize, column: usize, name: String, reason: String },
+    /// A gate applied to the wrong number of parameters
+    WrongParameters { line: usize, column: usize, name: String, expected: usize, found: usize },
+    /// A gate applied to the wrong number of qubits
+    WrongQubits { line: usize, column: usize, name: String, found: usize },
+    /// A qubit or register which is out of range, undeclared, or used twice in one gate
+    InvalidQubit { line: usize, column: usize, qubit: String, reason: String },
+    /// A statement or feature of the language which has no counterpart in [Circuit],
+    /// e.g. classical control
+    Unsupported { line: usize, column: usize, message: String },
+    /// The file could not be read
+    Io(std::io::Error),
+}
+
+/// Another name for [ReadError], for code which only reads OpenQASM
+pub type QasmError = ReadError;
+
+impl ReadError {
+    /// The line and column where the error was found, if it came from the source
+    pub fn position(&self) -> Option<(usize, usize)> {
+        use ReadError::*;
+        match self {
+            Parse { line, column, .. } | UnknownGate { line, column, .. } |
+            UnsupportedGate { line, column, .. } | WrongParameters { line, column, .. } |
+            WrongQubits { line, column, .. } | InvalidQubit { line, column, .. } |
+            Unsupported { line, column, .. } => Some((*line, *column)),
+            Io(_) => None,
+        }
+    }
+
+    /// Set the position of an error raised where it wasn't known, i.e. with line 0
+    fn at(mut self, l: usize, c: usize) -> ReadError {
+        use ReadError::*;
+        match &mut self {
+            Parse { line, column, .. } | UnknownGate { line, column, .. } |
+            UnsupportedGate { line, column, .. } | WrongParameters { line, column, .. } |
+            WrongQubits { line, column, .. } | InvalidQubit { line, column, .. } |
+            Unsupported { line, column, .. } => {
+                if *line == 0 { *line = l; *column = c; }
+            },
+            Io(_) => {},
+        }
+        self
+    }
+
+    fn parse(msg: impl Into<String>) -> ReadError {
+        ReadError::Parse { line: 0, column: 0, message: msg.into() }
+    }
+
+    fn unknown_gate(name: &str) -> ReadError {
+        ReadError::UnknownGate { line: 0, column: 0, name: name.to_string() }
+    }
+
+    fn unsupported_gate(name: &str, reason: impl Into<String>) -> ReadError {
+        ReadError::UnsupportedGate { line: 0, column: 0, name: name.to_string(), reason: reason.into() }
+    }
+
+    fn wrong_qubits(name: &str, found: usize) -> ReadError {
+        ReadError::WrongQubits { line: 0, column: 0, name: name.to_string(), found }
+    }
+
+    fn invalid_qubit(qubit: impl ToString, reason: impl Into<String>) -> ReadError {
+        ReadError::InvalidQubit { line: 0, column: 0, qubit: qubit.to_string(), reason: reason.into() }
+    }
+
+    fn unsupported(msg: impl Into<String>) -> ReadError {
+        ReadError::Unsupported { line: 0, column: 0, message: msg.into() }
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ReadError::*;
+        if let Some((line, column)) = self.position() {
+            write!(f, "line {}, column {}: ", line, column)?;
+        }
+        match self {
+            Parse { message, .. } | Unsupported { message, .. } => write!(f, "{}", message),
+            UnknownGate { name, .. } => write!(f, "Unknown gate: {}", name),
+            UnsupportedGate { name, reason, .. } => write!(f, "Gate '{}' {}", name, reason),
+            WrongParameters { name, expected, found, .. } =>
+                write!(f, "Gate '{}' expects {} parameter(s), got {}", name, expected, found),
+            WrongQubits { name, found, .. } => write!(f, "Wrong number of qubits for gate '{}': {}", name, found),
+            InvalidQubit { qubit, reason, .. } => write!(f, "{}: {}", reason, qubit),
+            Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ReadError {
+    fn from(e: std::io::Error) -> ReadError { ReadError::Io(e) }
+}
+
+/// Gates from `qelib1.inc` which aren't built in to [GType], written in terms of
+/// gates which are.
+const QELIB1: &str = r#"
+gate u3(theta,phi,lambda) q { U(theta,phi,lambda) q; }
+gate u2(phi,lambda) q { U(pi/2,phi,lambda) q; }
+gate u(theta,phi,lambda) q { U(theta,phi,lambda) q; }
+gate u0(gamma) q { }
+gate id a { }
+gate y a { z a; x a; }
+gate ry(theta) a { sdg a; rx(theta) a; s a; }
+gate sx a { rx(pi/2) a; }
+gate sxdg a { rx(-pi/2) a; }
+gate cy a,b { sdg b; cx a,b; s b; }
+gate ch a,b { h b; sdg b; cx a,b; h b; t b; cx a,b; t b; h b; s b; x b; s a; }
+gate crz(lambda) a,b { rz(lambda/2) b; cx a,b; rz(-lambda/2) b; cx a,b; }
+gate crx(theta) a,b { h b; crz(theta) a,b; h b; }
+gate cry(theta) a,b { sdg b; crx(theta) a,b; s b; }
+gate cu1(lambda) a,b { rz(lambda/2) a; cx a,b; rz(-lambda/2) b; cx a,b; rz(lambda/2) b; }
+gate cp(lambda) a,b { cu1(lambda) a,b; }
+gate cu3(theta,phi,lambda) c,t {
+  rz((lambda+phi)/2) c;
+  rz((lambda-phi)/2) t;
+  cx c,t;
+  U(-theta/2,0,-(phi+lambda)/2) t;
+  cx c,t;
+  U(theta/2,phi,0) t;
+}
+gate cswap a,b,c { cx c,b; ccx a,b,c; cx c,b; }
+gate rzz(theta) a,b { cx a,b; rz(theta) b; cx a,b; }
+gate rxx(theta) a,b { h a; h b; rzz(theta) a,b; h a; h b; }
+"#;
+
+/// Gates from `stdgates.inc` which aren't built in to [GType], written in terms of
+/// gates which are. Unlike in OpenQASM 2.0, these are correct up to global phase, which
+/// matters once they are controlled.
+const STDGATES: &str = r#"
+gate cphase(λ) a, b { ctrl @ p(λ) a, b; }
+gate cp(λ) a, b { ctrl @ p(λ) a, b; }
+gate cu1(λ) a, b { ctrl @ p(λ) a, b; }
+gate u2(φ, λ) q { gphase(-(φ+λ+π/2)/2); U(π/2, φ, λ) q; }
+gate u3(θ, φ, λ) q { gphase(-(φ+λ+θ)/2); U(θ, φ, λ) q; }
+gate id a { }
+gate y a { U(π, π/2, π/2) a; }
+gate ry(θ) a { U(θ, 0, 0) a; }
+gate sx a { pow(1/2) @ x a; }
+gate cy a, b { ctrl @ y a, b; }
+gate ch a, b { ctrl @ h a, b; }
+gate crx(θ) a, b { ctrl @ rx(θ) a, b; }
+gate cry(θ) a, b { ctrl @ ry(θ) a, b; }
+gate crz(θ) a, b { ctrl @ rz(θ) a, b; }
+gate cu(θ, φ, λ, γ) c, t { p(γ) c; ctrl @ U(θ, φ, λ) c, t; }
+gate cswap a, b, c { ctrl @ swap a, b, c; }
+"#;
+
+#[derive(Debug,Clone,PartialEq)]
+enum Token {
+    Ident(String),
+    Int(isize),
+    Real(f64),
+    Str(String),
+    Sym(&'static str),
+    Eof,
+}
+
+const SYMBOLS: [&str; 19] = ["->", "==", "**", ";", ",", "(", ")", "[", "]", "{", "}",
+                             "+", "-", "*", "/", "^", "@", ":", "="];
+
+/// A parse error at the given position
+fn err_at<T>(line: usize, col: usize, msg: impl Into<String>) -> Result<T, ReadError> {
+    Err(ReadError::parse(msg).at(line, col))
+}
+
+/// Split the source into tokens, each with a line and column number
+fn tokenize(source: &str) -> Result<Vec<(Token,usize,usize)>, ReadError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut toks = Vec::new();
+    let (mut i, mut line, mut col) = (0, 1, 1);
+
+    // move forward n characters, keeping track of the position
+    let advance = |i: &mut usize, line: &mut usize, col: &mut usize, n: usize| {
+        for _ in 0..n {
+            if chars[*i] == '\n' { *line += 1; *col = 1; } else { *col += 1; }
+            *i += 1;
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..(i+2).min(chars.len())].iter().collect();
+        let (l0, c0) = (line, col);
+
+        if c.is_whitespace() {
+            advance(&mut i, &mut line, &mut col, 1);
+        } else if rest == "//" {
+            while i < chars.len() && chars[i] != '\n' { advance(&mut i, &mut line, &mut col, 1); }
+        } else if rest == "/*" {
+            advance(&mut i, &mut line, &mut col, 2);
+            loop {
+                if i + 1 >= chars.len() { return err_at(l0, c0, "Unterminated comment"); }
+                if chars[i] == '*' && chars[i+1] == '/' { break; }
+                advance(&mut i, &mut line, &mut col, 1);
+            }
+            advance(&mut i, &mut line, &mut col, 2);
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                advance(&mut i, &mut line, &mut col, 1);
+            }
+            toks.push((Token::Ident(chars[start..i].iter().collect()), l0, c0));
+        } else if c.is_ascii_digit() || (c == '.' && i + 1 < chars.len() && chars[i+1].is_ascii_digit()) {
+            let start = i;
+            let mut real = false;
+            while i < chars.len() && chars[i].is_ascii_digit() { advance(&mut i, &mut line, &mut col, 1); }
+            if i < chars.len() && chars[i] == '.' {
+                real = true;
+                advance(&mut i, &mut line, &mut col, 1);
+                while i < chars.len() && chars[i].is_ascii_digit() { advance(&mut i, &mut line, &mut col, 1); }
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') { j += 1; }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    real = true;
+                    let n = j - i;
+                    advance(&mut i, &mut line, &mut col, n);
+                    while i < chars.len() && chars[i].is_ascii_digit() { advance(&mut i, &mut line, &mut col, 1); }
+                }
+            }
+
+            let s: String = chars[start..i].iter().collect();
+            let tok = if real {
+                s.parse::<f64>().map(Token::Real).map_err(|e| e.to_string())
+            } else {
+                s.parse::<isize>().map(Token::Int).map_err(|e| e.to_string())
+            };
+            match tok {
+                Ok(t) => toks.push((t, l0, c0)),
+                Err(e) => return err_at(l0, c0, format!("Bad number '{}': {}", s, e)),
+            }
+        } else if c == '"' {
+            advance(&mut i, &mut line, &mut col, 1);
+            let start = i;
+            while i < chars.len() && chars[i] != '"' { advance(&mut i, &mut line, &mut col, 1); }
+            if i == chars.len() { return err_at(l0, c0, "Unterminated string"); }
+            toks.push((Token::Str(chars[start..i].iter().collect()), l0, c0));
+            advance(&mut i, &mut line, &mut col, 1);
+        } else if let Some(&sym) = SYMBOLS.iter().find(|&&s| rest.starts_with(s)) {
+            advance(&mut i, &mut line, &mut col, sym.len());
+            toks.push((Token::Sym(sym), l0, c0));
+        } else {
+            return err_at(l0, c0, format!("Unexpected character '{}'", c));
+        }
+    }
+
+    toks.push((Token::Eof, line, col));
+    Ok(toks)
+}
+
+/// The value of a parameter expression
+///
+/// Values are kept exact for as long as possible, as rational numbers or rational
+/// multiples of pi. Anything else falls back to floating point.
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Value {
+    Pi(Rational),
+    Num(Rational),
+    Float(f64),
+}
+
+use Value::*;
+
+impl Value {
+    fn to_f64(self) -> f64 {
+        match self {
+            Pi(r) => r.to_f64().unwrap() * PI,
+            Num(r) => r.to_f64().unwrap(),
+            Float(f) => f,
+        }
+    }
+
+    fn is_zero(self) -> bool {
+        match self {
+            Pi(r) | Num(r) => r.is_zero(),
+            Float(f) => f == 0.0,
+        }
+    }
+
+    /// Return the value as a phase, i.e. a multiple of pi
+    fn to_phase(self) -> Rational {
+        match self {
+            Pi(r) => r,
+            _ => approximate_phase(self.to_f64() / PI),
+        }
+    }
+
+    fn neg(self) -> Value {
+        match self {
+            Pi(r) => Pi(-r),
+            Num(r) => Num(-r),
+            Float(f) => Float(-f),
+        }
+    }
+
+    fn add(self, other: Value) -> Value {
+        let exact = match (self, other) {
+            (Pi(x), Pi(y)) => x.checked_add(&y).map(Pi),
+            (Num(x), Num(y)) => x.checked_add(&y).map(Num),
+            (x, y) if x.is_zero() => Some(y),
+            (x, y) if y.is_zero() => Some(x),
+            _ => None,
+        };
+        exact.unwrap_or_else(|| Float(self.to_f64() + other.to_f64()))
+    }
+
+    fn sub(self, other: Value) -> Value {
+        self.add(other.neg())
+    }
+
+    fn mul(self, other: Value) -> Value {
+        let exact = match (self, other) {
+            (Pi(x), Num(y)) | (Num(y), Pi(x)) => x.checked_mul(&y).map(Pi),
+            (Num(x), Num(y)) => x.checked_mul(&y).map(Num),
+            _ => None,
+        };
+        exact.unwrap_or_else(|| Float(self.to_f64() * other.to_f64()))
+    }
+
+    fn div(self, other: Value) -> Option<Value> {
+        if other.is_zero() { return None; }
+        let exact = match (self, other) {
+            (Pi(x), Num(y)) => x.checked_div(&y).map(Pi),
+            (Num(x), Num(y)) | (Pi(x), Pi(y)) => x.checked_div(&y).map(Num),
+            _ => None,
+        };
+        Some(exact.unwrap_or_else(|| Float(self.to_f64() / other.to_f64())))
+    }
+
+    fn pow(self, other: Value) -> Value {
+        if let (Num(x), Num(y)) = (self, other) {
+            if y.is_integer() && y.abs() <= Rational::from_integer(64) {
+                let mut r = Some(Rational::one());
+                for _ in 0..y.numer().abs() { r = r.and_then(|r| r.checked_mul(&x)); }
+                if *y.numer() < 0 { r = r.and_then(|r| Rational::one().checked_div(&r)); }
+                if let Some(r) = r { return Num(r); }
+            }
+        }
+        Float(self.to_f64().powf(other.to_f64()))
+    }
+}
+
+/// Turn a floating-point multiple of pi into a rational number
+///
+/// This finds the fraction with the smallest denominator that is close enough to `f`,
+/// so parameters like 0.3333333333 or 1.5707963267949 come out as expected.
+pub fn approximate_phase(f: f64) -> Rational {
+    let eps = 1e-8;
+    let (mut h0, mut h1) = (0.0, 1.0);
+    let (mut k0, mut k1) = (1.0, 0.0);
+    let mut x = f;
+    for _ in 0..64 {
+        let a = x.floor();
+        let (h2, k2) = (a * h1 + h0, a * k1 + k0);
+        if k2 > 1e12 { break; }
+        if (h2 / k2 - f).abs() < eps {
+            return Rational::new(h2 as isize, k2 as isize);
+        }
+        h0 = h1; h1 = h2; k0 = k1; k1 = k2;
+        if (x - a).abs() < f64::EPSILON { break; }
+        x = 1.0 / (x - a);
+    }
+    Rational::approximate_float(f).unwrap_or_else(Rational::zero)
+}
+
+/// A parameter expression in a gate body, or a gate application
+#[derive(Debug,Clone)]
+enum Expr {
+    Val(Value),
+    Param(usize),
+    Neg(Box<Expr>),
+    Bin(&'static str, Box<Expr>, Box<Expr>),
+    Func(String, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, env: &[Value]) -> Result<Value, ReadError> {
+        Ok(match self {
+            Expr::Val(v) => *v,
+            Expr::Param(i) => env[*i],
+            Expr::Neg(e) => e.eval(env)?.neg(),
+            Expr::Bin(op, e1, e2) => {
+                let (v1, v2) = (e1.eval(env)?, e2.eval(env)?);
+                match *op {
+                    "+" => v1.add(v2),
+                    "-" => v1.sub(v2),
+                    "*" => v1.mul(v2),
+                    "/" => v1.div(v2).ok_or_else(|| ReadError::parse("Division by zero"))?,
+                    _ => v1.pow(v2),
+                }
+            },
+            Expr::Func(f, e) => {
+                let x = e.eval(env)?.to_f64();
+                Float(match f.as_str() {
+                    "sin" => x.sin(),
+                    "cos" => x.cos(),
+                    "tan" => x.tan(),
+                    "exp" => x.exp(),
+                    "ln" => x.ln(),
+                    _ => x.sqrt(),
+                })
+            },
+        })
+    }
+}
+
+/// A gate modifier, as in `ctrl(2) @ x a, b, c;`
+#[derive(Debug,Clone)]
+enum Modifier {
+    /// Add the given number of controls, which are negated if the flag is set
+    Ctrl(usize, bool),
+    Inv,
+    Pow(Expr),
+}
+
+/// A gate application inside the body of a gate definition
+#[derive(Debug,Clone)]
+struct GateCall {
+    mods: Vec<Modifier>,
+    name: String,
+    params: Vec<Expr>,
+    args: Vec<usize>,
+}
+
+/// A user-defined gate, or one from [QELIB1] or [STDGATES]
+#[derive(Debug,Clone)]
+struct GateDef {
+    num_params: usize,
+    num_args: usize,
+    body: Vec<GateCall>,
+    opaque: bool,
+    builtin: bool,
+}
+
+/// Gates that translate directly to a [GType], with their number of parameters
+/// and qubits. `U` and `gphase` are handled separately.
+fn native_gate(name: &str) -> Option<(GType, usize, Option<usize>)> {
+    let t = match name {
+        "u1" | "p" | "phase" => ZPhase,
+        _ => GType::from_qasm_name(name),
+    };
+    match t {
+        UnknownGate => None,
+        ZPhase | XPhase => Some((t, 1, Some(1))),
+        ParityPhase => Some((t, 1, None)),
+        _ => Some((t, 0, t.num_qubits())),
+    }
+}
+
+/// Emit a Z or X rotation, unless it is trivial
+fn push_rotation(gates: &mut Vec<Gate>, t: GType, q: usize, phase: Rational) {
+    let phase = phase.mod2();
+    if !phase.is_zero() { gates.push(Gate::new_with_phase(t, vec![q], phase)); }
+}
+
+/// Expand a gate application into gates from [GType]
+///
+/// The global phase of the expansion, relative to the gate as defined by OpenQASM, is
+/// added to `phase`. This is only used to build controlled gates.
+fn expand(defs: &FxHashMap<String,GateDef>, gates: &mut Vec<Gate>, phase: &mut Rational,
+          name: &str, params: &[Value], qs: &[usize]) -> Result<(), ReadError>
+{
+    if let Some(def) = defs.get(name) {
+        if def.opaque { return Err(ReadError::unsupported_gate(name, "is opaque, so cannot be applied")); }
+        for call in &def.body {
+            let ps = call.params.iter().map(|e| e.eval(params)).collect::<Result<Vec<_>,_>>()?;
+            let qs1: Vec<usize> = call.args.iter().map(|&i| qs[i]).collect();
+            expand_modified(defs, gates, phase, &call.mods, params, &call.name, &ps, &qs1)?;
+        }
+    } else if name == "U" {
+        // U(theta,phi,lambda) = e^(i(phi+lambda)/2) Rz(phi) Ry(theta) Rz(lambda), and
+        // Ry(theta) = S Rx(theta) S^dag. Our rotations are diag(1, e^(i alpha)) rather than
+        // Rz(alpha), which leaves a global phase of -theta/2.
+        let (theta, phi, lambda) = (params[0].to_phase(), params[1].to_phase(), params[2].to_phase());
+        if theta.mod2().is_zero() {
+            push_rotation(gates, ZPhase, qs[0], lambda + phi);
+        } else {
+            let half = Rational::new(1,2);
+            push_rotation(gates, ZPhase, qs[0], lambda - half);
+            push_rotation(gates, XPhase, qs[0], theta);
+            push_rotation(gates, ZPhase, qs[0], phi + half);
+        }
+        *phase -= theta / 2;
+    } else if name == "gphase" {
+        *phase += params[0].to_phase();
+    } else if let Some((t, _, _)) = native_gate(name) {
+        if let Some(j) = (1..qs.len()).find(|&j| qs[..j].contains(&qs[j])) {
+            return Err(ReadError::invalid_qubit(qs[j], format!("Gate '{}' uses a qubit more than once", name)));
+        }
+        let p = params.first().map_or(Rational::zero(), |p| p.to_phase());
+        // rz and rx are rotations exp(-i theta P/2), rather than diag(1, e^(i theta))
+        if name == "rz" || name == "rx" { *phase -= p / 2; }
+        gates.push(Gate::new_with_phase(t, qs.to_vec(), p.mod2()));
+    } else {
+        return Err(ReadError::unknown_gate(name));
+    }
+    Ok(())
+}
+
+/// Expand a gate application with modifiers, where `env` holds the parameters of the
+/// enclosing gate definition, if any
+#[allow(clippy::too_many_arguments)]
+fn expand_modified(defs: &FxHashMap<String,GateDef>, gates: &mut Vec<Gate>, phase: &mut Rational,
+                   mods: &[Modifier], env: &[Value],
+                   name: &str, params: &[Value], qs: &[usize]) -> Result<(), ReadError>
+{
+    let (m, rest) = match mods.split_first() {
+        Some(x) => x,
+        None => return expand(defs, gates, phase, name, params, qs),
+    };
+
+    let mut inner = Vec::new();
+    let mut inner_phase = Rational::zero();
+    match m {
+        Modifier::Ctrl(n, neg) => {
+            let (cs, ts) = qs.split_at(*n);
+            expand_modified(defs, &mut inner, &mut inner_phase, rest, env, name, params, ts)?;
+            for &c in cs.iter().rev() {
+                inner = control(c, &inner, inner_phase)?;
+                inner_phase = Rational::zero();
+            }
+            if *neg { gates.extend(cs.iter().map(|&c| Gate::new(NOT, vec![c]))); }
+            gates.append(&mut inner);
+            if *neg { gates.extend(cs.iter().map(|&c| Gate::new(NOT, vec![c]))); }
+        },
+        Modifier::Inv => {
+            expand_modified(defs, &mut inner, &mut inner_phase, rest, env, name, params, qs)?;
+            push_inverse(gates, &inner)?;
+            *phase -= inner_phase;
+        },
+        Modifier::Pow(e) => {
+            let k = e.eval(env)?;
+            expand_modified(defs, &mut inner, &mut inner_phase, rest, env, name, params, qs)?;
+            let too_big = || ReadError::unsupported_gate(name, "cannot be raised to such a large power");
+            let k = match k { Num(k) => k, _ => approximate_phase(k.to_f64()) };
+            if inner.is_empty() {
+                // nothing to repeat, so only the global phase changes
+            } else if let (1, Some((t, p))) = (inner.len(), rotation(&inner[0])) {
+                // a single rotation can be raised to any power by scaling its angle,
+                // which for a non-integer power takes the principal branch
+                let p = p.checked_mul(&k).ok_or_else(too_big)?.mod2();
+                if !p.is_zero() { gates.push(Gate::new_with_phase(t, inner[0].qs.clone(), p)); }
+            } else if k.is_integer() {
+                if k.numer().abs() > MAX_POWER { return Err(too_big()); }
+                for _ in 0..k.numer().abs() {
+                    if *k.numer() > 0 { gates.extend(inner.iter().cloned()); }
+                    else { push_inverse(gates, &inner)?; }
+                }
+            } else {
+                return Err(ReadError::unsupported_gate(name, "cannot be raised to a non-integer power"));
+            }
+            *phase = inner_phase.checked_mul(&k)
+                .and_then(|p| phase.checked_add(&p))
+                .ok_or_else(too_big)?;
+        },
+    }
+    Ok(())
+}
+
+/// Push the inverse of a sequence of gates
+fn push_inverse(gates: &mut Vec<Gate>, inner: &[Gate]) -> Result<(), ReadError> {
+    for g in inner.iter().rev() {
+        if let InitAncilla | PostSelect = g.t {
+            return Err(ReadError::unsupported_gate(g.qasm_name(), "cannot be inverted"));
+        }
+        let mut g = g.clone();
+        g.adjoint();
+        gates.push(g);
+    }
+    Ok(())
+}
+
+/// The largest power of a sequence of gates which is expanded by repeating it
+const MAX_POWER: isize = 1024;
+
+/// A gate as a Z, X or parity rotation, with its phase, if it is one
+fn rotation(g: &Gate) -> Option<(GType, Rational)> {
+    match g.t {
+        ZPhase | XPhase | ParityPhase => Some((g.t, g.phase)),
+        NOT => Some((XPhase, Rational::one())),
+        _ => z_phase(g.t).map(|p| (ZPhase, p)),
+    }
+}
+
+/// The phase of a gate which is a Z rotation
+fn z_phase(t: GType) -> Option<Rational> {
+    match t {
+        Z => Some(Rational::one()),
+        S => Some(Rational::new(1,2)),
+        T => Some(Rational::new(1,4)),
+        Sdg => Some(Rational::new(-1,2)),
+        Tdg => Some(Rational::new(-1,4)),
+        _ => None,
+    }
+}
+
+/// Push a controlled phase gate diag(1, 1, 1, e^(i pi phase))
+fn push_cphase(gates: &mut Vec<Gate>, c: usize, t: usize, phase: Rational) {
+    let phase = phase.mod2();
+    if phase == Rational::one() {
+        gates.push(Gate::new(CZ, vec![c, t]));
+    } else if !phase.is_zero() {
+        push_rotation(gates, ZPhase, c, phase / 2);
+        gates.push(Gate::new(CNOT, vec![c, t]));
+        push_rotation(gates, ZPhase, t, -phase / 2);
+        gates.push(Gate::new(CNOT, vec![c, t]));
+        push_rotation(gates, ZPhase, t, phase / 2);
+    }
+}
+
+/// Control a sequence of gates, which implements a unitary up to the given global phase,
+/// on the qubit `c`
+///
+/// The result is exact, including the global phase. This fails if some gate has no
+/// controlled version made up of gates from [GType].
+fn control(c: usize, gates: &[Gate], phase: Rational) -> Result<Vec<Gate>, ReadError> {
+    let mut out = Vec::new();
+    for g in gates {
+        if g.qs.contains(&c) {
+            return Err(ReadError::invalid_qubit(c, "Control qubit is also used as a target"));
+        }
+        let qs = &g.qs;
+        match g.t {
+            NOT => out.push(Gate::new(CNOT, vec![c, qs[0]])),
+            Z => out.push(Gate::new(CZ, vec![c, qs[0]])),
+            CNOT => out.push(Gate::new(TOFF, vec![c, qs[0], qs[1]])),
+            CZ => out.push(Gate::new(CCZ, vec![c, qs[0], qs[1]])),
+            ZPhase => push_cphase(&mut out, c, qs[0], g.phase),
+            S | T | Sdg | Tdg => push_cphase(&mut out, c, qs[0], z_phase(g.t).unwrap()),
+            XPhase => {
+                out.push(Gate::new(HAD, vec![qs[0]]));
+                push_cphase(&mut out, c, qs[0], g.phase);
+                out.push(Gate::new(HAD, vec![qs[0]]));
+            },
+            HAD => {
+                // H = Ry(pi/4) Z Ry(-pi/4)
+                let half = Rational::new(1,2);
+                out.push(Gate::new(Sdg, vec![qs[0]]));
+                out.push(Gate::new_with_phase(XPhase, vec![qs[0]], -half / 2));
+                out.push(Gate::new(S, vec![qs[0]]));
+                out.push(Gate::new(CZ, vec![c, qs[0]]));
+                out.push(Gate::new(Sdg, vec![qs[0]]));
+                out.push(Gate::new_with_phase(XPhase, vec![qs[0]], half / 2));
+                out.push(Gate::new(S, vec![qs[0]]));
+            },
+            SWAP => {
+                out.push(Gate::new(CNOT, vec![qs[1], qs[0]]));
+                out.push(Gate::new(TOFF, vec![c, qs[0], qs[1]]));
+                out.push(Gate::new(CNOT, vec![qs[1], qs[0]]));
+            },
+            XCX => {
+                out.push(Gate::new(HAD, vec![qs[0]]));
+                out.push(Gate::new(HAD, vec![qs[1]]));
+                out.push(Gate::new(CCZ, vec![c, qs[0], qs[1]]));
+                out.push(Gate::new(HAD, vec![qs[0]]));
+                out.push(Gate::new(HAD, vec![qs[1]]));
+            },
+            ParityPhase => {
+                if let Some((&t, rest)) = qs.split_last() {
+                    for &q in rest { out.push(Gate::new(CNOT, vec![q, t])); }
+                    push_cphase(&mut out, c, t, g.phase);
+                    for &q in rest.iter().rev() { out.push(Gate::new(CNOT, vec![q, t])); }
+                }
+            },
+            _ => return Err(ReadError::unsupported_gate(g.qasm_name(), "cannot be controlled")),
+        }
+    }
+    push_rotation(&mut out, ZPhase, c, phase);
+    Ok(out)
+}
+
+/// A reference to a register, or a single bit of one
+#[derive(Debug,Clone,Copy)]
+enum Arg {
+    Reg(usize, usize),
+    Bit(usize),
+}
+
+struct Parser {
+    toks: Vec<(Token,usize,usize)>,
+    pos: usize,
+    // name, whether it is quantum, offset and size
+    regs: Vec<(String,bool,usize,usize)>,
+    num_qubits: usize,
+    num_bits: usize,
+    defs: FxHashMap<String,GateDef>,
+    // values of classical constants, for OpenQASM 3
+    consts: FxHashMap<String,Value>,
+    gates: Vec<Gate>,
+    qasm3: bool,
+}
+
+impl Parser {
+    fn new(qasm3: bool) -> Parser {
+        let mut p = Parser {
+            toks: Vec::new(), pos: 0, regs: Vec::new(),
+            num_qubits: 0, num_bits: 0,
+            defs: FxHashMap::default(), consts: FxHashMap::default(),
+            gates: Vec::new(), qasm3,
+        };
+        p.toks = tokenize(if qasm3 { STDGATES } else { QELIB1 }).expect("gate library should tokenize");
+        while p.peek() != &Token::Eof {
+            p.parse_gate_def(false, true).expect("gate library should parse");
+        }
+        p
+    }
+
+    fn peek(&self) -> &Token { &self.toks[self.pos].0 }
+
+    fn here<T>(&self, msg: impl Into<String>) -> Result<T, ReadError> {
+        self.fail(ReadError::parse(msg))
+    }
+
+    /// Return the error, at the current position unless it already has one
+    fn fail<T>(&self, e: ReadError) -> Result<T, ReadError> {
+        let (_, line, col) = self.toks[self.pos];
+        Err(e.at(line, col))
+    }
+
+    fn next(&mut self) -> Token {
+        let t = self.toks[self.pos].0.clone();
+        if t != Token::Eof { self.pos += 1; }
+        t
+    }
+
+    fn accept(&mut self, sym: &str) -> bool {
+        if let Token::Sym(s) = self.peek() {
+            if *s == sym { self.pos += 1; return true; }
+        }
+        false
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), ReadError> {
+        if self.accept(sym) { Ok(()) }
+        else { self.here(format!("Expected '{}', got {}", sym, self.describe())) }
+    }
+
+    fn describe(&self) -> String {
+        match self.peek() {
+            Token::Ident(s) => format!("'{}'", s),
+            Token::Int(i) => format!("'{}'", i),
+            Token::Real(f) => format!("'{}'", f),
+            Token::Str(s) => format!("\"{}\"", s),
+            Token::Sym(s) => format!("'{}'", s),
+            Token::Eof => String::from("end of input"),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, ReadError> {
+        match self.peek().clone() {
+            Token::Ident(s) => { self.pos += 1; Ok(s) },
+            _ => self.here(format!("Expected identifier, got {}", self.describe())),
+        }
+    }
+
+    fn int(&mut self) -> Result<usize, ReadError> {
+        match *self.peek() {
+            Token::Int(i) if i >= 0 => { self.pos += 1; Ok(i as usize) },
+            _ => self.here(format!("Expected non-negative integer, got {}", self.describe())),
+        }
+    }
+
+    fn ident_list(&mut self) -> Result<Vec<String>, ReadError> {
+        let mut ids = vec![self.ident()?];
+        while self.accept(",") { ids.push(self.ident()?); }
+        Ok(ids)
+    }
+
+    fn parse_program(&mut self) -> Result<(), ReadError> {
+        if self.peek() == &Token::Ident(String::from("OPENQASM")) {
+            self.next();
+            match self.next() {
+                Token::Real(2.0) | Token::Int(2) | Token::Real(3.0) | Token::Int(3) => {},
+                _ => { self.pos -= 1; return self.fail(ReadError::unsupported("Only OpenQASM 2.0 and 3 are supported")); },
+            }
+            self.expect(";")?;
+        }
+
+        while self.peek() != &Token::Eof {
+            self.parse_statement()?;
+        }
+        Ok(())
+    }
+
+    fn parse_statement(&mut self) -> Result<(), ReadError> {
+        let start = self.pos;
+        let kw = self.ident()?;
+        match kw.as_str() {
+            "include" => {
+                let lib = if self.qasm3 { "stdgates.inc" } else { "qelib1.inc" };
+                match self.next() {
+                    Token::Str(s) if s == lib => {},
+                    Token::Str(s) => {
+                        self.pos -= 1;
+                        return self.fail(ReadError::unsupported(format!("Cannot include file \"{}\"", s)));
+                    },
+                    _ => { self.pos -= 1; return self.here("Expected file name"); },
+                }
+                self.expect(";")
+            },
+            "qreg" | "creg" | "qubit" | "bit" if self.qasm3 || kw == "qreg" || kw == "creg" => {
+                // OpenQASM 3 declares registers as `qubit[n] q;` or `qubit q;`
+                let new_style = kw == "qubit" || kw == "bit";
+                let mut sz = 1;
+                if new_style && self.accept("[") {
+                    sz = self.int()?;
+                    self.expect("]")?;
+                }
+                let name_pos = self.pos;
+                let name = self.ident()?;
+                if !new_style {
+                    self.expect("[")?;
+                    sz = self.int()?;
+                    self.expect("]")?;
+                }
+                self.expect(";")?;
+                if self.regs.iter().any(|r| r.0 == name) {
+                    self.pos = name_pos;
+                    return self.here(format!("Re-declaration of register: {}", name));
+                }
+                if kw == "qreg" || kw == "qubit" {
+                    self.regs.push((name, true, self.num_qubits, sz));
+                    self.num_qubits += sz;
+                } else {
+                    self.regs.push((name, false, self.num_bits, sz));
+                    self.num_bits += sz;
+                }
+                Ok(())
+            },
+            "gate" => self.parse_gate_def(false, false),
+            "opaque" => self.parse_gate_def(true, false),
+            "const" | "angle" | "float" | "int" | "uint" if self.qasm3 => {
+                self.pos = start;
+                self.parse_classical_decl()
+            },
+            "measure" => {
+                let q = self.parse_arg(true)?;
+                // OpenQASM 3 allows measuring without storing the result
+                if self.qasm3 && self.accept(";") { return Ok(()); }
+                self.expect("->")?;
+                let b = self.parse_arg(false)?;
+                if let (Arg::Reg(_, n), Arg::Reg(_, m)) = (q, b) {
+                    if n != m {
+                        self.pos = start;
+                        return self.here("Registers in measurement have different sizes");
+                    }
+                } else if let (Arg::Bit(_), Arg::Reg(..)) | (Arg::Reg(..), Arg::Bit(_)) = (q, b) {
+                    self.pos = start;
+                    return self.here("Cannot measure a register into a single bit, or vice-versa");
+                }
+                self.expect(";")
+            },
+            "barrier" => {
+                self.parse_arg(true)?;
+                while self.accept(",") { self.parse_arg(true)?; }
+                self.expect(";")
+            },
+            "reset" => { self.pos = start; self.fail(ReadError::unsupported("Reset is not supported")) },
+            "if" => {
+                self.pos = start;
+                self.fail(ReadError::unsupported("Classically controlled gates are not supported"))
+            },
+            "for" | "while" | "def" | "input" | "output" | "box" | "let" | "delay" | "extern" | "switch"
+                | "return" | "defcal" | "cal" | "defcalgrammar" if self.qasm3 =>
+            {
+                self.pos = start;
+                self.fail(ReadError::unsupported(format!("'{}' statements are not supported", kw)))
+            },
+            _ if self.qasm3 && self.regs.iter().any(|r| r.0 == kw && !r.1) => {
+                // c = measure q; or c[i] = measure q[i];
+                self.pos = start;
+                self.parse_arg(false)?;
+                self.expect("=")?;
+                match self.next() {
+                    Token::Ident(s) if s == "measure" => {},
+                    _ => { self.pos -= 1; return self.fail(ReadError::unsupported("Only measurements can be assigned to bits")); },
+                }
+                self.parse_arg(true)?;
+                self.expect(";")
+            },
+            _ => { self.pos = start; self.parse_application() },
+        }
+    }
+
+    /// Parse an OpenQASM 3 classical declaration, e.g. `const angle[20] theta = π/4;`
+    ///
+    /// Only declarations with an initial value are useful, as there is nothing to assign
+    /// them later, so those values are just remembered for use in parameter expressions.
+    fn parse_classical_decl(&mut self) -> Result<(), ReadError> {
+        if self.peek() == &Token::Ident(String::from("const")) { self.next(); }
+        match self.ident()?.as_str() {
+            "angle" | "float" | "int" | "uint" => {},
+            _ => { self.pos -= 1; return self.fail(ReadError::unsupported(format!("Unsupported type {}", self.describe()))); },
+        }
+        if self.accept("[") {
+            self.int()?;
+            self.expect("]")?;
+        }
+        let name = self.ident()?;
+        if self.accept("=") {
+            let start = self.pos;
+            let e = self.parse_expr(&[])?;
+            let v = match e.eval(&[]) {
+                Ok(v) => v,
+                Err(e) => { self.pos = start; return self.fail(e); },
+            };
+            self.consts.insert(name, v);
+        }
+        self.expect(";")
+    }
+
+    /// Parse `gate name(params) args { body }`, or `opaque name(params) args;`
+    fn parse_gate_def(&mut self, opaque: bool, builtin: bool) -> Result<(), ReadError> {
+        if builtin { self.ident()?; }
+        let name_pos = self.pos;
+        let name = self.ident()?;
+        let params = if self.accept("(") {
+            if self.accept(")") { Vec::new() }
+            else {
+                let ps = self.ident_list()?;
+                self.expect(")")?;
+                ps
+            }
+        } else { Vec::new() };
+        let args = self.ident_list()?;
+
+        let mut body = Vec::new();
+        if opaque {
+            self.expect(";")?;
+        } else {
+            self.expect("{")?;
+            while !self.accept("}") {
+                let call_pos = self.pos;
+                let mods = self.parse_modifiers(&params)?;
+                let name_pos = self.pos;
+                let gname = self.ident()?;
+                if gname == name {
+                    self.pos = name_pos;
+                    return self.here(format!("Gate '{}' cannot be used in its own definition", name));
+                }
+                if gname == "barrier" {
+                    self.ident_list()?;
+                    self.expect(";")?;
+                    continue;
+                }
+
+                let ps = self.parse_params(&params)?;
+
+                let arg_pos = self.pos;
+                let mut gargs = Vec::new();
+                let names = if self.peek() == &Token::Sym(";") { Vec::new() } else { self.ident_list()? };
+                for a in names {
+                    match args.iter().position(|a1| a1 == &a) {
+                        Some(i) => gargs.push(i),
+                        None => {
+                            self.pos = arg_pos;
+                            return self.fail(ReadError::invalid_qubit(a, "Unknown qubit argument"));
+                        },
+                    }
+                }
+                self.expect(";")?;
+
+                let end = self.pos;
+                self.pos = call_pos;
+                self.check_arity(&mods, &gname, ps.len(), gargs.len())?;
+                self.pos = end;
+                body.push(GateCall { mods, name: gname, params: ps, args: gargs });
+            }
+        }
+
+        if let Some(d) = self.defs.get(&name) {
+            if !d.builtin {
+                self.pos = name_pos;
+                return self.here(format!("Re-definition of gate: {}", name));
+            }
+        }
+        self.defs.insert(name, GateDef {
+            num_params: params.len(), num_args: args.len(), body, opaque, builtin
+        });
+        Ok(())
+    }
+
+    /// Check the gate exists and takes the given number of parameters and qubits, once
+    /// any controls added by modifiers are taken off
+    fn check_arity(&self, mods: &[Modifier], name: &str, num_params: usize, num_args: usize) -> Result<(), ReadError> {
+        let (np, nq) = if let Some(d) = self.defs.get(name) { (d.num_params, Some(d.num_args)) }
+            else if name == "U" { (3, Some(1)) }
+            else if name == "gphase" && self.qasm3 { (1, Some(0)) }
+            else if let Some((_, np, nq)) = native_gate(name) { (np, nq) }
+            else { return self.fail(ReadError::unknown_gate(name)); };
+
+        let num_ctrls: usize = mods.iter().map(|m| match m { Modifier::Ctrl(n, _) => *n, _ => 0 }).sum();
+        if np != num_params {
+            let (_, line, column) = self.toks[self.pos];
+            Err(ReadError::WrongParameters { line, column, name: name.to_string(), expected: np, found: num_params })
+        } else if num_args < num_ctrls || nq.map_or(num_args == num_ctrls, |nq| nq + num_ctrls != num_args) {
+            self.fail(ReadError::wrong_qubits(name, num_args))
+        } else { Ok(()) }
+    }
+
+    /// Parse a quantum or classical argument, `reg` or `reg[i]`
+    fn parse_arg(&mut self, quantum: bool) -> Result<Arg, ReadError> {
+        let start = self.pos;
+        let name = self.ident()?;
+        let (offset, sz) = match self.regs.iter().find(|r| r.0 == name) {
+            Some(r) if r.1 == quantum => (r.2, r.3),
+            Some(_) => {
+                self.pos = start;
+                let reason = format!("Expected a {} register", if quantum { "quantum" } else { "classical" });
+                return self.fail(ReadError::invalid_qubit(name, reason));
+            },
+            None => { self.pos = start; return self.fail(ReadError::invalid_qubit(name, "Undeclared register")); },
+        };
+
+        if self.accept("[") {
+            let ipos = self.pos;
+            let i = self.int()?;
+            if i >= sz {
+                self.pos = ipos;
+                return self.fail(ReadError::invalid_qubit(format!("{}[{}]", name, i), "Index out of bounds"));
+            }
+            self.expect("]")?;
+            Ok(Arg::Bit(offset + i))
+        } else {
+            Ok(Arg::Reg(offset, sz))
+        }
+    }
+
+    /// The name of a qubit in the source, e.g. `q[2]`
+    fn qubit_name(&self, q: usize) -> String {
+        match self.regs.iter().find(|r| r.1 && r.2 <= q && q < r.2 + r.3) {
+            Some(r) => format!("{}[{}]", r.0, q - r.2),
+            None => q.to_string(),
+        }
+    }
+
+    /// Parse any gate modifiers in front of a gate name, e.g. `inv @ ctrl(2) @`
+    fn parse_modifiers(&mut self, params: &[String]) -> Result<Vec<Modifier>, ReadError> {
+        let mut mods = Vec::new();
+        if !self.qasm3 { return Ok(mods); }
+        while let Token::Ident(kw) = self.peek().clone() {
+            let m = match kw.as_str() {
+                "inv" => { self.next(); Modifier::Inv },
+                "pow" => {
+                    self.next();
+                    self.expect("(")?;
+                    let e = self.parse_expr(params)?;
+                    self.expect(")")?;
+                    Modifier::Pow(e)
+                },
+                "ctrl" | "negctrl" => {
+                    self.next();
+                    let mut n = 1;
+                    if self.accept("(") {
+                        n = self.int()?;
+                        self.expect(")")?;
+                    }
+                    Modifier::Ctrl(n, kw == "negctrl")
+                },
+                _ => break,
+            };
+            self.expect("@")?;
+            mods.push(m);
+        }
+        Ok(mods)
+    }
+
+    /// Parse an optional list of parameters in parentheses
+    fn parse_params(&mut self, params: &[String]) -> Result<Vec<Expr>, ReadError> {
+        let mut ps = Vec::new();
+        if self.accept("(") && !self.accept(")") {
+            ps.push(self.parse_expr(params)?);
+            while self.accept(",") { ps.push(self.parse_expr(params)?); }
+            self.expect(")")?;
+        }
+        Ok(ps)
+    }
+
+    /// Parse a gate application at the top level, applying it to each qubit of any
+    /// whole registers in the arguments
+    fn parse_application(&mut self) -> Result<(), ReadError> {
+        let start = self.pos;
+        let mods = self.parse_modifiers(&[])?;
+        let name = self.ident()?;
+        let params = self.parse_params(&[])?;
+
+        let mut args = Vec::new();
+        let mut arg_pos = Vec::new();
+        if self.peek() != &Token::Sym(";") {
+            arg_pos.push(self.pos);
+            args.push(self.parse_arg(true)?);
+            while self.accept(",") {
+                arg_pos.push(self.pos);
+                args.push(self.parse_arg(true)?);
+            }
+        }
+        let end = self.pos;
+        self.expect(";")?;
+
+        self.pos = start;
+        let params = match params.iter().map(|e| e.eval(&[])).collect::<Result<Vec<_>,_>>() {
+            Ok(ps) => ps,
+            Err(e) => return self.fail(e),
+        };
+        self.check_arity(&mods, &name, params.len(), args.len())?;
+
+        let mut size = None;
+        for a in &args {
+            if let Arg::Reg(_, sz) = *a {
+                if size.is_some_and(|s| s != sz) {
+                    return self.here("Registers in gate application have different sizes");
+                }
+                size = Some(sz);
+            }
+        }
+
+        for i in 0..size.unwrap_or(1) {
+            let qs: Vec<usize> = args.iter().map(|a| match *a {
+                Arg::Reg(offset, _) => offset + i,
+                Arg::Bit(q) => q,
+            }).collect();
+            if let Some(j) = (1..qs.len()).find(|&j| qs[..j].contains(&qs[j])) {
+                self.pos = arg_pos[j];
+                let q = self.qubit_name(qs[j]);
+                return self.fail(ReadError::invalid_qubit(q, "Qubit used more than once in a gate"));
+            }
+            // the global phase of a whole circuit is not recorded
+            let mut phase = Rational::zero();
+            if let Err(e) = expand_modified(&self.defs, &mut self.gates, &mut phase, &mods, &[],
+                                            &name, &params, &qs) {
+                return self.fail(e);
+            }
+        }
+
+        self.pos = end + 1;
+        Ok(())
+    }
+
+    fn parse_expr(&mut self, params: &[String]) -> Result<Expr, ReadError> {
+        let mut e = self.parse_term(params)?;
+        loop {
+            let op = if self.accept("+") { "+" } else if self.accept("-") { "-" } else { break };
+            e = Expr::Bin(op, Box::new(e), Box::new(self.parse_term(params)?));
+        }
+        Ok(e)
+    }
+
+    fn parse_term(&mut self, params: &[String]) -> Result<Expr, ReadError> {
+        let mut e = self.parse_factor(params)?;
+        loop {
+            let op = if self.accept("*") { "*" } else if self.accept("/") { "/" } else { break };
+            e = Expr::Bin(op, Box::new(e), Box::new(self.parse_factor(params)?));
+        }
+        Ok(e)
+    }
+
+    fn parse_factor(&mut self, params: &[String]) -> Result<Expr, ReadError> {
+        if self.accept("-") {
+            Ok(Expr::Neg(Box::new(self.parse_factor(params)?)))
+        } else {
+            let e = self.parse_atom(params)?;
+            // OpenQASM 3 writes powers as a ** b
+            if self.accept("^") || self.accept("**") {
+                Ok(Expr::Bin("^", Box::new(e), Box::new(self.parse_factor(params)?)))
+            } else { Ok(e) }
+        }
+    }
+
+    fn parse_atom(&mut self, params: &[String]) -> Result<Expr, ReadError> {
+        let start = self.pos;
+        let e = match self.next() {
+            Token::Int(i) => Expr::Val(Num(Rational::from_integer(i))),
+            Token::Real(f) => Expr::Val(Float(f)),
+            Token::Sym("(") => {
+                let e = self.parse_expr(params)?;
+                self.expect(")")?;
+                e
+            },
+            Token::Ident(s) if s == "pi" => Expr::Val(Pi(Rational::one())),
+            Token::Ident(s) if params.contains(&s) => {
+                Expr::Param(params.iter().position(|p| p == &s).unwrap())
+            },
+            Token::Ident(s) if self.qasm3 && (s == "π" || s == "tau" || s == "τ") => {
+                Expr::Val(Pi(Rational::from_integer(if s == "π" { 1 } else { 2 })))
+            },
+            Token::Ident(s) if self.qasm3 && (s == "euler" || s == "ℇ") => Expr::Val(Float(std::f64::consts::E)),
+            Token::Ident(s) if self.consts.contains_key(&s) => Expr::Val(self.consts[&s]),
+            Token::Ident(s) if ["sin", "cos", "tan", "exp", "ln", "sqrt"].contains(&s.as_str()) => {
+                self.expect("(")?;
+                let e = self.parse_expr(params)?;
+                self.expect(")")?;
+                Expr::Func(s, Box::new(e))
+            },
+            Token::Ident(s) => { self.pos = start; return self.here(format!("Unknown parameter: {}", s)); },
+            _ => { self.pos = start; return self.here(format!("Expected expression, got {}", self.describe())); },
+        };
+
+        // for backwards compatibility, allow a number directly followed by pi, as in 2pi/3
+        if let (Expr::Val(_), Token::Ident(s)) = (&e, self.peek()) {
+            if s == "pi" || (self.qasm3 && s == "π") {
+                self.next();
+                return Ok(Expr::Bin("*", Box::new(e), Box::new(Expr::Val(Pi(Rational::one())))));
+            }
+        }
+        Ok(e)
+    }
+}
+
+/// Parse an OpenQASM 2.0 or 3 program into a [Circuit]
+///
+/// The version is taken from the `OPENQASM` header, defaulting to 2.0 if there is none.
+/// Errors record the line and column where the problem was found, see [ReadError].
+pub fn parse(source: &str) -> Result<Circuit, ReadError> {
+    let toks = tokenize(source)?;
+    let qasm3 = toks[0].0 == Token::Ident(String::from("OPENQASM")) &&
+        matches!(toks[1].0, Token::Real(3.0) | Token::Int(3));
+    let mut p = Parser::new(qasm3);
+    p.toks = toks;
+    p.pos = 0;
+    p.parse_program()?;
+
+    let mut c = Circuit::new(p.num_qubits);
+    for g in p.gates { c.push(g); }
+    Ok(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+
+    #[test]
+    fn gate_defs() {
+        let c = parse(r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            gate mygate(a, b) x, y {
+                rz(a/2) x;
+                cx x, y;
+                rx(-b) y;
+            }
+            gate twice(a) x, y { mygate(a, 2*a) x, y; mygate(a, a) y, x; }
+            qreg q[3];
+            twice(pi/2) q[2], q[0];
+        "#).unwrap();
+
+        let mut c1 = Circuit::new(3);
+        c1.add_gate_with_phase("rz", vec![2], Rational::new(1,4));
+        c1.add_gate("cx", vec![2, 0]);
+        c1.add_gate_with_phase("rx", vec![0], Rational::new(-1,1));
+        c1.add_gate_with_phase("rz", vec![0], Rational::new(1,4));
+        c1.add_gate("cx", vec![0, 2]);
+        c1.add_gate_with_phase("rx", vec![2], Rational::new(-1,2));
+        assert_eq!(c, c1);
+    }
+
+    #[test]
+    fn expressions() {
+        let c = parse(r#"
+            qreg q[1];
+            rz(pi/4 + 3*pi/4) q[0];
+            rz(-(pi - pi/3) * 2) q[0];
+            rz(2^3 * pi / 16) q[0];
+            rz(0.25*pi) q[0];
+            rz(1.0471975511965976) q[0];
+            rz(pi/2 + 0.5) q[0];
+            rz(acos) q[0];
+        "#);
+        assert_eq!(c.unwrap_err().to_string(), "line 9, column 16: Unknown parameter: acos");
+
+        let c = parse(r#"
+            qreg q[1];
+            rz(pi/4 + 3*pi/4) q[0];
+            rz(-(pi - pi/3) * 2) q[0];
+            rz(2^3 * pi / 16) q[0];
+            rz(0.25*pi) q[0];
+            rz(1.0471975511965976) q[0];
+            p(cos(pi)*pi) q[0];
+        "#).unwrap();
+        let phases: Vec<Rational> = c.gates.iter().map(|g| g.phase).collect();
+        assert_eq!(phases, vec![Rational::new(1,1), Rational::new(2,3), Rational::new(1,2),
+                                Rational::new(1,4), Rational::new(1,3), Rational::new(1,1)]);
+    }
+
+    #[test]
+    fn broadcast() {
+        let c = parse(r#"
+            qreg q[3];
+            qreg r[3];
+            creg c[3];
+            h q;
+            cx q, r;
+            cz q[0], r;
+            barrier q, r[1];
+            measure q -> c;
+            measure r[0] -> c[0];
+        "#).unwrap();
+        assert_eq!(c.num_qubits(), 6);
+        assert_eq!(c.num_gates(), 9);
+        assert_eq!(c.gates[4], Gate::new(CNOT, vec![1, 4]));
+        assert_eq!(c.gates[8], Gate::new(CZ, vec![0, 5]));
+
+        let e = parse("qreg q[2]; qreg r[3];\ncx q, r;").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Registers in gate application have different sizes");
+    }
+
+    #[test]
+    fn repeated_qubits() {
+        let e = parse("qreg q[2];\ncx q[0], q[0];").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 10: Qubit used more than once in a gate: q[0]");
+        assert!(matches!(&e, ReadError::InvalidQubit { line: 2, column: 10, qubit, .. } if qubit == "q[0]"));
+        let e = parse("qreg q[3];\nccx q[0], q[1], q[0];").unwrap_err();
+        assert_eq!(e.position(), Some((2, 17)));
+        let e = parse("qreg q[2]; qreg r[2];\ncx q, q;").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 7: Qubit used more than once in a gate: q[0]");
+        let e = parse("qreg q[2]; qreg r[2];\ncz r[1], r;").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 10: Qubit used more than once in a gate: r[1]");
+
+        // repeated qubits inside a gate definition are caught when it is applied
+        let e = parse("qreg q[2];\ngate bad a, b { cx a, a; }\nbad q[0], q[1];").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 1: Gate 'cx' uses a qubit more than once: 0");
+    }
+
+    #[test]
+    fn errors() {
+        let e = parse("qreg q[2];\nfoo q[0];").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Unknown gate: foo");
+        assert!(matches!(&e, ReadError::UnknownGate { line: 2, column: 1, name } if name == "foo"));
+        let e = parse("qreg q[2];\n  h q[2];").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 7: Index out of bounds: q[2]");
+        assert!(matches!(&e, ReadError::InvalidQubit { qubit, .. } if qubit == "q[2]"));
+        let e = parse("qreg q[2];\nh r[0];").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 3: Undeclared register: r");
+        let e = parse("qreg q[2];\nrz(pi/0) q[0];").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Division by zero");
+        let e = parse("qreg q[2];\ncx q[0];").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Wrong number of qubits for gate 'cx': 1");
+        let e = parse("qreg q[2];\nrz q[0];").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Gate 'rz' expects 1 parameter(s), got 0");
+        assert!(matches!(e, ReadError::WrongParameters { expected: 1, found: 0, .. }));
+        let e = parse("gate g a { h b; }").unwrap_err();
+        assert_eq!(e.to_string(), "line 1, column 14: Unknown qubit argument: b");
+        let e = parse("gate g(x) a { rz(y) a; }").unwrap_err();
+        assert_eq!(e.to_string(), "line 1, column 18: Unknown parameter: y");
+        let e = parse("OPENQASM 4.0;").unwrap_err();
+        assert_eq!(e.to_string(), "line 1, column 10: Only OpenQASM 2.0 and 3 are supported");
+        let e = parse("qreg q[1];\ngphase(pi) q[0];").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Unknown gate: gphase");
+        let e = parse("qreg q[1];\nh q[0]").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 7: Expected ';', got end of input");
+        let e = parse("opaque g a;\nqreg q[1];\ng q[0];").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 1: Gate 'g' is opaque, so cannot be applied");
+        let e = parse("qreg q[1];\n/* unfinished").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Unterminated comment");
+    }
+
+    #[test]
+    fn qelib1_gates() {
+        // check the decompositions of gates which aren't built in
+        let c = parse("qreg q[1]; u3(pi/2, 0, pi) q[0];").unwrap();
+        let h = parse("qreg q[1]; h q[0];").unwrap();
+        assert!(equal_up_to_global_phase(&c.to_tensorf(), &h.to_tensorf(), 1e-10));
+
+        let c = parse("qreg q[1]; u2(0, pi) q[0];").unwrap();
+        assert!(equal_up_to_global_phase(&c.to_tensorf(), &h.to_tensorf(), 1e-10));
+
+        let c = parse("qreg q[1]; u3(pi, 0, pi) q[0];").unwrap();
+        let x = parse("qreg q[1]; x q[0];").unwrap();
+        assert!(equal_up_to_global_phase(&c.to_tensorf(), &x.to_tensorf(), 1e-10));
+
+        let c = parse("qreg q[1]; ry(pi/3) q[0]; ry(pi/6) q[0];").unwrap();
+        let y = parse("qreg q[1]; ry(pi/2) q[0];").unwrap();
+        let u = parse("qreg q[1]; U(pi/2, 0, 0) q[0];").unwrap();
+        assert!(equal_up_to_global_phase(&c.to_tensorf(), &y.to_tensorf(), 1e-10));
+        assert!(equal_up_to_global_phase(&u.to_tensorf(), &y.to_tensorf(), 1e-10));
+
+        let c = parse("qreg q[1]; sx q[0]; sx q[0];").unwrap();
+        assert!(equal_up_to_global_phase(&c.to_tensorf(), &x.to_tensorf(), 1e-10));
+
+        // controlled gates, in terms of their definition with a controlled-phase
+        let pairs = [
+            ("ch q[0], q[1];", "ry(-pi/4) q[1]; cz q[0], q[1]; ry(pi/4) q[1];"),
+            ("cy q[0], q[1];", "cz q[0], q[1]; cx q[0], q[1]; s q[0];"),
+            ("crz(pi/2) q[0], q[1];", "rz(-pi/4) q[0]; cu1(pi/2) q[0], q[1];"),
+            ("cu1(pi) q[0], q[1];", "cz q[0], q[1];"),
+            ("cu1(pi/2) q[0], q[1];", "cp(pi/2) q[0], q[1];"),
+            ("crx(pi) q[0], q[1];", "cx q[0], q[1]; sdg q[0];"),
+            ("cry(pi) q[0], q[1];", "cy q[0], q[1]; sdg q[0];"),
+            ("cu3(pi/2, 0, pi) q[0], q[1];", "ch q[0], q[1];"),
+            ("cswap q[0], q[1], q[2];", "ccx q[0], q[2], q[1]; ccx q[0], q[1], q[2]; ccx q[0], q[2], q[1];"),
+            ("rzz(pi/4) q[0], q[1];", "pp(pi/4) q[0], q[1];"),
+            ("rxx(pi/4) q[0], q[1];", "h q; pp(pi/4) q[0], q[1]; h q;"),
+            ("id q[0]; u0(0) q[1];", ""),
+        ];
+        for (s1, s2) in pairs.iter() {
+            let c1 = parse(&format!("qreg q[3]; {}", s1)).unwrap();
+            let c2 = parse(&format!("qreg q[3]; {}", s2)).unwrap();
+            assert!(equal_up_to_global_phase(&c1.to_tensorf(), &c2.to_tensorf(), 1e-10),
+                    "{} != {}", s1, s2);
+        }
+    }
+
+    #[test]
+    fn qiskit_style() {
+        let c = parse(r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            gate rzx(param0) q0,q1 { h q1; cx q0,q1; rz(param0) q1; cx q0,q1; h q1; }
+            gate ecr q0,q1 { rzx(pi/4) q0,q1; x q0; rzx(-pi/4) q0,q1; }
+            qreg q[2];
+            creg meas[2];
+            u(pi/2,0,pi) q[0];
+            u3(3.141592653589793,0,3.141592653589793) q[1];
+            ecr q[0],q[1];
+            barrier q[0],q[1];
+            measure q[0] -> meas[0];
+            measure q[1] -> meas[1];
+        "#).unwrap();
+        assert_eq!(c.num_qubits(), 2);
+        assert_eq!(c.num_gates(), 17);
+    }
+
+    #[test]
+    fn qasm3_program() {
+        let c = parse(r#"
+            OPENQASM 3.0;
+            include "stdgates.inc";
+            qubit[2] q;
+            qubit r;
+            bit[2] c;
+            bit d;
+            const angle[20] theta = π/4;
+            float[64] x = 2 * theta;
+            gate g(a) s, t {
+                ctrl @ rz(a) s, t;
+                inv @ t t;
+            }
+            h q;
+            g(theta) q[0], r;
+            rx(x ** 2 / pi) r;
+            c = measure q;
+            d = measure r;
+            c[0] = measure q[1];
+            measure r;
+            measure q -> c;
+        "#).unwrap();
+
+        let mut c1 = Circuit::new(3);
+        c1.add_gate("h", vec![0]);
+        c1.add_gate("h", vec![1]);
+        c1.add_gate_with_phase("rz", vec![0], Rational::new(1,8));
+        c1.add_gate("cx", vec![0, 2]);
+        c1.add_gate_with_phase("rz", vec![2], Rational::new(-1,8));
+        c1.add_gate("cx", vec![0, 2]);
+        c1.add_gate_with_phase("rz", vec![2], Rational::new(1,8));
+        c1.add_gate_with_phase("rz", vec![0], Rational::new(-1,8));
+        c1.add_gate("tdg", vec![2]);
+        c1.add_gate_with_phase("rx", vec![2], Rational::new(1,4));
+        assert_eq!(c, c1);
+    }
+
+    #[test]
+    fn qasm3_modifiers() {
+        let c = parse(r#"
+            OPENQASM 3;
+            qubit[3] q;
+            ctrl @ x q[0], q[1];
+            ctrl @ ctrl @ x q[0], q[1], q[2];
+            ctrl(2) @ z q[0], q[1], q[2];
+        "#).unwrap();
+        assert_eq!(c.gates, vec![Gate::new(CNOT, vec![0, 1]), Gate::new(TOFF, vec![0, 1, 2]),
+                                 Gate::new(CCZ, vec![0, 1, 2])]);
+
+        // these should agree exactly, not just up to global phase
+        let pairs = [
+            ("ctrl @ rz(pi/2) q[0], q[1];", "rz(-pi/4) q[0]; cp(pi/2) q[0], q[1];"),
+            ("cp(pi/2) q[0], q[1];", "t q[0]; cx q[0], q[1]; tdg q[1]; cx q[0], q[1]; t q[1];"),
+            ("ctrl @ h q[0], q[1];", "ry(-pi/4) q[1]; cz q[0], q[1]; ry(pi/4) q[1];"),
+            ("cu(pi/2, 0, pi, 0) q[0], q[1];", "ch q[0], q[1];"),
+            ("cy q[0], q[1];", "sdg q[1]; cx q[0], q[1]; s q[1];"),
+            ("crx(pi) q[0], q[1];", "cx q[0], q[1]; sdg q[0];"),
+            ("cry(pi) q[0], q[1];", "cy q[0], q[1]; sdg q[0];"),
+            ("negctrl @ x q[0], q[1];", "x q[0]; cx q[0], q[1]; x q[0];"),
+            ("ctrl @ gphase(π/2) q[0];", "s q[0];"),
+            ("ctrl @ inv @ s q[0], q[1];", "cp(-pi/2) q[0], q[1];"),
+            ("inv @ ctrl @ rx(pi/3) q[2], q[0];", "crx(-pi/3) q[2], q[0];"),
+            ("cswap q[0], q[1], q[2];", "ccx q[0], q[2], q[1]; ccx q[0], q[1], q[2]; ccx q[0], q[2], q[1];"),
+            ("inv @ t q[0]; t q[0]; pow(-1) @ h q[1]; h q[1];", ""),
+            ("pow(2) @ t q[0]; pow(1/2) @ s q[1]; sx q[2]; sx q[2];", "s q[0]; t q[1]; x q[2];"),
+            ("pow(1/2) @ rz(pi) q[0];", "rz(pi/2) q[0];"),
+            ("ctrl @ pow(1/3) @ p(pi) q[0], q[1];", "cp(pi/3) q[0], q[1];"),
+            ("pow(100000000) @ x q[0]; pow(-100000001) @ t q[1];", "tdg q[1];"),
+            ("pow(1024) @ h q[0]; pow(-3) @ cx q[1], q[2];", "cx q[1], q[2];"),
+            ("ctrl @ U(pi/2, 0, pi) q[1], q[2];", "ch q[1], q[2];"),
+            ("ctrl @ u3(pi/2, 0, pi) q[1], q[2];", "ctrl @ gphase(-3*pi/4) q[1]; ch q[1], q[2];"),
+        ];
+        for (s1, s2) in pairs.iter() {
+            let c1 = parse(&format!("OPENQASM 3; include \"stdgates.inc\"; qubit[3] q; {}", s1)).expect(s1);
+            let c2 = parse(&format!("OPENQASM 3; include \"stdgates.inc\"; qubit[3] q; {}", s2)).expect(s2);
+            assert!(compare_tensors(&c1.to_tensorf(), &c2.to_tensorf(), 1e-10), "{} != {}", s1, s2);
+        }
+    }
+
+    #[test]
+    fn qasm3_errors() {
+        let e = parse("OPENQASM 3;\nqubit[4] q;\nctrl(3) @ x q[0], q[1], q[2], q[3];").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 1: Gate 'ccx' cannot be controlled");
+        assert!(matches!(&e, ReadError::UnsupportedGate { name, .. } if name == "ccx"));
+        let e = parse("OPENQASM 3;\nqubit[4] q;\nctrl @ x q[0], q[0];").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 16: Qubit used more than once in a gate: q[0]");
+        let e = parse("OPENQASM 3;\nqubit[4] q;\npow(0.5) @ h q[0];").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 1: Gate 'h' cannot be raised to a non-integer power");
+        let e = parse("OPENQASM 3;\nqubit[4] q;\npow(100000000) @ cx q[0], q[1];").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 1: Gate 'cx' cannot be raised to such a large power");
+        let e = parse("OPENQASM 3;\nqubit[4] q;\npow(2**62) @ p(3*pi/5) q[0];").unwrap_err();
+        assert!(matches!(&e, ReadError::UnsupportedGate { name, .. } if name == "p"));
+        let e = parse("OPENQASM 3;\nqubit[4] q;\nctrl(2) @ x q[0], q[1];").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 1: Wrong number of qubits for gate 'x': 2");
+        let e = parse("OPENQASM 3;\nqubit[4] q;\nfor i in [0:3] { h q[i]; }").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 1: 'for' statements are not supported");
+        let e = parse("OPENQASM 3;\nqubit q;\nqubit q;").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 7: Re-declaration of register: q");
+        let e = parse("OPENQASM 3;\ninclude \"qelib1.inc\";").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 9: Cannot include file \"qelib1.inc\"");
+    }
+
+    #[test]
+    fn qasm3_round_trip() {
+        let mut c = Circuit::new(4);
+        c.add_gate("h", vec![0]);
+        c.add_gate_with_phase("rz", vec![1], Rational::new(-3,4));
+        c.add_gate_with_phase("rx", vec![2], Rational::new(1,1));
+        c.add_gate("cx", vec![0, 1]);
+        c.add_gate("ccz", vec![0, 1, 3]);
+        c.add_gate("ccx", vec![3, 2, 1]);
+        c.add_gate("swap", vec![2, 0]);
+        c.add_gate("sdg", vec![3]);
+        let s = c.to_qasm3();
+        assert!(s.starts_with("OPENQASM 3;\ninclude \"stdgates.inc\";\nqubit[4] q;\nh q[0];\nrz(-3*pi/4) q[1];\n"));
+        assert!(s.contains("ctrl(2) @ z q[0], q[1], q[3];"));
+        assert_eq!(parse(&s).unwrap(), c);
+
+        c.add_gate_with_phase("pp", vec![0, 2, 3], Rational::new(1,2));
+        c.add_gate("xcx", vec![1, 2]);
+        let c1 = parse(&c.to_qasm3()).unwrap();
+        assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+    }
+}
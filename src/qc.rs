@@ -0,0 +1,290 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A parser for the `.qc` circuit format
+//!
+//! This is the format used by the benchmark circuits of Amy, Maslov and Mosca. A
+//! program declares its qubits with `.v`, then lists gates between `BEGIN` and `END`,
+//! one per line, with controls before the target. For example:
+//!
+//! ```text
+//! .v a b c
+//! .i a b c
+//! BEGIN
+//! H c
+//! tof a b c
+//! T* a
+//! END
+//! ```
+//!
+//! The supported gates are `H`, `X`, `Y`, `Z`, `S`/`P`, `T`, their inverses `S*`,
+//! `P*` and `T*`, `swap`, and `tof` or `cnot`. `tof`, `X` and `Z` take any number of
+//! qubits, with all but the last being controls. Toffolis with more than two controls
+//! and Z gates on more than three qubits are decomposed using
+//! [Gate::push_controlled_phase]. The `.i` and `.o` lines are ignored, so every qubit
+//! is treated as an input and an output.
+
+use num::{Rational,One};
+use rustc_hash::FxHashMap;
+use crate::circuit::Circuit;
+use crate::gate::*;
+use crate::qasm::ReadError;
+
+/// The most qubits a multiply-controlled gate can act on, as its decomposition grows
+/// exponentially with the number of qubits
+pub const MAX_CONTROLLED_QUBITS: usize = 16;
+
+/// Push a NOT with any number of controls, decomposing it if needed
+///
+/// With more than two controls, this uses 2^n gates for n qubits, so callers should
+/// keep to at most [MAX_CONTROLLED_QUBITS].
+pub fn push_controlled_not(gs: &mut Vec<Gate>, qs: &[usize]) {
+    match qs.len() {
+        1 => gs.push(Gate::new(NOT, qs.to_vec())),
+        2 => gs.push(Gate::new(CNOT, qs.to_vec())),
+        3 => gs.push(Gate::new(TOFF, qs.to_vec())),
+        n => {
+            let t = qs[n-1];
+            gs.push(Gate::new(HAD, vec![t]));
+            Gate::push_controlled_phase(gs, qs, Rational::one());
+            gs.push(Gate::new(HAD, vec![t]));
+        },
+    }
+}
+
+/// The column of `word` in `line`, counting from 1, where `word` is a slice of `line`
+fn column(line: &str, word: &str) -> usize {
+    let offset = word.as_ptr() as usize - line.as_ptr() as usize;
+    line[..offset].chars().count() + 1
+}
+
+/// Parse a `.qc` program into a [Circuit]
+///
+/// Errors give the line and column where the problem was found, as for [crate::qasm].
+pub fn parse(source: &str) -> Result<Circuit, ReadError> {
+    let mut names: FxHashMap<&str, usize> = FxHashMap::default();
+    let mut gates = Vec::new();
+    // false before BEGIN, true between BEGIN and END
+    let mut in_body = false;
+    let mut done = false;
+    let mut last_line = 0;
+
+    for (i, raw) in source.lines().enumerate() {
+        let line = raw.split('#').next().unwrap().trim();
+        if line.is_empty() { continue; }
+        last_line = i + 1;
+        let mut words = line.split_whitespace();
+        let cmd = words.next().unwrap();
+        let (l, c) = (i + 1, column(raw, cmd));
+        let parse_err = |message: String| Err(ReadError::Parse { line: l, column: c, message });
+
+        if done { return parse_err(format!("Unexpected '{}' after END", cmd)); }
+
+        if !in_body {
+            match cmd {
+                ".v" => {
+                    for w in words.flat_map(|w| w.split(',')).filter(|w| !w.is_empty()) {
+                        if names.contains_key(w) {
+                            return Err(ReadError::Parse { line: l, column: column(raw, w),
+                                message: format!("Re-declaration of qubit: {}", w) });
+                        }
+                        names.insert(w, names.len());
+                    }
+                },
+                ".i" | ".o" | ".c" | ".ol" => {},
+                "BEGIN" => {
+                    if words.next().is_some() {
+                        return Err(ReadError::Unsupported { line: l, column: c,
+                            message: String::from("Sub-circuits are not supported") });
+                    }
+                    in_body = true;
+                },
+                _ => return parse_err(format!("Unexpected '{}' before BEGIN", cmd)),
+            }
+            continue;
+        }
+
+        if cmd == "END" { done = true; continue; }
+
+        let mut qs = Vec::new();
+        for w in words {
+            match names.get(w) {
+                Some(q) if qs.contains(q) =>
+                    return Err(ReadError::InvalidQubit { line: l, column: column(raw, w),
+                        qubit: w.to_string(), reason: String::from("Qubit used more than once in a gate") }),
+                Some(&q) => qs.push(q),
+                None => return Err(ReadError::InvalidQubit { line: l, column: column(raw, w),
+                    qubit: w.to_string(), reason: String::from("Undeclared qubit") }),
+            }
+        }
+        let wrong_qubits = || Err(ReadError::WrongQubits { line: l, column: c, name: cmd.to_string(), found: qs.len() });
+        if qs.is_empty() { return wrong_qubits(); }
+        if qs.len() > MAX_CONTROLLED_QUBITS {
+            return Err(ReadError::UnsupportedGate { line: l, column: c, name: cmd.to_string(),
+                reason: format!("cannot have more than {} controls", MAX_CONTROLLED_QUBITS - 1) });
+        }
+
+        let t = match cmd {
+            "tof" | "Tof" | "TOF" | "cnot" | "CNOT" | "X" | "x" => {
+                push_controlled_not(&mut gates, &qs);
+                continue;
+            },
+            "Z" | "z" => {
+                Gate::push_controlled_phase(&mut gates, &qs, Rational::one());
+                continue;
+            },
+            "H" | "h" => HAD,
+            "S" | "P" => S,
+            "S*" | "P*" => Sdg,
+            "T" => T,
+            "T*" => Tdg,
+            "Y" => {
+                // Y = iXZ, and global phases are ignored
+                if qs.len() != 1 { return wrong_qubits(); }
+                gates.push(Gate::new(Z, qs.clone()));
+                NOT
+            },
+            "swap" | "SWAP" => {
+                if qs.len() != 2 { return wrong_qubits(); }
+                gates.push(Gate::new(SWAP, qs));
+                continue;
+            },
+            _ => return Err(ReadError::UnknownGate { line: l, column: c, name: cmd.to_string() }),
+        };
+        if qs.len() != 1 { return wrong_qubits(); }
+        gates.push(Gate::new(t, qs));
+    }
+
+    if !done {
+        let message = String::from(if in_body { "Missing END" } else { "Missing BEGIN" });
+        return Err(ReadError::Parse { line: last_line + 1, column: 1, message });
+    }
+
+    let mut c = Circuit::new(names.len());
+    for g in gates { c.push(g); }
+    Ok(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+    use num::Zero;
+    use num::complex::Complex;
+
+    /// Write a Clifford+T circuit in the .qc format, to test against the benchmarks
+    fn to_qc(c: &Circuit) -> String {
+        let vars: Vec<String> = (0..c.num_qubits()).map(|i| format!("q{}", i)).collect();
+        let mut s = format!(".v {}\n.i {}\n.o {}\n\nBEGIN\n", vars.join(" "), vars.join(" "), vars.join(" "));
+        for g in &c.gates {
+            let name = match g.t {
+                HAD => "H", NOT | CNOT | TOFF => "tof", Z | CZ | CCZ => "Z",
+                S => "S", Sdg => "S*", T => "T", Tdg => "T*", SWAP => "swap",
+                _ => panic!("Unexpected gate: {:?}", g),
+            };
+            let qs: Vec<String> = g.qs.iter().map(|&q| vars[q].clone()).collect();
+            s += &format!("{} {}\n", name, qs.join(" "));
+        }
+        s + "END\n"
+    }
+
+    #[test]
+    fn benchmarks() {
+        for f in ["tof_3", "barenco_tof_4", "mod5_4", "vbe_adder_3"].iter() {
+            let c = Circuit::from_file(&format!("circuits/{}.qasm", f)).unwrap();
+            let c1 = parse(&to_qc(&c)).unwrap();
+            assert_eq!(c, c1, "{}", f);
+        }
+    }
+
+    #[test]
+    fn fixtures() {
+        // files in the .qc format, checked against independently written QASM, so that
+        // the order of controls and the meaning of T* are tested
+        let c = Circuit::from_file("circuits/qc/tof_3.qc").unwrap();
+        let c1 = Circuit::from_file("circuits/tof_3.qasm").unwrap();
+        assert_eq!(c.num_qubits(), 5);
+        assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+
+        let c = Circuit::from_file("circuits/qc/toffoli_ct.qc").unwrap();
+        assert_eq!(c.stats().tcount, 7);
+        let c1 = Circuit::from_qasm("qreg q[3]; ccx q[0], q[1], q[2];").unwrap();
+        assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+    }
+
+    #[test]
+    fn gates() {
+        let c = parse(r#"
+            # a comment
+            .v a,b c d
+            .i a b c
+            .o a b c d
+            BEGIN
+            H a   # another comment
+            cnot a b
+            tof a b c
+            Z a b
+            T* d
+            P b
+            END
+        "#).unwrap();
+        assert_eq!(c.num_qubits(), 4);
+        assert_eq!(c.gates, vec![
+            Gate::new(HAD, vec![0]), Gate::new(CNOT, vec![0, 1]), Gate::new(TOFF, vec![0, 1, 2]),
+            Gate::new(CZ, vec![0, 1]), Gate::new(Tdg, vec![3]), Gate::new(S, vec![1]),
+        ]);
+    }
+
+    #[test]
+    fn multi_controlled() {
+        // a Toffoli with 3 controls should flip the target only on 1111 and 1110
+        let c = parse(".v a b c d\nBEGIN\ntof a b c d\nEND").unwrap();
+        let m = c.to_matrixf();
+        for x in 0..16 {
+            let y = if x >> 1 == 7 { x ^ 1 } else { x };
+            for y1 in 0..16 {
+                let expected = if y1 == y { Complex::new(1.0, 0.0) } else { Complex::zero() };
+                assert!((m[[y1, x]] - expected).norm() < 1e-10, "{} -> {}", x, y1);
+            }
+        }
+
+        let c = parse(".v a b c d\nBEGIN\nZ a b c d\nEND").unwrap();
+        let m = c.to_matrixf();
+        for x in 0..16 {
+            let expected = if x == 15 { -1.0 } else { 1.0 };
+            assert!((m[[x, x]] - Complex::new(expected, 0.0)).norm() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let e = parse(".v a b\nBEGIN\ntof a c\nEND").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 7: Undeclared qubit: c");
+        let e = parse(".v a b\nBEGIN\nH a b\nEND").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 1: Wrong number of qubits for gate 'H': 2");
+        let e = parse(".v a b\nBEGIN\n  Rz a\nEND").unwrap_err();
+        assert!(matches!(&e, ReadError::UnknownGate { line: 3, column: 3, name } if name == "Rz"));
+        let e = parse(".v a b\nBEGIN\nH a\n").unwrap_err();
+        assert_eq!(e.to_string(), "line 4, column 1: Missing END");
+        let vs: Vec<String> = (0..20).map(|i| format!("q{}", i)).collect();
+        let e = parse(&format!(".v {}\nBEGIN\ntof {}\nEND", vs.join(" "), vs.join(" "))).unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 1: Gate 'tof' cannot have more than 15 controls");
+        let e = parse(".v a b\nBEGIN\ntof a a b\nEND").unwrap_err();
+        assert_eq!(e.to_string(), "line 3, column 7: Qubit used more than once in a gate: a");
+        let e = parse(".v a b\nBEGIN\nswap b b\nEND").unwrap_err();
+        assert!(matches!(&e, ReadError::InvalidQubit { line: 3, column: 8, qubit, .. } if qubit == "b"));
+    }
+}
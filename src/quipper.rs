@@ -0,0 +1,585 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A parser for circuits in Quipper's ASCII format
+//!
+//! This is the format written by Quipper's `print_generic ASCII`, with one gate per
+//! line between an `Inputs:` and an `Outputs:` line. For example:
+//!
+//! ```text
+//! Inputs: 0:Qbit, 1:Qbit, 2:Qbit
+//! QGate["H"](2)
+//! QGate["not"](2) with controls=[+0,-1]
+//! QGate["T"]*(0)
+//! QRot["exp(-i%Z)",0.39269908169872414](1)
+//! Outputs: 0:Qbit, 1:Qbit, 2:Qbit
+//! ```
+//!
+//! Wires are numbered by Quipper, and are mapped to qubits in the order they first
+//! appear. The gates `not`, `X`, `multinot`, `Z`, `S`, `T`, `Y`, `swap`, `omega`, the
+//! rotations `exp(-i%Z)` and `R(2pi/%)` and `GPhase` can have any number of positive or
+//! negative controls, and multiply-controlled gates are decomposed using
+//! [Gate::push_controlled_phase]. `H` and `V` are only supported without controls.
+//!
+//! `QInit0` and `QInit1` start a fresh qubit using [InitAncilla], and `QTerm0` and
+//! `QTerm1` end one using [PostSelect]. `QDiscard` leaves the qubit as an output of the
+//! circuit. Classical wires, measurements and subroutines are not supported.
+
+use num::{Rational,Zero,One};
+use rustc_hash::FxHashMap;
+use crate::circuit::Circuit;
+use crate::gate::*;
+use crate::qasm::approximate_phase;
+use crate::qasm::ReadError;
+use crate::qc::{push_controlled_not, MAX_CONTROLLED_QUBITS};
+
+/// A single line of a Quipper program, split into its parts
+struct Statement<'a> {
+    /// the gate, e.g. `QGate`, `QRot` or `QInit0`
+    op: &'a str,
+    /// the contents of the square brackets after `op`, split at the top-level comma
+    label: Vec<&'a str>,
+    inverse: bool,
+    wires: Vec<&'a str>,
+    /// the `with` clauses following the wires
+    clauses: Vec<&'a str>,
+}
+
+impl<'a> Statement<'a> {
+    fn parse(line: &'a str) -> Result<Statement<'a>, String> {
+        let end = line.find(['[', '(']).unwrap_or(line.len());
+        let op = line[..end].trim();
+        let mut rest = &line[end..];
+        let mut label = Vec::new();
+
+        if rest.starts_with('[') {
+            // find the matching ']', skipping over quoted strings
+            let mut quoted = false;
+            let mut start = 1;
+            let mut close = None;
+            for (i, c) in rest.char_indices().skip(1) {
+                match c {
+                    '"' => quoted = !quoted,
+                    ',' if !quoted => { label.push(rest[start..i].trim()); start = i + 1; },
+                    ']' if !quoted => { close = Some(i); break; },
+                    _ => {},
+                }
+            }
+            let close = close.ok_or("Missing ']'")?;
+            label.push(rest[start..close].trim());
+            rest = &rest[close+1..];
+        }
+
+        let inverse = rest.starts_with('*');
+        if inverse { rest = &rest[1..]; }
+
+        if !rest.starts_with('(') { return Err(format!("Expected '(' after '{}'", op)); }
+        let close = rest.find(')').ok_or("Missing ')'")?;
+        let wires = rest[1..close].split(',').map(|w| w.trim()).filter(|w| !w.is_empty()).collect();
+        let clauses = rest[close+1..].split("with").map(|s| s.trim()).filter(|s| !s.is_empty()).collect();
+
+        Ok(Statement { op, label, inverse, wires, clauses })
+    }
+
+    /// the gate name, without its quotes
+    fn name(&self) -> Result<&'a str, String> {
+        match self.label.first() {
+            Some(s) if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') => Ok(&s[1..s.len()-1]),
+            _ => Err(format!("Expected a quoted gate name after '{}'", self.op)),
+        }
+    }
+
+    /// the value of a clause of the form `key=value`, if present
+    fn clause(&self, key: &str) -> Option<&'a str> {
+        self.clauses.iter().find_map(|c| {
+            let mut kv = c.splitn(2, '=');
+            if kv.next().map(|k| k.trim()) == Some(key) { kv.next().map(|v| v.trim()) } else { None }
+        })
+    }
+}
+
+/// Parses a program, keeping track of which qubit each Quipper wire currently holds
+struct Parser {
+    /// wire -> qubit, or None for a classical wire
+    wires: FxHashMap<usize, Option<usize>>,
+    nqubits: usize,
+    gates: Vec<Gate>,
+    /// the position of the current statement, for errors
+    line: usize,
+    column: usize,
+}
+
+impl Parser {
+    fn parse_err<T>(&self, message: impl Into<String>) -> Result<T, ReadError> {
+        Err(ReadError::Parse { line: self.line, column: self.column, message: message.into() })
+    }
+
+    fn unsupported<T>(&self, message: impl Into<String>) -> Result<T, ReadError> {
+        Err(ReadError::Unsupported { line: self.line, column: self.column, message: message.into() })
+    }
+
+    fn unsupported_gate<T>(&self, name: &str, reason: impl Into<String>) -> Result<T, ReadError> {
+        Err(ReadError::UnsupportedGate { line: self.line, column: self.column,
+                                         name: name.to_string(), reason: reason.into() })
+    }
+
+    fn wire_err<T>(&self, id: usize, reason: &str) -> Result<T, ReadError> {
+        Err(ReadError::InvalidQubit { line: self.line, column: self.column,
+                                      qubit: id.to_string(), reason: reason.to_string() })
+    }
+
+    fn wire_id(&self, w: &str) -> Result<usize, ReadError> {
+        // the Inputs and Outputs lines give a type after the wire, e.g. 0:Qbit
+        let w = w.split(':').next().unwrap().trim();
+        w.parse().or_else(|_| self.parse_err(format!("Invalid wire: {}", w)))
+    }
+
+    fn qubit(&self, w: &str) -> Result<usize, ReadError> {
+        let id = self.wire_id(w)?;
+        match self.wires.get(&id) {
+            Some(Some(q)) => Ok(*q),
+            Some(None) => self.wire_err(id, "Classical wires are not supported"),
+            None => self.wire_err(id, "Wire is not in use"),
+        }
+    }
+
+    fn new_wire(&mut self, w: &str, classical: bool) -> Result<usize, ReadError> {
+        let id = self.wire_id(w)?;
+        if let Some(Some(_)) = self.wires.get(&id) {
+            return self.wire_err(id, "Wire is already in use");
+        }
+        if classical {
+            self.wires.insert(id, None);
+            Ok(0)
+        } else {
+            let q = self.nqubits;
+            self.nqubits += 1;
+            self.wires.insert(id, Some(q));
+            Ok(q)
+        }
+    }
+
+    fn inputs(&mut self, decls: &str) -> Result<(), ReadError> {
+        if decls.trim() == "None" { return Ok(()); }
+        for d in decls.split(',').map(|d| d.trim()).filter(|d| !d.is_empty()) {
+            match d.split(':').nth(1).map(|t| t.trim()) {
+                Some("Qbit") => { self.new_wire(d, false)?; },
+                Some("Cbit") => { self.new_wire(d, true)?; },
+                _ => return self.parse_err(format!("Invalid input: {}", d)),
+            }
+        }
+        Ok(())
+    }
+
+    /// the controls of a statement, as qubits with a flag for negative controls
+    fn controls(&self, st: &Statement) -> Result<Vec<(usize, bool)>, ReadError> {
+        let cs = match st.clause("controls") {
+            Some(cs) => cs,
+            None => return Ok(vec![]),
+        };
+        if !cs.starts_with('[') || !cs.ends_with(']') {
+            return self.parse_err(format!("Invalid controls: {}", cs));
+        }
+        let mut controls = Vec::new();
+        for c in cs[1..cs.len()-1].split(',').map(|c| c.trim()).filter(|c| !c.is_empty()) {
+            let neg = c.starts_with('-');
+            let w = c.trim_start_matches(['+', '-']);
+            controls.push((self.qubit(w)?, neg));
+        }
+        Ok(controls)
+    }
+
+    /// check no qubit is used twice among the controls and targets of a gate
+    fn check_distinct(&self, cs: &[usize], ts: &[usize]) -> Result<(), ReadError> {
+        let qs: Vec<usize> = cs.iter().chain(ts).copied().collect();
+        match (1..qs.len()).find(|&j| qs[..j].contains(&qs[j])) {
+            Some(j) => {
+                let id = self.wires.iter().find(|&(_, &q)| q == Some(qs[j])).map_or(qs[j], |(&id, _)| id);
+                self.wire_err(id, "Wire used more than once in a gate")
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// check a gate doesn't have too many controls to decompose
+    fn check_controls(&self, name: &str, num_controls: usize) -> Result<(), ReadError> {
+        if num_controls >= MAX_CONTROLLED_QUBITS {
+            self.unsupported_gate(name, format!("cannot have more than {} controls", MAX_CONTROLLED_QUBITS - 1))
+        } else { Ok(()) }
+    }
+
+    /// push a diagonal gate diag(e^{i pi g}, e^{i pi (g + p)}), with the given controls
+    ///
+    /// The global phase g only matters when there are controls, where it becomes a phase
+    /// on the controls.
+    fn diagonal(&mut self, cs: &[usize], t: usize, global: Rational, phase: Rational) {
+        Gate::push_controlled_phase(&mut self.gates, cs, global);
+        let mut qs = cs.to_vec();
+        qs.push(t);
+        Gate::push_controlled_phase(&mut self.gates, &qs, phase);
+    }
+
+    fn gate(&mut self, st: &Statement) -> Result<(), ReadError> {
+        let name = st.name().or_else(|e| self.parse_err(e))?;
+        let controls = self.controls(st)?;
+        let cs: Vec<usize> = controls.iter().map(|&(q, _)| q).collect();
+        let ts = st.wires.iter().map(|w| self.qubit(w)).collect::<Result<Vec<_>, _>>()?;
+        self.check_distinct(&cs, &ts)?;
+        let (line, column) = (self.line, self.column);
+        let arity = |n: usize| if ts.len() == n { Ok(()) }
+            else { Err(ReadError::WrongQubits { line, column, name: name.to_string(), found: ts.len() }) };
+        // swap has an extra qubit controlling its decomposition
+        self.check_controls(name, cs.len() + if name == "swap" { 1 } else { 0 })?;
+        let sign = if st.inverse { -Rational::one() } else { Rational::one() };
+
+        // negative controls are handled by conjugating with NOT
+        let negated: Vec<usize> = controls.iter().filter(|&&(_, neg)| neg).map(|&(q, _)| q).collect();
+        for &q in &negated { self.gates.push(Gate::new(NOT, vec![q])); }
+
+        match name {
+            "not" | "X" | "multinot" => {
+                if name != "multinot" { arity(1)?; }
+                for &t in &ts {
+                    let mut qs = cs.clone();
+                    qs.push(t);
+                    push_controlled_not(&mut self.gates, &qs);
+                }
+            },
+            "Z" | "S" | "T" => {
+                arity(1)?;
+                let t = ts[0];
+                if cs.is_empty() {
+                    let gt = match (name, st.inverse) {
+                        ("Z", _) => Z, ("S", false) => S, ("S", true) => Sdg,
+                        ("T", false) => T, _ => Tdg,
+                    };
+                    self.gates.push(Gate::new(gt, vec![t]));
+                } else {
+                    let phase = match name { "Z" => Rational::one(), "S" => Rational::new(1, 2), _ => Rational::new(1, 4) };
+                    self.diagonal(&cs, t, Rational::zero(), sign * phase);
+                }
+            },
+            "Y" => {
+                // Y = S X S*
+                arity(1)?;
+                let t = ts[0];
+                let mut qs = cs.clone();
+                qs.push(t);
+                self.gates.push(Gate::new(Sdg, vec![t]));
+                push_controlled_not(&mut self.gates, &qs);
+                self.gates.push(Gate::new(S, vec![t]));
+            },
+            "swap" => {
+                arity(2)?;
+                if cs.is_empty() {
+                    self.gates.push(Gate::new(SWAP, ts.clone()));
+                } else {
+                    let mut qs = cs.clone();
+                    qs.push(ts[0]);
+                    qs.push(ts[1]);
+                    self.gates.push(Gate::new(CNOT, vec![ts[1], ts[0]]));
+                    push_controlled_not(&mut self.gates, &qs);
+                    self.gates.push(Gate::new(CNOT, vec![ts[1], ts[0]]));
+                }
+            },
+            "omega" => {
+                // the scalar e^{i pi/4}, which is only visible with controls
+                Gate::push_controlled_phase(&mut self.gates, &cs, sign * Rational::new(1, 4));
+            },
+            "H" | "V" => {
+                arity(1)?;
+                if !cs.is_empty() { return self.unsupported_gate(name, "cannot be controlled"); }
+                let t = ts[0];
+                if name == "H" {
+                    self.gates.push(Gate::new(HAD, vec![t]));
+                } else {
+                    // V = H S H
+                    self.gates.push(Gate::new(HAD, vec![t]));
+                    self.gates.push(Gate::new(if st.inverse { Sdg } else { S }, vec![t]));
+                    self.gates.push(Gate::new(HAD, vec![t]));
+                }
+            },
+            _ => return Err(ReadError::UnknownGate { line, column, name: name.to_string() }),
+        }
+
+        for &q in &negated { self.gates.push(Gate::new(NOT, vec![q])); }
+        Ok(())
+    }
+
+    fn rotation(&mut self, st: &Statement) -> Result<(), ReadError> {
+        let name = st.name().or_else(|e| self.parse_err(e))?;
+        let param = match st.label.get(1) {
+            Some(p) if st.label.len() == 2 => p.parse::<f64>().or_else(|_| self.parse_err(format!("Invalid parameter: {}", p)))?,
+            _ => return Err(ReadError::WrongParameters { line: self.line, column: self.column,
+                                                         name: name.to_string(), expected: 1, found: st.label.len() - 1 }),
+        };
+        let controls = self.controls(st)?;
+        let cs: Vec<usize> = controls.iter().map(|&(q, _)| q).collect();
+        if st.wires.len() != 1 {
+            return Err(ReadError::WrongQubits { line: self.line, column: self.column,
+                                                name: name.to_string(), found: st.wires.len() });
+        }
+        let t = self.qubit(st.wires[0])?;
+        self.check_distinct(&cs, &[t])?;
+        self.check_controls(name, cs.len())?;
+        let sign = if st.inverse { -Rational::one() } else { Rational::one() };
+
+        let (global, phase) = match name {
+            // exp(-i t Z) = diag(e^{-it}, e^{it})
+            "exp(-i%Z)" => {
+                let p = approximate_phase(param / std::f64::consts::PI);
+                (-p, p * 2)
+            },
+            // R(2pi/n) = diag(1, e^{2 pi i/n})
+            "R(2pi/%)" => {
+                if param.fract() != 0.0 || param == 0.0 {
+                    return self.parse_err(format!("Invalid parameter: {}", param));
+                }
+                (Rational::zero(), Rational::new(2, 1) / Rational::from_integer(param as isize))
+            },
+            _ => return Err(ReadError::UnknownGate { line: self.line, column: self.column, name: name.to_string() }),
+        };
+
+        let negated: Vec<usize> = controls.iter().filter(|&&(_, neg)| neg).map(|&(q, _)| q).collect();
+        for &q in &negated { self.gates.push(Gate::new(NOT, vec![q])); }
+        self.diagonal(&cs, t, sign * global, sign * phase);
+        for &q in &negated { self.gates.push(Gate::new(NOT, vec![q])); }
+        Ok(())
+    }
+
+    fn global_phase(&mut self, st: &Statement) -> Result<(), ReadError> {
+        let t = match st.clause("t") {
+            Some(t) => t,
+            None => return self.parse_err("Missing 't' for GPhase"),
+        };
+        let t = t.parse::<f64>().or_else(|_| self.parse_err(format!("Invalid parameter: {}", t)))?;
+        let controls = self.controls(st)?;
+        self.check_controls("GPhase", controls.len().saturating_sub(1))?;
+        let negated: Vec<usize> = controls.iter().filter(|&&(_, neg)| neg).map(|&(q, _)| q).collect();
+        let cs: Vec<usize> = controls.iter().map(|&(q, _)| q).collect();
+        let sign = if st.inverse { -Rational::one() } else { Rational::one() };
+        for &q in &negated { self.gates.push(Gate::new(NOT, vec![q])); }
+        Gate::push_controlled_phase(&mut self.gates, &cs, sign * approximate_phase(t));
+        for &q in &negated { self.gates.push(Gate::new(NOT, vec![q])); }
+        Ok(())
+    }
+
+    fn statement(&mut self, line: &str) -> Result<(), ReadError> {
+        if let Some(decls) = line.strip_prefix("Inputs:") { return self.inputs(decls); }
+        if line.starts_with("Outputs:") { return Ok(()); }
+        if line.starts_with("Subroutine") { return self.unsupported("Subroutines are not supported"); }
+
+        let st = Statement::parse(line).or_else(|e| self.parse_err(e))?;
+        let one_wire = || if st.wires.len() == 1 { Ok(st.wires[0]) }
+            else { self.parse_err(format!("'{}' should act on 1 wire", st.op)) };
+
+        match st.op {
+            "QGate" => self.gate(&st),
+            "QRot" => self.rotation(&st),
+            "GPhase" => self.global_phase(&st),
+            "QInit0" | "QInit1" => {
+                let q = self.new_wire(one_wire()?, false)?;
+                self.gates.push(Gate::new(InitAncilla, vec![q]));
+                if st.op == "QInit1" { self.gates.push(Gate::new(NOT, vec![q])); }
+                Ok(())
+            },
+            "QTerm0" | "QTerm1" => {
+                let w = one_wire()?;
+                let q = self.qubit(w)?;
+                if st.op == "QTerm1" { self.gates.push(Gate::new(NOT, vec![q])); }
+                self.gates.push(Gate::new(PostSelect, vec![q]));
+                self.wires.remove(&self.wire_id(w)?);
+                Ok(())
+            },
+            "QDiscard" => {
+                let w = one_wire()?;
+                self.qubit(w)?;
+                self.wires.remove(&self.wire_id(w)?);
+                Ok(())
+            },
+            "CDiscard" | "Comment" => Ok(()),
+            "QMeas" => self.unsupported("Measurements are not supported"),
+            op if op.starts_with('C') => self.unsupported(format!("Classical gate '{}' is not supported", op)),
+            op => self.parse_err(format!("Unknown statement: {}", op)),
+        }
+    }
+}
+
+/// Parse a program in Quipper's ASCII format into a [Circuit]
+///
+/// Errors give the line and column of the statement where the problem was found.
+pub fn parse(source: &str) -> Result<Circuit, ReadError> {
+    let mut p = Parser { wires: FxHashMap::default(), nqubits: 0, gates: Vec::new(), line: 0, column: 0 };
+    for (i, raw) in source.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() { continue; }
+        p.line = i + 1;
+        p.column = raw[..raw.len() - raw.trim_start().len()].chars().count() + 1;
+        p.statement(line)?;
+    }
+
+    let mut c = Circuit::new(p.nqubits);
+    for g in p.gates { c.push(g); }
+    Ok(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+    use num::complex::Complex;
+
+    /// Write a Clifford+T circuit in Quipper's format, to test against the benchmarks
+    fn to_quipper(c: &Circuit) -> String {
+        let wires: Vec<String> = (0..c.num_qubits()).map(|i| format!("{}:Qbit", i)).collect();
+        let mut s = format!("Inputs: {}\n", wires.join(", "));
+        for g in &c.gates {
+            let (name, inv) = match g.t {
+                HAD => ("H", false), NOT | CNOT | TOFF => ("not", false), Z | CZ | CCZ => ("Z", false),
+                S => ("S", false), Sdg => ("S", true), T => ("T", false), Tdg => ("T", true),
+                SWAP => ("swap", false),
+                _ => panic!("Unexpected gate: {:?}", g),
+            };
+            let (cs, ts) = if g.t == SWAP { (&g.qs[..0], &g.qs[..]) } else { g.qs.split_at(g.qs.len() - 1) };
+            let ts: Vec<String> = ts.iter().map(|q| q.to_string()).collect();
+            s += &format!("QGate[\"{}\"]{}({})", name, if inv { "*" } else { "" }, ts.join(","));
+            if !cs.is_empty() {
+                let cs: Vec<String> = cs.iter().map(|q| format!("+{}", q)).collect();
+                s += &format!(" with controls=[{}]", cs.join(","));
+            }
+            s += "\n";
+        }
+        s + &format!("Outputs: {}\n", wires.join(", "))
+    }
+
+    #[test]
+    fn benchmarks() {
+        for f in ["tof_3", "barenco_tof_4", "mod5_4", "vbe_adder_3"].iter() {
+            let c = Circuit::from_file(&format!("circuits/{}.qasm", f)).unwrap();
+            let c1 = parse(&to_quipper(&c)).unwrap();
+            assert_eq!(c, c1, "{}", f);
+        }
+    }
+
+    #[test]
+    fn fixtures() {
+        // files in Quipper's format, checked against independently written QASM, so that
+        // the order of controls, inverses and negative controls are tested
+        let c = Circuit::from_file("circuits/quipper/tof_3.quipper").unwrap();
+        let c1 = Circuit::from_file("circuits/tof_3.qasm").unwrap();
+        assert_eq!(c.num_qubits(), 5);
+        assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+
+        let c = Circuit::from_file("circuits/quipper/toffoli_ct.quipper").unwrap();
+        assert_eq!(c.stats().tcount, 7);
+        let c1 = Circuit::from_qasm("qreg q[3]; ccx q[0], q[1], q[2];").unwrap();
+        assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+
+        let c = Circuit::from_file("circuits/quipper/negative_controls.quipper").unwrap();
+        let c1 = Circuit::from_qasm(r#"
+            qreg q[3];
+            x q[0]; ccx q[0], q[1], q[2]; x q[0];
+            x q[2]; cz q[2], q[0]; x q[2];
+        "#).unwrap();
+        assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+    }
+
+    #[test]
+    fn gates() {
+        let c = parse(r#"
+            Inputs: 0:Qbit, 2:Qbit
+            Comment["ENTER: test"](0:"x", 2:"y")
+            QGate["H"](0)
+            QGate["not"](2) with controls=[-0]
+            QGate["T"]*(2) with nocontrol
+            QRot["exp(-i%Z)",0.7853981633974483](0)
+            QRot["R(2pi/%)",8.0]*(2)
+            QInit1(5)
+            QGate["multinot"](2,5)
+            QTerm0(5)
+            QDiscard(2)
+            Outputs: 0:Qbit
+        "#).unwrap();
+        assert_eq!(c.num_qubits(), 3);
+        assert_eq!(c.gates, vec![
+            Gate::new(HAD, vec![0]),
+            Gate::new(NOT, vec![0]), Gate::new(CNOT, vec![0, 1]), Gate::new(NOT, vec![0]),
+            Gate::new(Tdg, vec![1]),
+            Gate::new_with_phase(ZPhase, vec![0], Rational::new(1, 2)),
+            Gate::new_with_phase(ZPhase, vec![1], Rational::new(-1, 4)),
+            Gate::new(InitAncilla, vec![2]), Gate::new(NOT, vec![2]),
+            Gate::new(NOT, vec![1]), Gate::new(NOT, vec![2]),
+            Gate::new(PostSelect, vec![2]),
+        ]);
+    }
+
+    #[test]
+    fn controlled() {
+        // a controlled S gate with a negative control, then a controlled-controlled swap
+        let c = parse("Inputs: 0:Qbit, 1:Qbit\nQGate[\"S\"](1) with controls=[-0]").unwrap();
+        let m = c.to_matrixf();
+        let i = Complex::new(0.0, 1.0);
+        for (x, d) in [Complex::one(), i, Complex::one(), Complex::one()].iter().enumerate() {
+            assert!((m[[x, x]] - d).norm() < 1e-10, "{}", x);
+        }
+
+        let c = parse("Inputs: 0:Qbit, 1:Qbit, 2:Qbit, 3:Qbit\nQGate[\"swap\"](2,3) with controls=[+0,+1]").unwrap();
+        let m = c.to_matrixf();
+        for x in 0..16 {
+            let y = if x >> 2 == 3 { (x & 12) | ((x & 1) << 1) | ((x >> 1) & 1) } else { x };
+            for y1 in 0..16 {
+                let expected = if y1 == y { Complex::one() } else { Complex::zero() };
+                assert!((m[[y1, x]] - expected).norm() < 1e-10, "{} -> {}", x, y1);
+            }
+        }
+
+        // controlled global phases only act on the controls
+        let c = parse("Inputs: 0:Qbit, 1:Qbit\nQGate[\"omega\"](1) with controls=[+0]").unwrap();
+        assert_eq!(c.gates, vec![Gate::new_with_phase(ZPhase, vec![0], Rational::new(1, 4))]);
+        let c = parse("Inputs: 0:Qbit, 1:Qbit\nGPhase() with t=0.5 with controls=[+0,+1]").unwrap();
+        assert_eq!(c.gates.len(), 3);
+        let m = c.to_matrixf();
+        assert!((m[[3, 3]] - i).norm() < 1e-10);
+        assert!((m[[2, 2]] - Complex::one()).norm() < 1e-10);
+    }
+
+    #[test]
+    fn errors() {
+        let e = parse("Inputs: 0:Qbit\n  QGate[\"not\"](1)").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 3: Wire is not in use: 1");
+        let e = parse("Inputs: 0:Qbit, 1:Qbit\nQGate[\"H\"](1) with controls=[+0]").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Gate 'H' cannot be controlled");
+        let e = parse("Inputs: 0:Qbit\nQGate[\"W\"](0)").unwrap_err();
+        assert!(matches!(&e, ReadError::UnknownGate { line: 2, column: 1, name } if name == "W"));
+        let e = parse("Inputs: 0:Qbit, 1:Cbit\nQGate[\"not\"](0) with controls=[+1]").unwrap_err();
+        assert!(matches!(&e, ReadError::InvalidQubit { qubit, .. } if qubit == "1"));
+        assert_eq!(e.to_string(), "line 2, column 1: Classical wires are not supported: 1");
+        let e = parse("Inputs: 0:Qbit\nQMeas(0)").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Measurements are not supported");
+        let e = parse("Inputs: 0:Qbit\nQInit0(0)").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Wire is already in use: 0");
+        let ws: Vec<String> = (0..20).map(|i| format!("{}:Qbit", i)).collect();
+        let cs: Vec<String> = (1..20).map(|i| format!("+{}", i)).collect();
+        let e = parse(&format!("Inputs: {}\nQGate[\"not\"](0) with controls=[{}]", ws.join(", "), cs.join(","))).unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Gate 'not' cannot have more than 15 controls");
+        let e = parse("Inputs: 0:Qbit, 3:Qbit\nQGate[\"not\"](3) with controls=[+3]").unwrap_err();
+        assert_eq!(e.to_string(), "line 2, column 1: Wire used more than once in a gate: 3");
+        let e = parse("Inputs: 0:Qbit, 1:Qbit\nQGate[\"swap\"](0,0)").unwrap_err();
+        assert!(matches!(&e, ReadError::InvalidQubit { qubit, .. } if qubit == "0"));
+        let e = parse("Inputs: 0:Qbit, 1:Qbit\nQRot[\"R(2pi/%)\",4.0](1) with controls=[-1]").unwrap_err();
+        assert!(matches!(&e, ReadError::InvalidQubit { qubit, .. } if qubit == "1"));
+    }
+}
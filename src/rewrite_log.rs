@@ -0,0 +1,402 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tracing of rewrite rule applications
+//!
+//! A [RewriteLog] records each rule from [crate::basic_rules] applied
+//! through it, along with the vertices it was applied to and the factor it
+//! changed the scalar by. Rules can be applied one at a time with
+//! [RewriteLog::apply], or by passing the log to the `_with_log` variants of
+//! the functions in [crate::simplify]. The log can be replayed step by step on
+//! a copy of the original graph, e.g. to find the first step after which the
+//! tensor is wrong:
+//!
+//! ```
+//! # use quizx::graph::*;
+//! # use quizx::vec_graph::Graph;
+//! # use quizx::circuit::Circuit;
+//! # use quizx::tensor::*;
+//! # use quizx::rewrite_log::*;
+//! # use quizx::simplify::*;
+//! let c = Circuit::from_qasm("qreg q[2]; h q[0]; cx q[0], q[1]; s q[1]; h q[1];").unwrap();
+//! let g0: Graph = c.to_graph();
+//! let mut g = g0.clone();
+//! let mut log = RewriteLog::new();
+//! clifford_simp_with_log(&mut g, Some(&mut log));
+//!
+//! let t = g0.to_tensor4();
+//! assert_eq!(log.replay(&g0, |_, h| h.to_tensor4() == t), None);
+//! ```
+
+use std::fmt;
+use crate::graph::*;
+use crate::scalar::*;
+use crate::basic_rules::*;
+use num::traits::One;
+
+/// A rule from [crate::basic_rules] that can be recorded in a [RewriteLog]
+#[derive(Debug,Copy,Clone,PartialEq,Eq,Hash)]
+pub enum Rule {
+    SpiderFusion,
+    RemoveId,
+    ColorChange,
+    LocalComp,
+    Pivot,
+    PivotBoundary,
+    UnfuseBoundary,
+    PivotGadget,
+    GadgetFusion,
+    GadgetToPhase,
+    HBoxFusion,
+    HBoxToEdge,
+    HBoxMult,
+}
+
+impl Rule {
+    /// The name of the rule, as in [crate::basic_rules]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::SpiderFusion => "spider_fusion",
+            Rule::RemoveId => "remove_id",
+            Rule::ColorChange => "color_change",
+            Rule::LocalComp => "local_comp",
+            Rule::Pivot => "pivot",
+            Rule::PivotBoundary => "pivot_boundary",
+            Rule::UnfuseBoundary => "unfuse_boundary",
+            Rule::PivotGadget => "pivot_gadget",
+            Rule::GadgetFusion => "gadget_fusion",
+            Rule::GadgetToPhase => "gadget_to_phase",
+            Rule::HBoxFusion => "hbox_fusion",
+            Rule::HBoxToEdge => "hbox_to_edge",
+            Rule::HBoxMult => "hbox_mult",
+        }
+    }
+
+    /// The number of vertices the rule is applied to
+    pub fn arity(&self) -> usize {
+        match self {
+            Rule::RemoveId | Rule::ColorChange | Rule::LocalComp |
+            Rule::GadgetToPhase | Rule::HBoxToEdge => 1,
+            _ => 2,
+        }
+    }
+
+    /// Check the rule applies to the given vertices
+    ///
+    /// This is the corresponding `check_X` function from [crate::basic_rules],
+    /// and returns false if the wrong number of vertices is given.
+    pub fn check(&self, g: &impl GraphLike, vs: &[V]) -> bool {
+        if vs.len() != self.arity() || !vs.iter().all(|&v| g.contains_vertex(v)) {
+            return false;
+        }
+
+        match self {
+            Rule::SpiderFusion => check_spider_fusion(g, vs[0], vs[1]),
+            Rule::RemoveId => check_remove_id(g, vs[0]),
+            Rule::ColorChange => check_color_change(g, vs[0]),
+            Rule::LocalComp => check_local_comp(g, vs[0]),
+            Rule::Pivot => check_pivot(g, vs[0], vs[1]),
+            Rule::PivotBoundary => check_pivot_boundary(g, vs[0], vs[1]),
+            Rule::UnfuseBoundary => check_unfuse_boundary(g, vs[0], vs[1]),
+            Rule::PivotGadget => check_pivot_gadget(g, vs[0], vs[1]),
+            Rule::GadgetFusion => check_gadget_fusion(g, vs[0], vs[1]),
+            Rule::GadgetToPhase => check_gadget_to_phase(g, vs[0]),
+            Rule::HBoxFusion => check_hbox_fusion(g, vs[0], vs[1]),
+            Rule::HBoxToEdge => check_hbox_to_edge(g, vs[0]),
+            Rule::HBoxMult => check_hbox_mult(g, vs[0], vs[1]),
+        }
+    }
+
+    /// Apply the rule without checking it applies
+    ///
+    /// As with the `X_unsafe` functions in [crate::basic_rules], this may
+    /// make unsound changes, or panic, if [Rule::check] doesn't return true.
+    pub fn apply_unsafe(&self, g: &mut impl GraphLike, vs: &[V]) {
+        match self {
+            Rule::SpiderFusion => spider_fusion_unsafe(g, vs[0], vs[1]),
+            Rule::RemoveId => remove_id_unsafe(g, vs[0]),
+            Rule::ColorChange => color_change_unsafe(g, vs[0]),
+            Rule::LocalComp => local_comp_unsafe(g, vs[0]),
+            Rule::Pivot => pivot_unsafe(g, vs[0], vs[1]),
+            Rule::PivotBoundary => pivot_boundary_unsafe(g, vs[0], vs[1]),
+            Rule::UnfuseBoundary => unfuse_boundary_unsafe(g, vs[0], vs[1]),
+            Rule::PivotGadget => pivot_gadget_unsafe(g, vs[0], vs[1]),
+            Rule::GadgetFusion => gadget_fusion_unsafe(g, vs[0], vs[1]),
+            Rule::GadgetToPhase => gadget_to_phase_unsafe(g, vs[0]),
+            Rule::HBoxFusion => hbox_fusion_unsafe(g, vs[0], vs[1]),
+            Rule::HBoxToEdge => hbox_to_edge_unsafe(g, vs[0]),
+            Rule::HBoxMult => hbox_mult_unsafe(g, vs[0], vs[1]),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A single rule application in a [RewriteLog]
+#[derive(Debug,Clone,PartialEq)]
+pub struct RewriteStep {
+    pub rule: Rule,
+    pub vertices: Vec<V>,
+    /// The factor the rule multiplied the scalar of the graph by
+    pub scalar: ScalarN,
+}
+
+impl fmt::Display for RewriteStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{:?}, scalar {}", self.rule, self.vertices, self.scalar)
+    }
+}
+
+/// A record of the rules applied to a graph
+///
+/// Rules applied through the log are recorded in order. Other changes to the
+/// graph are not, so for [RewriteLog::replay] to make sense, the log should
+/// record everything done to the graph it started from.
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct RewriteLog {
+    steps: Vec<RewriteStep>,
+}
+
+impl RewriteLog {
+    pub fn new() -> RewriteLog { RewriteLog::default() }
+    pub fn steps(&self) -> &[RewriteStep] { &self.steps }
+    pub fn len(&self) -> usize { self.steps.len() }
+    pub fn is_empty(&self) -> bool { self.steps.is_empty() }
+
+    /// Forget all but the first `len` steps
+    ///
+    /// Use this along with [GraphLike::rollback], to drop the steps that were
+    /// undone.
+    pub fn truncate(&mut self, len: usize) { self.steps.truncate(len); }
+
+    /// Apply a rule if it matches, and record it
+    ///
+    /// Returns true if the rule was applied.
+    pub fn apply(&mut self, g: &mut impl GraphLike, rule: Rule, vs: &[V]) -> bool {
+        if !rule.check(g, vs) { return false; }
+        self.apply_unsafe(g, rule, vs);
+        true
+    }
+
+    /// Apply a rule without checking it applies, and record it
+    ///
+    /// See [Rule::apply_unsafe]. This is how the drivers in
+    /// [crate::simplify] record their rewrites.
+    pub fn apply_unsafe(&mut self, g: &mut impl GraphLike, rule: Rule, vs: &[V]) {
+        // apply the rule to a unit scalar, to see what it contributes
+        let s = std::mem::replace(g.scalar_mut(), ScalarN::one());
+        rule.apply_unsafe(g, vs);
+        let factor = std::mem::replace(g.scalar_mut(), s);
+        *g.scalar_mut() *= &factor;
+
+        self.steps.push(RewriteStep { rule, vertices: vs.to_vec(), scalar: factor });
+    }
+
+    /// Replay the log on a copy of the graph it started from
+    ///
+    /// After the i-th step, `f(i, h)` is called with the copy `h`. If it
+    /// returns false, replaying stops and `Some(i)` is returned, otherwise
+    /// the result is `None` once all steps are done. Panics if a step doesn't
+    /// apply, which means the log didn't start from `g`.
+    pub fn replay<G, F>(&self, g: &G, mut f: F) -> Option<usize>
+        where G: GraphLike + Clone,
+              F: FnMut(usize, &G) -> bool
+    {
+        let mut h = g.clone();
+        for (i, step) in self.steps.iter().enumerate() {
+            if !step.rule.check(&h, &step.vertices) {
+                panic!("Step {} ({}) does not apply", i, step);
+            }
+            step.rule.apply_unsafe(&mut h, &step.vertices);
+            if !f(i, &h) { return Some(i); }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::simplify::*;
+    use crate::tensor::*;
+    use crate::vec_graph::Graph;
+
+    fn circuit() -> Circuit {
+        Circuit::from_qasm(r#"
+            qreg q[3];
+            h q[0];
+            cx q[0], q[1];
+            s q[1];
+            cz q[1], q[2];
+            h q[2];
+            t q[0];
+            cx q[2], q[0];
+            s q[2];
+            h q[0];
+            cz q[0], q[1];
+            sdg q[0];
+            cx q[1], q[2];
+            h q[1];
+            cz q[0], q[2];
+            s q[1];
+            h q[2];
+        "#).unwrap()
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let g0: Graph = circuit().to_graph();
+        let mut g = g0.clone();
+        let mut log = RewriteLog::new();
+        let n = clifford_simp_with_log(&mut g, Some(&mut log));
+        assert!(n > 0 && log.len() >= n);
+        assert!(log.steps().iter().any(|s| s.rule == Rule::LocalComp));
+
+        let t = g0.to_tensor4();
+        assert_eq!(g.to_tensor4(), t);
+
+        // every step preserves the tensor, and the scalars of the steps
+        // multiply to the scalar of the result
+        let mut s = g0.scalar().clone();
+        let mut last = None;
+        let bad = log.replay(&g0, |i, h| {
+            s *= &log.steps()[i].scalar;
+            assert_eq!(h.scalar(), &s);
+            last = Some(h.clone());
+            h.to_tensor4() == t
+        });
+        assert_eq!(bad, None);
+        assert_eq!(last.unwrap(), g);
+    }
+
+    #[test]
+    fn stop_replay() {
+        let g0: Graph = circuit().to_graph();
+        let mut g = g0.clone();
+        let mut log = RewriteLog::new();
+        clifford_simp_with_log(&mut g, Some(&mut log));
+
+        let n = g0.num_vertices() - 3;
+        let i = log.replay(&g0, |_, h| h.num_vertices() > n).unwrap();
+        let mut h = g0.clone();
+        for step in &log.steps()[..i] {
+            assert!(log.clone().apply(&mut h, step.rule, &step.vertices));
+        }
+        assert!(h.num_vertices() > n);
+        log.steps()[i].rule.apply_unsafe(&mut h, &log.steps()[i].vertices);
+        assert!(h.num_vertices() <= n);
+    }
+
+    #[test]
+    fn apply_with_rollback() {
+        let mut g: Graph = circuit().to_graph();
+        let mut log = RewriteLog::new();
+        to_graph_like_with_log(&mut g, Some(&mut log));
+        id_simp_with_log(&mut g, Some(&mut log));
+
+        // try local complementation at each vertex, and keep it only if it
+        // doesn't increase the number of edges
+        let t = g.to_tensor4();
+        let (mut kept, mut undone) = (0, 0);
+        for v in g.vertex_vec() {
+            let ne = g.num_edges();
+            let s = g.scalar().clone();
+            let n = log.len();
+            let cp = g.checkpoint();
+            if log.apply(&mut g, Rule::LocalComp, &[v]) && g.num_edges() > ne {
+                g.rollback(cp);
+                log.truncate(n);
+                assert_eq!(g.num_edges(), ne);
+                assert_eq!(g.scalar(), &s);
+                undone += 1;
+            } else {
+                if log.len() > n { kept += 1; }
+                g.commit(cp);
+            }
+            assert_eq!(g.to_tensor4(), t);
+        }
+
+        assert!(kept > 0 && undone > 0);
+
+        let mut last = None;
+        log.replay(&circuit().to_graph(), |_, h: &Graph| { last = Some(h.clone()); true });
+        let h = last.unwrap();
+        let edges = |g: &Graph| {
+            let mut es: Vec<_> = g.edges().map(|(s,t,et)| (s.min(t), s.max(t), et)).collect();
+            es.sort();
+            es
+        };
+        assert_eq!(h.vertex_vec(), g.vertex_vec());
+        assert_eq!(edges(&h), edges(&g));
+        assert_eq!(h.scalar(), g.scalar());
+
+        assert!(!log.apply(&mut g, Rule::Pivot, &[0]));
+        assert_eq!(Rule::PivotGadget.to_string(), "pivot_gadget");
+    }
+
+    #[test]
+    fn full_reduce_steps() {
+        let c0 = Circuit::from_qasm("qreg q[3]; ccx q[0], q[1], q[2]; s q[0]; ccx q[0], q[1], q[2];").unwrap();
+        let c1 = Circuit::from_qasm(r#"
+            qreg q[3];
+            cz q[0], q[1];
+            t q[0];
+            cx q[0], q[1];
+            t q[1];
+            s q[2];
+            t q[2];
+            s q[1];
+            h q[2];
+            cz q[0], q[2];
+            h q[2];
+            cx q[1], q[2];
+            h q[2];
+        "#).unwrap();
+
+        let mut rules = vec![];
+        for c in &[c0, c1] {
+            let g0: Graph = c.to_graph();
+            let mut g = g0.clone();
+            let mut log = RewriteLog::new();
+            full_reduce_with_log(&mut g, Some(&mut log));
+            rules.extend(log.steps().iter().map(|s| s.rule));
+
+            // the same rewrites are done as without a log
+            let mut h = g0.clone();
+            full_reduce(&mut h);
+            assert_eq!(h, g);
+
+            let t = g0.to_tensorf();
+            let mut last = None;
+            let bad = log.replay(&g0, |_, h| {
+                last = Some(h.clone());
+                compare_tensors(&h.to_tensorf(), &t, 1e-10)
+            });
+            assert_eq!(bad, None);
+            assert_eq!(last.unwrap(), g);
+        }
+
+        for rule in &[Rule::PivotBoundary, Rule::PivotGadget, Rule::GadgetFusion, Rule::UnfuseBoundary] {
+            assert!(rules.contains(rule), "no {} step", rule);
+        }
+    }
+}
@@ -15,6 +15,7 @@
 // limitations under the License.
 
 use num::{integer,Integer};
+use num::traits::{CheckedAdd,CheckedMul,CheckedSub};
 use num::complex::Complex;
 use num::rational::Rational;
 pub use num::traits::identities::{Zero,One};
@@ -24,10 +25,13 @@ use approx::AbsDiffEq;
 /// A type for exact and approximate representation of complex
 /// numbers.
 ///
-/// The [Exact] representation of a scalar is given as an element of
-/// Q\[omega\], where omega is the 2N-th root of unity, represented by
-/// its first N coefficients. Addition for this type is O(N) and
-/// multiplication O(N^2).
+/// The [Exact] representation of a scalar is given as sqrt(2)^p times
+/// an element of Q\[omega\], where omega is the 2N-th root of unity,
+/// represented by its first N coefficients. Addition for this type is
+/// O(N) and multiplication O(N^2). Factors of 2 are kept in the power
+/// p rather than the coefficients, so that scalars picking up very
+/// large or small powers of sqrt(2), e.g. while simplifying big
+/// diagrams, don't overflow.
 ///
 /// The type of the coefficient list is given as a type parameter
 /// implementing a trait [Coeffs].  This is to allow fixed N (with an
@@ -38,7 +42,7 @@ use approx::AbsDiffEq;
 /// floating point [Complex] number.
 #[derive(Debug,Clone)]
 pub enum Scalar<T: Coeffs> {
-    Exact(T),
+    Exact(i32, T),
     Float(Complex<f64>),
 }
 
@@ -115,14 +119,14 @@ impl<T: Coeffs> Scalar<T> {
 
     pub fn float_value(&self) -> Complex<f64> {
         match self {
-            Exact(coeffs) => {
+            Exact(pow, coeffs) => {
                 let omega = Complex::new(-1f64, 0f64).powf(1f64 / (coeffs.len() as f64));
 
                 let mut num = Complex::new(0f64, 0f64);
                 for i in 0..coeffs.len() {
                     num += (*coeffs[i].numer() as f64 / *coeffs[i].denom() as f64) * omega.powu(i as u32);
                 }
-                num
+                num * f64::sqrt(2.0).powi(*pow)
             },
             Float(c) => *c
         }
@@ -140,6 +144,22 @@ impl<T: Coeffs> Scalar<T> {
         Float(self.float_value())
     }
 
+    /// The complex conjugate of the scalar
+    pub fn conj(&self) -> Scalar<T> {
+        match self {
+            Exact(pow, coeffs) => {
+                // conj(omega^i) = omega^(-i) = -omega^(N-i)
+                let n = coeffs.len();
+                let mut coeffs1 = coeffs.clone();
+                for i in 1..n {
+                    coeffs1[n - i] = -coeffs[i];
+                }
+                Exact(*pow, coeffs1)
+            },
+            Float(c) => Float(c.conj()),
+        }
+    }
+
     pub fn one_plus_phase(p: Rational) -> Scalar<T> {
         Scalar::one() + Scalar::from_phase(p)
     }
@@ -150,16 +170,75 @@ impl<T: Coeffs> Scalar<T> {
                 for i in 0..coeffs.len() {
                     coeffs1[i*pad] = Rational::new(coeffs[i], 1);
                 }
-                Exact(coeffs1)
+                Exact(0, coeffs1)
             },
             None => panic!("Wrong number of coefficients for scalar type")
         }
     }
+
+    /// Build an exact scalar, moving as many factors of 2 as possible from
+    /// the coefficients into the power of sqrt(2).
+    fn reduced(mut pow: i32, mut coeffs: T) -> Scalar<T> {
+        let n = coeffs.len();
+        if (0..n).all(|i| coeffs[i].is_zero()) { return Exact(0, coeffs); }
+        let two = Rational::from_integer(2);
+        while (0..n).any(|i| coeffs[i].denom().is_even()) {
+            for i in 0..n { coeffs[i] *= two; }
+            pow -= 2;
+        }
+        while (0..n).all(|i| coeffs[i].numer().is_even()) {
+            for i in 0..n { coeffs[i] /= two; }
+            pow += 2;
+        }
+        Exact(pow, coeffs)
+    }
+}
+
+/// Add the coefficients `c`, multiplied by sqrt(2)^d, to `out`
+///
+/// The length of `out` should be a multiple of the length of `c`, and also of 4 if
+/// d is odd. Returns None if the result doesn't fit in the coefficients.
+fn add_scaled<T: Coeffs>(out: &mut T, c: &T, d: i32) -> Option<()> {
+    let n = out.len();
+    let step = n / c.len();
+    if d/2 >= (isize::BITS - 1) as i32 { return None; }
+    let r = Rational::from_integer(1isize << (d/2));
+    if d % 2 == 0 {
+        for i in 0..c.len() {
+            out[i*step] = out[i*step].checked_add(&c[i].checked_mul(&r)?)?;
+        }
+    } else {
+        // sqrt(2) = omega^(n/4) - omega^(3n/4)
+        for i in 0..c.len() {
+            let x = c[i].checked_mul(&r)?;
+            for &(shift, plus) in &[(n/4, true), (3*n/4, false)] {
+                // wrapping around past omega^n = -1 flips the sign
+                let pos = i*step + shift;
+                let (pos, plus) = if pos < n { (pos, plus) } else { (pos - n, !plus) };
+                out[pos] = if plus { out[pos].checked_add(&x)? } else { out[pos].checked_sub(&x)? };
+            }
+        }
+    }
+    Some(())
+}
+
+/// Write two exact scalars as coefficient lists of the same length, times the same power
+/// of sqrt(2). Returns None if [Coeffs] doesn't allow a list of the required length, or
+/// if the coefficients overflow.
+fn align<T: Coeffs>(p0: i32, c0: &T, p1: i32, c1: &T) -> Option<(i32, T, T)> {
+    let p = i32::min(p0, p1);
+    let mut lcm = integer::lcm(c0.len(), c1.len());
+    if (p0 - p1) % 2 != 0 { lcm = integer::lcm(lcm, 4); }
+    let (mut a0, _) = T::new(lcm)?;
+    let (mut a1, _) = T::new(lcm)?;
+    add_scaled(&mut a0, c0, p0 - p)?;
+    add_scaled(&mut a1, c1, p1 - p)?;
+    Some((p, a0, a1))
 }
 
 impl<T: Coeffs> Zero for Scalar<T> {
     fn zero() -> Scalar<T> {
-        Exact(T::zero())
+        Exact(0, T::zero())
     }
 
     fn is_zero(&self) -> bool {
@@ -169,7 +248,7 @@ impl<T: Coeffs> Zero for Scalar<T> {
 
 impl<T: Coeffs> One for Scalar<T> {
     fn one() -> Scalar<T> {
-        Exact(T::one())
+        Exact(0, T::one())
     }
 
     fn is_one(&self) -> bool {
@@ -179,29 +258,7 @@ impl<T: Coeffs> One for Scalar<T> {
 
 impl<T: Coeffs> Sqrt2 for Scalar<T> {
     fn sqrt2_pow(p: i32) -> Scalar<T> {
-        match T::new(4) {
-            Some((mut coeffs,pad)) => {
-                // we use the fact that when omega = e^(i pi/4), omega - omega^3 = sqrt(2)
-
-                if p.rem_euclid(2) == 0 {
-                    // for even p, use: sqrt(2)^p = 2^(p/2)
-                    let r =
-                        if p < 0 { Rational::new(1, 1isize << -p/2) }
-                        else { Rational::new(1isize << p/2, 1) };
-                    coeffs[0] = r;
-                } else {
-                    // for odd p, use:
-                    // sqrt(2)^p = sqrt(2)^(p-1) * sqrt(2) = 2^((p-1)/2) * (omega - omega^3)
-                    let r =
-                        if p < 0 { Rational::new(1, 1isize << -(p-1)/2) }
-                        else { Rational::new(1isize << (p-1)/2, 1) };
-                    coeffs[pad] = r;
-                    coeffs[3*pad] = -r;
-                }
-                Exact(coeffs)
-            }
-            None => Float(Complex::new(2.0f64.powi(p), 0.0f64))
-        }
+        Exact(p, T::one())
     }
 }
 
@@ -221,7 +278,7 @@ impl<T: Coeffs> FromPhase for Scalar<T> {
                     Rational::one()
                 };
                 coeffs[rnumer as usize] = sgn;
-                Exact(coeffs)
+                Exact(0, coeffs)
             },
             None => {
                 let f = (*p.numer() as f64) / (*p.denom() as f64);
@@ -235,7 +292,8 @@ impl<T: Coeffs> FromPhase for Scalar<T> {
 impl<T: Coeffs> fmt::Display for Scalar<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Exact(coeffs) => {
+            Exact(pow, coeffs) => {
+                if *pow != 0 && !self.is_zero() { write!(f, "sqrt(2)^{} * (", pow)?; }
                 let mut fst = true;
                 for i in 0..coeffs.len() {
                     if !coeffs[i].is_zero() {
@@ -247,8 +305,9 @@ impl<T: Coeffs> fmt::Display for Scalar<T> {
                     }
                 }
 
-                if fst { write!(f, "0") }
-                else { Ok(()) }
+                if fst { write!(f, "0")?; }
+                if *pow != 0 && !self.is_zero() { write!(f, ")")?; }
+                Ok(())
             },
             Float(c) => write!(f, "{}", c),
         }
@@ -264,7 +323,7 @@ impl<'a, 'b, T: Coeffs> std::ops::Mul<&'b Scalar<T>> for &'a Scalar<T> {
         match (self,rhs) {
             (Float(c), x) => Float(c * x.float_value()),
             (x, Float(c)) => Float(x.float_value() * c),
-            (Exact(coeffs0), Exact(coeffs1)) => {
+            (Exact(pow0, coeffs0), Exact(pow1, coeffs1)) => {
                 let (lcm, pad0, pad1) = lcm_with_padding(coeffs0.len(), coeffs1.len());
                 match T::new(lcm) {
                     Some((mut coeffs,pad)) => {
@@ -279,7 +338,7 @@ impl<'a, 'b, T: Coeffs> std::ops::Mul<&'b Scalar<T>> for &'a Scalar<T> {
                             }
                         }
 
-                        Exact(coeffs)
+                        Scalar::reduced(pow0 + pow1, coeffs)
                     },
                     None => {
                         Float(self.float_value() * rhs.float_value())
@@ -321,23 +380,19 @@ impl<'a, 'b, T: Coeffs> std::ops::Add<&'b Scalar<T>> for &'a Scalar<T> {
         match (self,rhs) {
             (Float(c), x) => Float(c + x.float_value()),
             (x, Float(c)) => Float(x.float_value() + c),
-            (Exact(coeffs0), Exact(coeffs1)) => {
-                let (lcm, pad0, pad1) = lcm_with_padding(coeffs0.len(), coeffs1.len());
-
-                match T::new(lcm) {
-                    Some((mut coeffs, pad)) => {
-                        for i in 0..coeffs0.len() {
-                            coeffs[i*pad*pad0] += coeffs0[i];
-                        }
+            (Exact(pow0, coeffs0), Exact(pow1, coeffs1)) => {
+                if self.is_zero() { return rhs.clone(); }
+                if rhs.is_zero() { return self.clone(); }
 
-                        for i in 0..coeffs1.len() {
-                            coeffs[i*pad*pad1] += coeffs1[i];
-                        }
+                let sum = align(*pow0, coeffs0, *pow1, coeffs1).and_then(|(pow, mut coeffs, coeffs1)| {
+                    for i in 0..coeffs.len() {
+                        coeffs[i] = coeffs[i].checked_add(&coeffs1[i])?;
+                    }
+                    Some(Scalar::reduced(pow, coeffs))
+                });
 
-                        Exact(coeffs)
-                    },
-                    None => Float(self.float_value() + self.float_value())
-                }
+                // fall back to floating point if the exact sum can't be represented
+                sum.unwrap_or_else(|| Float(self.float_value() + rhs.float_value()))
             },
         }
     }
@@ -368,13 +423,13 @@ impl<T: Coeffs> FromScalar<Scalar<T>> for Complex<f64> {
 impl<S: Coeffs, T: Coeffs> FromScalar<Scalar<T>> for Scalar<S> {
     fn from_scalar(s: &Scalar<T>) -> Scalar<S> {
         match s {
-            Exact(coeffs) => {
+            Exact(pow, coeffs) => {
                 match S::new(coeffs.len()) {
                     Some((mut coeffs1, pad)) => {
                         for i in 0..coeffs.len() {
                             coeffs1[i*pad] = coeffs[i];
                         }
-                        Exact(coeffs1)
+                        Exact(*pow, coeffs1)
                     },
                     None => Float(s.float_value()),
                 }
@@ -406,16 +461,16 @@ impl<T: Coeffs> PartialEq for Scalar<T> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Float(c0), Float(c1)) => c0 == c1,
-            (Exact(coeffs0), Exact(coeffs1)) => {
-                let (lcm, pad0, pad1) = lcm_with_padding(coeffs0.len(), coeffs1.len());
-                let mut all_eq = true;
-                for i in 0..lcm {
-                    let c0 = if i % pad0 == 0 { coeffs0[i/pad0] } else { Rational::zero() };
-                    let c1 = if i % pad1 == 0 { coeffs1[i/pad1] } else { Rational::zero() };
-                    all_eq = all_eq && c0 == c1;
+            (Exact(pow0, coeffs0), Exact(pow1, coeffs1)) => {
+                let zero0 = (0..coeffs0.len()).all(|i| coeffs0[i].is_zero());
+                let zero1 = (0..coeffs1.len()).all(|i| coeffs1[i].is_zero());
+                if zero0 || zero1 { return zero0 && zero1; }
+
+                match align(*pow0, coeffs0, *pow1, coeffs1) {
+                    Some((_, coeffs0, coeffs1)) =>
+                        (0..coeffs0.len()).all(|i| coeffs0[i] == coeffs1[i]),
+                    None => self.float_value() == other.float_value()
                 }
-
-                all_eq
             },
             _ => false
         }
@@ -491,8 +546,8 @@ mod tests {
     fn mul_same_base() {
         let s = Scalar4::from_int_coeffs(&[1, 2, 3, 4]);
         let t = Scalar4::from_int_coeffs(&[4, 5, 6, 7]);
-        let st = &s * &t;
-        assert!(match st { Exact(_) => true, _ => false });
+        let st = s * t;
+        assert!(matches!(st, Exact(..)));
         assert_abs_diff_eq!(st.to_float(), s.to_float() * t.to_float());
     }
 
@@ -536,6 +591,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn large_powers() {
+        let s = ScalarN::sqrt2_pow(1000) * ScalarN::sqrt2_pow(-999);
+        assert_eq!(s, ScalarN::sqrt2());
+        assert_eq!(s, ScalarN::from_int_coeffs(&[0, 1, 0, -1]));
+        let s = ScalarN::sqrt2_pow(400) + ScalarN::sqrt2_pow(400);
+        assert_eq!(s, ScalarN::sqrt2_pow(402));
+        assert_ne!(s, ScalarN::sqrt2_pow(401));
+        let s = ScalarN::sqrt2_pow(-301) * ScalarN::from_int_coeffs(&[6, 2]);
+        assert_abs_diff_eq!(s * ScalarN::sqrt2_pow(300), Scalar::complex(6.0 / f64::sqrt(2.0), 2.0 / f64::sqrt(2.0)));
+    }
+
+    #[test]
+    fn large_gaps() {
+        let close = |s: ScalarN, z: f64| (s.float_value().re - z).abs() < 1e-10 * z;
+
+        // these powers of sqrt(2) are too far apart to align the coefficients exactly
+        let s = ScalarN::sqrt2_pow(200) + ScalarN::one();
+        assert!(close(s, 2f64.powi(100)));
+        let s = ScalarN::one() + ScalarN::sqrt2_pow(201);
+        assert!(close(s, 2f64.powf(100.5)));
+        assert_ne!(ScalarN::sqrt2_pow(130), ScalarN::one());
+        assert_ne!(ScalarN::one(), ScalarN::sqrt2_pow(-130));
+
+        // the shift fits, but multiplying it with the coefficients overflows
+        let big = ScalarN::from_int_coeffs(&[3isize.pow(30)]) * ScalarN::sqrt2_pow(80);
+        assert!(close(&big + ScalarN::one(), 3f64.powi(30) * 2f64.powi(40)));
+        assert_ne!(big, ScalarN::one());
+    }
+
+    #[test]
+    fn conjugates() {
+        let s = ScalarN::from_int_coeffs(&[1, 2, 3, 4]) * ScalarN::sqrt2_pow(-3);
+        let z = s.float_value();
+        assert_abs_diff_eq!(s.conj(), Scalar::complex(z.re, -z.im));
+        assert_eq!(ScalarN::from_phase(Rational::new(1,4)).conj(), ScalarN::from_phase(Rational::new(-1,4)));
+        assert_eq!(ScalarN::from_phase(Rational::new(2,3)).conj(), ScalarN::from_phase(Rational::new(-2,3)));
+        assert_eq!(Scalar4::complex(1.0, 2.0).conj(), Scalar4::complex(1.0, -2.0));
+    }
+
     #[test]
     fn one_plus_phases() {
         assert_abs_diff_eq!(ScalarN::one_plus_phase(Rational::new(1,1)), ScalarN::zero());
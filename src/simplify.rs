@@ -0,0 +1,520 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Simplification strategies
+//!
+//! These functions apply the rules in [crate::basic_rules] repeatedly,
+//! until no more rewrites are possible. Each one returns the number of
+//! rewrites it applied, so `0` means the graph was left unchanged.
+//!
+//! Each function also has a `_with_log` variant, which records every rule
+//! application in a [RewriteLog], so the simplification can be replayed
+//! step by step.
+
+use crate::graph::*;
+use crate::basic_rules::*;
+use crate::rewrite_log::{RewriteLog, Rule};
+use rustc_hash::FxHashMap;
+
+/// Apply a rule to every vertex where it matches, until it no longer
+/// matches anywhere
+pub fn vertex_simp<G: GraphLike>(g: &mut G,
+                                 check: impl Fn(&G, V) -> bool,
+                                 mut rule: impl FnMut(&mut G, V)) -> usize
+{
+    let mut count = 0;
+    loop {
+        let mut changed = false;
+        for v in g.vertex_vec() {
+            if g.contains_vertex(v) && check(g, v) {
+                rule(g, v);
+                count += 1;
+                changed = true;
+            }
+        }
+
+        if !changed { break; }
+    }
+
+    count
+}
+
+/// Apply a rule to every edge where it matches, until it no longer
+/// matches anywhere
+pub fn edge_simp<G: GraphLike>(g: &mut G,
+                               check: impl Fn(&G, V, V) -> bool,
+                               mut rule: impl FnMut(&mut G, V, V)) -> usize
+{
+    let mut count = 0;
+    loop {
+        let mut changed = false;
+        for (v0, v1, _) in g.edge_vec() {
+            if g.contains_vertex(v0) && g.contains_vertex(v1) && check(g, v0, v1) {
+                rule(g, v0, v1);
+                count += 1;
+                changed = true;
+            }
+        }
+
+        if !changed { break; }
+    }
+
+    count
+}
+
+/// Apply a rule without checking it, recording it if there is a log
+fn apply(g: &mut impl GraphLike, log: Option<&mut RewriteLog>, rule: Rule, vs: &[V]) {
+    match log {
+        Some(log) => log.apply_unsafe(g, rule, vs),
+        None => rule.apply_unsafe(g, vs),
+    }
+}
+
+/// Fuse all pairs of Z or X spiders connected by a normal edge
+pub fn spider_simp(g: &mut impl GraphLike) -> usize {
+    spider_simp_with_log(g, None)
+}
+
+/// [spider_simp], recording the rewrites in `log`
+pub fn spider_simp_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    edge_simp(g, check_spider_fusion,
+              |g, v0, v1| apply(g, log.as_deref_mut(), Rule::SpiderFusion, &[v0, v1]))
+}
+
+/// Remove all spiders of arity 2 with phase 0
+pub fn id_simp(g: &mut impl GraphLike) -> usize {
+    id_simp_with_log(g, None)
+}
+
+/// [id_simp], recording the rewrites in `log`
+pub fn id_simp_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    vertex_simp(g, check_remove_id,
+                |g, v| apply(g, log.as_deref_mut(), Rule::RemoveId, &[v]))
+}
+
+/// Check if v is the inner vertex of a phase gadget
+fn is_gadget_root(g: &impl GraphLike, v: V) -> bool {
+    g.neighbors(v).any(|w| g.vertex_type(w) == VType::Z && g.degree(w) == 1)
+}
+
+/// Apply local complementation wherever possible
+///
+/// Spiders next to phase gadgets are left alone, since local
+/// complementation could turn the gadget into an ordinary spider and
+/// break extraction.
+pub fn lcomp_simp(g: &mut impl GraphLike) -> usize {
+    lcomp_simp_with_log(g, None)
+}
+
+/// [lcomp_simp], recording the rewrites in `log`
+pub fn lcomp_simp_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    vertex_simp(g,
+                |g, v| check_local_comp(g, v) && !g.neighbors(v).any(|w| is_gadget_root(g, w)),
+                |g, v| apply(g, log.as_deref_mut(), Rule::LocalComp, &[v]))
+}
+
+/// Apply pivoting wherever possible
+///
+/// As with [lcomp_simp], the inner vertices of phase gadgets are never
+/// pivoted.
+pub fn pivot_simp(g: &mut impl GraphLike) -> usize {
+    pivot_simp_with_log(g, None)
+}
+
+/// [pivot_simp], recording the rewrites in `log`
+pub fn pivot_simp_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    edge_simp(g,
+              |g, v0, v1| check_pivot(g, v0, v1) &&
+                          !is_gadget_root(g, v0) && !is_gadget_root(g, v1),
+              |g, v0, v1| apply(g, log.as_deref_mut(), Rule::Pivot, &[v0, v1]))
+}
+
+/// Pivot interior Pauli spiders with boundary spiders wherever possible
+pub fn pivot_boundary_simp(g: &mut impl GraphLike) -> usize {
+    pivot_boundary_simp_with_log(g, None)
+}
+
+/// [pivot_boundary_simp], recording the rewrites in `log`
+pub fn pivot_boundary_simp_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    edge_simp(g,
+              |g, v0, v1| check_pivot_boundary(g, v0, v1) || check_pivot_boundary(g, v1, v0),
+              |g, v0, v1| {
+                  let vs = if check_pivot_boundary(g, v0, v1) { [v0, v1] } else { [v1, v0] };
+                  apply(g, log.as_deref_mut(), Rule::PivotBoundary, &vs)
+              })
+}
+
+/// Pivot interior Pauli spiders with non-Pauli spiders wherever possible
+///
+/// The non-Pauli phases are moved on to phase gadgets.
+pub fn pivot_gadget_simp(g: &mut impl GraphLike) -> usize {
+    pivot_gadget_simp_with_log(g, None)
+}
+
+/// [pivot_gadget_simp], recording the rewrites in `log`
+pub fn pivot_gadget_simp_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    edge_simp(g,
+              |g, v0, v1| check_pivot_gadget(g, v0, v1) || check_pivot_gadget(g, v1, v0),
+              |g, v0, v1| {
+                  let vs = if check_pivot_gadget(g, v0, v1) { [v0, v1] } else { [v1, v0] };
+                  apply(g, log.as_deref_mut(), Rule::PivotGadget, &vs)
+              })
+}
+
+/// Fuse phase gadgets with the same support
+///
+/// Gadgets are collected by the neighbourhoods of their roots, and any
+/// gadgets acting on a single spider are folded into its phase. Returns
+/// the number of gadgets removed.
+pub fn gadget_simp(g: &mut impl GraphLike) -> usize {
+    gadget_simp_with_log(g, None)
+}
+
+/// [gadget_simp], recording the rewrites in `log`
+pub fn gadget_simp_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    let mut count = 0;
+    loop {
+        let mut gadgets: FxHashMap<Vec<V>,V> = FxHashMap::default();
+        let mut i = 0;
+        for l in g.vertex_vec() {
+            if !g.contains_vertex(l) ||
+               g.vertex_type(l) != VType::Z ||
+               g.degree(l) != 1 { continue; }
+            let v = g.neighbors(l).next().unwrap();
+            if check_gadget_to_phase(g, v) {
+                apply(g, log.as_deref_mut(), Rule::GadgetToPhase, &[v]);
+                i += 1;
+                continue;
+            }
+
+            let mut ns: Vec<V> = g.neighbors(v).filter(|&w| w != l).collect();
+            ns.sort_unstable();
+            if let Some(&v0) = gadgets.get(&ns) {
+                if check_gadget_fusion(g, v0, v) {
+                    apply(g, log.as_deref_mut(), Rule::GadgetFusion, &[v0, v]);
+                    i += 1;
+                    continue;
+                }
+            }
+            gadgets.insert(ns, v);
+        }
+
+        if i == 0 { break; }
+        count += i;
+    }
+
+    count
+}
+
+/// Make sure no spider is adjacent to more than one boundary
+///
+/// For each spider with several boundaries, all but one of them are moved
+/// on to a new phase-free spider. Returns the number of spiders added.
+fn separate_boundaries(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    let mut count = 0;
+    for v in g.vertex_vec() {
+        if g.vertex_type(v) != VType::Z { continue; }
+        let bs: Vec<V> = g.neighbors(v)
+            .filter(|&w| g.vertex_type(w) == VType::B)
+            .collect();
+
+        for &b in bs.iter().skip(1) {
+            apply(g, log.as_deref_mut(), Rule::UnfuseBoundary, &[v, b]);
+            count += 1;
+        }
+    }
+
+    count
+}
+
+/// Bring a ZX-diagram into graph-like form
+///
+/// Afterwards, all spiders are Z spiders connected by Hadamard
+/// edges, and every spider is adjacent to at most one boundary. Returns
+/// the number of spiders fused, plus the number of new spiders added to
+/// separate boundaries.
+pub fn to_graph_like(g: &mut impl GraphLike) -> usize {
+    to_graph_like_with_log(g, None)
+}
+
+/// [to_graph_like], recording the rewrites in `log`
+///
+/// This is the same as [GraphLike::x_to_z], but with each X spider turned
+/// into a Z spider by a logged [Rule::ColorChange].
+pub fn to_graph_like_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    for v in g.vertex_vec() {
+        if g.vertex_type(v) == VType::X {
+            apply(g, log.as_deref_mut(), Rule::ColorChange, &[v]);
+        }
+    }
+    spider_simp_with_log(g, log.as_deref_mut()) + separate_boundaries(g, log)
+}
+
+/// Simplify the interior of a graph-like diagram with Clifford rules
+///
+/// This removes all interior spiders with phase a multiple of pi/2,
+/// except possibly some that are adjacent to phase gadgets. Phase-free
+/// spiders next to a boundary are kept, so the result stays graph-like.
+pub fn interior_clifford_simp(g: &mut impl GraphLike) -> usize {
+    interior_clifford_simp_with_log(g, None)
+}
+
+/// [interior_clifford_simp], recording the rewrites in `log`
+pub fn interior_clifford_simp_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    let mut count = to_graph_like_with_log(g, log.as_deref_mut());
+    loop {
+        let i =
+            vertex_simp(g, |g, v| check_remove_id(g, v) &&
+                                  g.neighbors(v).all(|w| g.vertex_type(w) != VType::B),
+                        |g, v| apply(g, log.as_deref_mut(), Rule::RemoveId, &[v])) +
+            spider_simp_with_log(g, log.as_deref_mut()) +
+            pivot_simp_with_log(g, log.as_deref_mut()) +
+            lcomp_simp_with_log(g, log.as_deref_mut()) +
+            separate_boundaries(g, log.as_deref_mut());
+        if i == 0 { break; }
+        count += i;
+    }
+
+    count
+}
+
+/// Simplify a diagram with Clifford rules
+///
+/// As well as [interior_clifford_simp], this removes interior Pauli
+/// spiders adjacent to the boundary by pivoting.
+pub fn clifford_simp(g: &mut impl GraphLike) -> usize {
+    clifford_simp_with_log(g, None)
+}
+
+/// [clifford_simp], recording the rewrites in `log`
+pub fn clifford_simp_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    let mut count = 0;
+    loop {
+        count += interior_clifford_simp_with_log(g, log.as_deref_mut());
+        let i = pivot_boundary_simp_with_log(g, log.as_deref_mut());
+        if i == 0 { break; }
+        count += i;
+    }
+
+    count
+}
+
+/// Simplify a diagram as much as possible
+///
+/// For diagrams coming from circuits, the result is a graph-like diagram
+/// whose only interior spiders have non-Clifford phases, or are part of a
+/// phase gadget.
+pub fn full_reduce(g: &mut impl GraphLike) -> usize {
+    full_reduce_with_log(g, None)
+}
+
+/// [full_reduce], recording the rewrites in `log`
+pub fn full_reduce_with_log(g: &mut impl GraphLike, mut log: Option<&mut RewriteLog>) -> usize {
+    let mut count = interior_clifford_simp_with_log(g, log.as_deref_mut());
+    count += pivot_gadget_simp_with_log(g, log.as_deref_mut());
+    loop {
+        count += clifford_simp_with_log(g, log.as_deref_mut());
+        let i = gadget_simp_with_log(g, log.as_deref_mut());
+        count += interior_clifford_simp_with_log(g, log.as_deref_mut());
+        let j = pivot_gadget_simp_with_log(g, log.as_deref_mut());
+        if i + j == 0 { break; }
+        count += i + j;
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use num::Rational;
+    use crate::tensor::ToTensor;
+    use crate::vec_graph::Graph;
+
+    fn test_circuit() -> Circuit {
+        Circuit::from_qasm(r#"
+            qreg q[4];
+            cx q[0], q[1];
+            h q[1];
+            s q[2];
+            cz q[1], q[2];
+            cx q[3], q[0];
+            h q[3];
+            t q[1];
+            x q[2];
+            cx q[2], q[3];
+            sdg q[0];
+            h q[0];
+            cz q[0], q[3];
+            tdg q[3];
+            cx q[1], q[0];
+            h q[2];
+        "#).unwrap()
+    }
+
+    #[test]
+    fn spider_simp_chain() {
+        let mut g = Graph::new();
+        let b0 = g.add_vertex(VType::B);
+        let mut prev = b0;
+        for _ in 0..10 {
+            let v = g.add_vertex(VType::Z);
+            g.set_phase(v, Rational::new(1,4));
+            g.add_edge(prev, v);
+            prev = v;
+        }
+        let b1 = g.add_vertex(VType::B);
+        g.add_edge(prev, b1);
+        g.set_inputs(vec![b0]);
+        g.set_outputs(vec![b1]);
+
+        let h = g.clone();
+        assert_eq!(spider_simp(&mut g), 9);
+        assert_eq!(g.num_vertices(), 3);
+        assert_eq!(spider_simp(&mut g), 0);
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+    }
+
+    #[test]
+    fn id_simp_chain() {
+        let mut g = Graph::new();
+        let b0 = g.add_vertex(VType::B);
+        let mut prev = b0;
+        for i in 0..6 {
+            let v = g.add_vertex(VType::Z);
+            g.add_edge_with_type(prev, v, if i % 2 == 0 { EType::H } else { EType::N });
+            prev = v;
+        }
+        let b1 = g.add_vertex(VType::B);
+        g.add_edge(prev, b1);
+        g.set_inputs(vec![b0]);
+        g.set_outputs(vec![b1]);
+
+        let h = g.clone();
+        assert_eq!(id_simp(&mut g), 6);
+        assert_eq!(g.num_vertices(), 2);
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+    }
+
+    #[test]
+    fn graph_like() {
+        let c = test_circuit();
+        let mut g: Graph = c.to_graph();
+        let h = g.clone();
+        to_graph_like(&mut g);
+
+        for v in g.vertices() {
+            let t = g.vertex_type(v);
+            assert!(t == VType::B || t == VType::Z);
+            if t == VType::Z {
+                assert!(g.neighbors(v).filter(|&w| g.vertex_type(w) == VType::B).count() <= 1);
+            }
+        }
+
+        for (v0, v1, et) in g.edges() {
+            if g.vertex_type(v0) == VType::Z && g.vertex_type(v1) == VType::Z {
+                assert_eq!(et, EType::H);
+            }
+        }
+
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+    }
+
+    #[test]
+    fn lcomp_pivot_simp() {
+        let c = test_circuit();
+        let mut g: Graph = c.to_graph();
+        to_graph_like(&mut g);
+        let h = g.clone();
+        let i = lcomp_simp(&mut g) + pivot_simp(&mut g);
+        assert!(i > 0);
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+    }
+
+    #[test]
+    fn clifford_simp_circuit() {
+        let c = test_circuit();
+        let mut g: Graph = c.to_graph();
+        let n = g.num_vertices();
+        assert!(clifford_simp(&mut g) > 0);
+        assert!(g.num_vertices() < n);
+        assert_eq!(clifford_simp(&mut g), 0);
+        assert_eq!(c.to_tensor4(), g.to_tensor4());
+    }
+
+    #[test]
+    fn gadget_simp_support() {
+        let mut g = Graph::new();
+        let mut inputs = vec![];
+        let mut outputs = vec![];
+        let mut vs = vec![];
+        for _ in 0..3 {
+            let i = g.add_vertex(VType::B);
+            let v = g.add_vertex(VType::Z);
+            let o = g.add_vertex(VType::B);
+            g.add_edge(i, v);
+            g.add_edge(v, o);
+            inputs.push(i);
+            outputs.push(o);
+            vs.push(v);
+        }
+        g.set_inputs(inputs);
+        g.set_outputs(outputs);
+
+        let supports = [vec![vs[0], vs[1]], vec![vs[1], vs[2]], vec![vs[1], vs[0]], vec![vs[2]]];
+        for (k, support) in supports.iter().enumerate() {
+            let r = g.add_vertex(VType::Z);
+            let l = g.add_vertex(VType::Z);
+            g.set_phase(l, Rational::new(2 * k as isize + 1, 4));
+            g.add_edge_with_type(r, l, EType::H);
+            for &v in support { g.add_edge_with_type(r, v, EType::H); }
+        }
+
+        let h = g.clone();
+        assert_eq!(gadget_simp(&mut g), 2);
+        assert_eq!(g.num_vertices(), 13);
+        assert_eq!(gadget_simp(&mut g), 0);
+        assert_eq!(g.to_tensor4(), h.to_tensor4());
+    }
+
+    #[test]
+    fn full_reduce_toffolis() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[4];
+            h q[3];
+            ccx q[0], q[1], q[3];
+            h q[1];
+            ccx q[2], q[3], q[1];
+            t q[0];
+            cx q[1], q[2];
+            ccz q[0], q[2], q[3];
+            h q[0];
+        "#).unwrap();
+
+        let mut g: Graph = c.to_graph();
+        full_reduce(&mut g);
+        assert_eq!(c.to_tensor4(), g.to_tensor4());
+    }
+
+    #[test]
+    fn full_reduce_hash_graph() {
+        let c = test_circuit();
+        let mut g: crate::hash_graph::Graph = c.to_graph();
+        full_reduce(&mut g);
+        assert_eq!(c.to_tensor4(), g.to_tensor4());
+    }
+}
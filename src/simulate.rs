@@ -0,0 +1,525 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exact simulation by stabilizer decompositions
+//!
+//! Plugging all of the inputs and outputs of a circuit with basis states gives a
+//! closed diagram, whose scalar is an amplitude of the circuit. Clifford rewriting
+//! removes everything but the non-Clifford spiders, which are then split into a sum
+//! of diagrams with fewer T-spiders, each of which gets simplified again. For T-spiders,
+//! this uses the 2-term decomposition of |T>|T> from:
+//!
+//! S. Bravyi, G. Smith, J. Smolin. Trading classical and quantum computational
+//! resources. PRX 2016
+//!
+//! as well as 2-term decompositions of 3 and 4 T-spiders around a Pauli spider, i.e.
+//! T's applied to a cat state, as in:
+//!
+//! A. Kissinger, J. van de Wetering, R. Vandaele. Classical simulation of quantum
+//! circuits with partial and graphical stabiliser decompositions. TQC 2022
+//!
+//! All of the scalars are computed exactly, so the result is an exact [ScalarN].
+
+use num::{Rational, Zero, One};
+use crate::graph::*;
+use crate::scalar::*;
+use crate::circuit::Circuit;
+use crate::simplify::{full_reduce, clifford_simp};
+use crate::tensor::ToTensor;
+use crate::vec_graph::Graph;
+use rustc_hash::FxHashMap;
+
+/// Replace boundaries by computational basis states
+///
+/// Boundary `bs[i]` becomes |1> if `bits[i]` is true and |0> otherwise.
+fn plug<G: GraphLike>(g: &mut G, bs: &[V], bits: &[bool]) {
+    if bs.len() != bits.len() {
+        panic!("Expected {} bits, got {}", bs.len(), bits.len());
+    }
+
+    // a 1-legged X spider with phase b*pi is sqrt(2) |b>
+    for (&v, &b) in bs.iter().zip(bits) {
+        g.set_vertex_type(v, VType::X);
+        g.set_phase(v, if b { Rational::one() } else { Rational::zero() });
+    }
+    g.scalar_mut().mul_sqrt2_pow(-(bs.len() as i32));
+}
+
+/// Plug every input of a graph with the basis state |bits>
+///
+/// Afterwards, the graph has no inputs.
+pub fn plug_inputs<G: GraphLike>(g: &mut G, bits: &[bool]) {
+    let inputs = g.inputs().clone();
+    plug(g, &inputs, bits);
+    g.set_inputs(vec![]);
+}
+
+/// Plug every output of a graph with the basis effect <bits|
+///
+/// Afterwards, the graph has no outputs.
+pub fn plug_outputs<G: GraphLike>(g: &mut G, bits: &[bool]) {
+    let outputs = g.outputs().clone();
+    plug(g, &outputs, bits);
+    g.set_outputs(vec![]);
+}
+
+fn is_t_like(p: Rational) -> bool { *p.denom() == 4 }
+
+fn is_clifford(p: Rational) -> bool { *p.denom() <= 2 }
+
+/// Remove every spider without neighbours, multiplying the scalar by its value
+fn remove_isolated<G: GraphLike>(g: &mut G) {
+    let isolated: Vec<V> = g.vertices().filter(|&v| g.degree(v) == 0).collect();
+    for v in isolated {
+        let p = g.phase(v);
+        *g.scalar_mut() *= ScalarN::one_plus_phase(p);
+        g.remove_vertex(v);
+    }
+}
+
+/// Find a Pauli spider whose 3 or 4 neighbours are all T-like, preferring 4
+fn find_cat<G: GraphLike>(g: &G) -> Option<V> {
+    let mut best = None;
+    for v in g.vertices() {
+        let d = g.degree(v);
+        if *g.phase(v).denom() == 1 && (d == 3 || d == 4) &&
+           g.incident_edges(v).all(|(w,et)| et == EType::H && w != v && is_t_like(g.phase(w)))
+        {
+            if d == 4 { return Some(v); }
+            best = best.or(Some(v));
+        }
+    }
+    best
+}
+
+/// Split a graph whose T-spiders hang off the Pauli spider `v` into 2 terms
+///
+/// Unfusing the T phases from the neighbours leaves T's applied to a cat state.
+/// With parity a, the phase of v, this state is sum_{|x| = a mod 2} e^{i pi |x|/4} |x>.
+/// When the weights |x| only take two values w and w+2, it is a combination of the cat
+/// state with the T's replaced by identities and by S gates. This leaves one case,
+/// 4 neighbours and a = 0, where we instead combine the cat state with a GHZ state.
+fn cat_terms<G: GraphLike + Clone>(g: &G, v: V) -> Vec<G> {
+    let ns = g.neighbor_vec(v);
+    let a = *g.phase(v).numer();
+    let mut g0 = g.clone();
+    let mut g1 = g.clone();
+    for &w in &ns {
+        g0.add_to_phase(w, Rational::new(-1,4));
+    }
+
+    if ns.len() == 4 && a == 0 {
+        *g0.scalar_mut() *= ScalarN::from_phase(Rational::new(1,2));
+
+        for &w in &ns { g1.add_to_phase(w, Rational::new(-1,4)); }
+        g1.remove_vertex(v);
+        let ghz = g1.add_vertex(VType::Z);
+        g1.set_phase(ghz, Rational::new(-1,2));
+        for &w in &ns { g1.add_edge(ghz, w); }
+        *g1.scalar_mut() *= ScalarN::from_phase(Rational::new(-1,4));
+        g1.scalar_mut().mul_sqrt2_pow(-1);
+    } else {
+        // the smallest weight of the right parity is w = a
+        *g0.scalar_mut() *= ScalarN::from_phase(Rational::new(a + 1, 4));
+        g0.scalar_mut().mul_sqrt2_pow(-1);
+
+        for &w in &ns { g1.add_to_phase(w, Rational::new(1,4)); }
+        *g1.scalar_mut() *= ScalarN::from_phase(Rational::new(-a - 1, 4));
+        g1.scalar_mut().mul_sqrt2_pow(-1);
+    }
+
+    vec![g0, g1]
+}
+
+/// Split a graph with T-spiders `v0` and `v1` into 2 terms
+///
+/// This uses |T>|T> = (|00> + i|11>) + e^{i pi/4} (|01> + |10>). The first term
+/// connects v0 and v1 via a spider with phase pi/2 and the second via a NOT.
+fn pair_terms<G: GraphLike + Clone>(g: &G, v0: V, v1: V) -> Vec<G> {
+    let mut g0 = g.clone();
+    g0.add_to_phase(v0, Rational::new(-1,4));
+    g0.add_to_phase(v1, Rational::new(-1,4));
+    let mut g1 = g0.clone();
+
+    let s = g0.add_vertex(VType::Z);
+    g0.set_phase(s, Rational::new(1,2));
+    g0.add_edge(v0, s);
+    g0.add_edge(s, v1);
+
+    let x = g1.add_vertex(VType::Z);
+    g1.set_phase(x, Rational::one());
+    g1.add_edge_with_type(v0, x, EType::H);
+    g1.add_edge_with_type(x, v1, EType::H);
+    *g1.scalar_mut() *= ScalarN::from_phase(Rational::new(1,4));
+
+    vec![g0, g1]
+}
+
+/// Split a graph into 2 terms by fixing the value of the spider `v`
+///
+/// Setting v to |b> removes it, and adds b*pi to every neighbour, since these
+/// are connected by Hadamard edges.
+fn cut_terms<G: GraphLike + Clone>(g: &G, v: V) -> Vec<G> {
+    let ns = g.neighbor_vec(v);
+    let p = g.phase(v);
+    let mut g0 = g.clone();
+    g0.remove_vertex(v);
+    g0.scalar_mut().mul_sqrt2_pow(-(ns.len() as i32));
+    let mut g1 = g0.clone();
+    for &w in &ns { g1.add_to_phase(w, Rational::one()); }
+    *g1.scalar_mut() *= ScalarN::from_phase(p);
+
+    vec![g0, g1]
+}
+
+/// Split a reduced, graph-like diagram into a sum of simpler diagrams
+fn decompose<G: GraphLike + Clone>(g: &G) -> Vec<G> {
+    if let Some(v) = find_cat(g) {
+        return cat_terms(g, v);
+    }
+
+    let ts: Vec<V> = g.vertices().filter(|&v| is_t_like(g.phase(v))).take(2).collect();
+    if ts.len() == 2 {
+        return pair_terms(g, ts[0], ts[1]);
+    }
+
+    // otherwise, cut a non-Clifford spider, or failing that, the biggest one
+    let v = g.vertices().find(|&v| !is_clifford(g.phase(v)))
+        .or_else(|| g.vertices().max_by_key(|&v| g.degree(v)))
+        .expect("Graph should be non-empty");
+    cut_terms(g, v)
+}
+
+/// Compute the scalar of a closed diagram exactly
+///
+/// The diagram should have no boundaries or H-boxes. This includes the scalar
+/// already stored in the graph.
+pub fn evaluate<G: GraphLike + Clone>(g: &G) -> ScalarN {
+    if g.vertices().any(|v| g.vertex_type(v) == VType::B) {
+        panic!("Can only evaluate graphs without boundaries");
+    }
+
+    let mut sum = ScalarN::zero();
+    let mut stack = vec![g.clone()];
+    while let Some(mut h) = stack.pop() {
+        full_reduce(&mut h);
+        remove_isolated(&mut h);
+        if h.scalar().is_zero() { continue; }
+        if h.num_vertices() == 0 {
+            sum = sum + h.scalar();
+        } else {
+            stack.extend(decompose(&h));
+        }
+    }
+
+    sum
+}
+
+/// Split the vertices of a graph into connected components
+fn components<G: GraphLike>(g: &G) -> Vec<Vec<V>> {
+    let mut seen: FxHashMap<V,()> = FxHashMap::default();
+    let mut comps = vec![];
+    for v in g.vertices() {
+        if seen.insert(v, ()).is_some() { continue; }
+        let mut comp = vec![v];
+        let mut i = 0;
+        while i < comp.len() {
+            for w in g.neighbors(comp[i]) {
+                if seen.insert(w, ()).is_none() { comp.push(w); }
+            }
+            i += 1;
+        }
+        comps.push(comp);
+    }
+    comps
+}
+
+/// Compute the scalar of a connected piece of a closed diagram
+///
+/// Lone spiders are evaluated directly, and everything else by contracting
+/// a copy of the piece with [Scalar4] entries. This is exact as long as the
+/// phases are multiples of pi/4.
+fn eval_component<G: GraphLike>(g: &G, comp: &[V]) -> ScalarN {
+    if comp.len() == 1 {
+        let v = comp[0];
+        if g.vertex_type(v) == VType::Z || g.vertex_type(v) == VType::X {
+            return ScalarN::one_plus_phase(g.phase(v));
+        }
+    }
+
+    let mut h = Graph::new();
+    let mut vmap: FxHashMap<V,V> = FxHashMap::default();
+    for &v in comp {
+        let w = h.add_vertex_with_data(VData {
+            ty: g.vertex_type(v),
+            phase: g.phase(v),
+            qubit: g.qubit(v),
+            row: g.row(v),
+        });
+        vmap.insert(v, w);
+    }
+    for &v in comp {
+        for (w, et) in g.incident_edges(v) {
+            if v < w { h.add_edge_with_type(vmap[&v], vmap[&w], et); }
+        }
+    }
+
+    let t = h.to_tensor4();
+    ScalarN::from_scalar(t.iter().next().expect("Expected a scalar"))
+}
+
+/// Evaluate a closed diagram by simplification and tensor contraction
+///
+/// The diagram is first simplified with [clifford_simp], after which it
+/// often falls apart into small pieces, e.g. thanks to the Hopf rule. These
+/// are evaluated separately, so the cost is exponential only in the size of the
+/// biggest non-Clifford piece. The result includes the scalar already stored in
+/// the graph. See [evaluate] for diagrams with many T-spiders.
+pub fn eval_scalar<G: GraphLike + Clone>(g: &G) -> ScalarN {
+    if g.vertices().any(|v| g.vertex_type(v) == VType::B) {
+        panic!("Can only evaluate graphs without boundaries");
+    }
+
+    let mut h = g.clone();
+    clifford_simp(&mut h);
+    let mut s = h.scalar().clone();
+    for comp in components(&h) {
+        if s.is_zero() { break; }
+        s *= eval_component(&h, &comp);
+    }
+    s
+}
+
+/// Compute the amplitude <output|C|input> of a circuit exactly
+pub fn amplitude(c: &Circuit, input: &[bool], output: &[bool]) -> ScalarN {
+    let mut g: Graph = c.to_graph();
+    plug_inputs(&mut g, input);
+    plug_outputs(&mut g, output);
+    evaluate(&g)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::Complex;
+
+    fn bits(n: usize, x: usize) -> Vec<bool> {
+        (0..n).map(|i| x & (1 << (n - 1 - i)) != 0).collect()
+    }
+
+    /// Check every amplitude of a small circuit against its tensor
+    fn check_amplitudes(c: &Circuit) {
+        let n = c.num_qubits();
+        let t = c.to_tensorf();
+        for x in 0..(1 << n) {
+            for y in 0..(1 << n) {
+                let ix: Vec<usize> = bits(n, x).iter().chain(bits(n, y).iter())
+                    .map(|&b| b as usize).collect();
+                let a = amplitude(c, &bits(n, x), &bits(n, y));
+                assert!(close(a.float_value(), t[&ix[..]]));
+            }
+        }
+    }
+
+    fn close(a: Complex<f64>, b: Complex<f64>) -> bool {
+        (a - b).norm() < 1e-10
+    }
+
+    fn closed_scalar(g: &Graph) -> Complex<f64> {
+        *g.to_tensorf().iter().next().unwrap()
+    }
+
+    #[test]
+    fn plugging() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[2];
+            h q[0];
+            cx q[0], q[1];
+        "#).unwrap();
+
+        let mut g: Graph = c.to_graph();
+        plug_inputs(&mut g, &[false, false]);
+        assert!(g.inputs().is_empty());
+        assert_eq!(g.outputs().len(), 2);
+        let t = g.to_tensorf();
+        let h = Complex::new(1.0 / f64::sqrt(2.0), 0.0);
+        assert!(close(t[[0,0]], h));
+        assert!(close(t[[1,1]], h));
+        assert!(close(t[[0,1]], Complex::zero()));
+
+        plug_outputs(&mut g, &[true, true]);
+        assert!(close(evaluate(&g).float_value(), h));
+    }
+
+    #[test]
+    fn small_circuits() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[3];
+            h q[0];
+            t q[0];
+            cx q[0], q[1];
+            t q[1];
+            h q[2];
+            cx q[1], q[2];
+            tdg q[2];
+            h q[1];
+            t q[1];
+            cx q[2], q[0];
+            t q[0];
+        "#).unwrap();
+        check_amplitudes(&c);
+
+        let c = Circuit::from_qasm(r#"
+            qreg q[3];
+            h q[2];
+            ccx q[0], q[1], q[2];
+            h q[0];
+            ccz q[2], q[0], q[1];
+            rz(0.3*pi) q[1];
+            h q[1];
+        "#).unwrap();
+        check_amplitudes(&c);
+    }
+
+    #[test]
+    fn decompositions() {
+        // a Pauli spider with 3 or 4 T-like neighbours, each attached to a spider
+        // with a generic phase, so the cat decompositions are used first
+        for &(n, a) in &[(3, 0), (3, 1), (4, 0), (4, 1)] {
+            let mut g = Graph::new();
+            let hub = g.add_vertex(VType::Z);
+            g.set_phase(hub, Rational::new(a, 1));
+            for i in 0..n {
+                let t = g.add_vertex(VType::Z);
+                g.set_phase(t, Rational::new(2 * i as isize + 1, 4));
+                g.add_edge_with_type(hub, t, EType::H);
+                let u = g.add_vertex(VType::Z);
+                g.set_phase(u, Rational::new(1, 3 + i as isize));
+                g.add_edge_with_type(t, u, EType::H);
+            }
+            assert_eq!(find_cat(&g), Some(hub));
+            for h in cat_terms(&g, hub) {
+                assert_eq!(find_cat(&h), None);
+            }
+            let s: ScalarN = cat_terms(&g, hub).iter()
+                .fold(ScalarN::zero(), |s, h| s + evaluate(h));
+            assert!(close(s.float_value(), closed_scalar(&g)));
+            assert!(close(evaluate(&g).float_value(), closed_scalar(&g)));
+        }
+
+        // two T-spiders in a triangle with a Clifford spider
+        let mut g = Graph::new();
+        let vs: Vec<V> = (0..3).map(|_| g.add_vertex(VType::Z)).collect();
+        g.set_phase(vs[0], Rational::new(1,4));
+        g.set_phase(vs[1], Rational::new(7,4));
+        g.set_phase(vs[2], Rational::new(1,2));
+        g.add_edge_with_type(vs[0], vs[1], EType::H);
+        g.add_edge_with_type(vs[1], vs[2], EType::H);
+        g.add_edge_with_type(vs[2], vs[0], EType::H);
+        let s: ScalarN = pair_terms(&g, vs[0], vs[1]).iter()
+            .fold(ScalarN::zero(), |s, h| s + evaluate(h));
+        assert!(close(s.float_value(), closed_scalar(&g)));
+        let s: ScalarN = cut_terms(&g, vs[2]).iter()
+            .fold(ScalarN::zero(), |s, h| s + evaluate(h));
+        assert!(close(s.float_value(), closed_scalar(&g)));
+    }
+
+    #[test]
+    fn closed_diagrams() {
+        // lone spiders, including a pair disconnected by the Hopf rule
+        let mut g = Graph::new();
+        let z = g.add_vertex(VType::Z);
+        let x = g.add_vertex(VType::X);
+        g.set_phase(z, Rational::new(1,3));
+        g.set_phase(x, Rational::new(1,2));
+        g.add_edge_smart(z, x, EType::N);
+        g.add_edge_smart(z, x, EType::N);
+        assert_eq!(g.num_edges(), 0);
+        let v = g.add_vertex(VType::X);
+        g.set_phase(v, Rational::one());
+        let mut expected = ScalarN::one_plus_phase(Rational::new(1,3)) *
+            ScalarN::one_plus_phase(Rational::new(1,2));
+        expected.mul_sqrt2_pow(-2);
+        assert_eq!(g.eval_scalar(), ScalarN::zero());
+        g.remove_vertex(v);
+        assert_eq!(g.eval_scalar(), expected);
+
+        let c = Circuit::from_qasm(r#"
+            qreg q[3];
+            h q[0];
+            t q[0];
+            cx q[0], q[1];
+            ccz q[0], q[1], q[2];
+            h q[2];
+            t q[2];
+            cx q[2], q[1];
+        "#).unwrap();
+        for y in 0..8 {
+            let mut g: Graph = c.to_graph();
+            plug_inputs(&mut g, &[false, true, false]);
+            plug_outputs(&mut g, &bits(3, y));
+            let s = g.eval_scalar();
+            assert_eq!(s, evaluate(&g));
+            assert!(close(s.float_value(), closed_scalar(&g)));
+
+            // two disconnected copies
+            let mut h = g.clone();
+            let mut vmap: FxHashMap<V,V> = FxHashMap::default();
+            for v in g.vertices() {
+                let w = h.add_vertex(g.vertex_type(v));
+                h.set_phase(w, g.phase(v));
+                vmap.insert(v, w);
+            }
+            for (v, w, et) in g.edges() { h.add_edge_with_type(vmap[&v], vmap[&w], et); }
+            *h.scalar_mut() *= g.scalar();
+            assert_eq!(components(&h).len(), 2 * components(&g).len());
+            assert_eq!(h.eval_scalar(), &s * &s);
+        }
+    }
+
+    #[test]
+    fn exact_amplitudes() {
+        // GHZ state with a T on every qubit: <1..1|C|0..0> = e^{i pi n/4} / sqrt(2)
+        let n = 50;
+        let mut c = Circuit::new(n);
+        c.add_gate("h", vec![0]);
+        for q in 1..n { c.add_gate("cx", vec![q-1, q]); }
+        for q in 0..n { c.add_gate("t", vec![q]); }
+        let mut expected = ScalarN::from_phase(Rational::new(n as isize, 4));
+        expected.mul_sqrt2_pow(-1);
+        assert_eq!(amplitude(&c, &vec![false; n], &vec![true; n]), expected);
+        let mut out = vec![true; n];
+        out[17] = false;
+        assert_eq!(amplitude(&c, &vec![false; n], &out), ScalarN::zero());
+
+        // U followed by its adjoint, with U acting on 60 qubits
+        let n = 60;
+        let mut c = Circuit::new(n);
+        for i in 0..24 {
+            let q = (7 * i) % n;
+            c.add_gate("h", vec![q]);
+            c.add_gate("t", vec![q]);
+            c.add_gate("cx", vec![q, (q + 11) % n]);
+            if i % 4 == 0 { c.add_gate("ccz", vec![q, (q + 1) % n, (q + 5) % n]); }
+        }
+        let c = &c + c.to_adjoint();
+        let zero = vec![false; n];
+        assert_eq!(amplitude(&c, &zero, &zero), ScalarN::one());
+        let mut out = vec![false; n];
+        out[3] = true;
+        assert_eq!(amplitude(&c, &zero, &out), ScalarN::zero());
+    }
+}
@@ -0,0 +1,362 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stabilizer tableaux of Clifford circuits
+//!
+//! A Clifford unitary U is determined, up to a global phase, by where it sends each
+//! X_i and Z_i under conjugation. These are Pauli strings with a sign, and they get
+//! updated in linear time for each gate, following:
+//!
+//! S. Aaronson, D. Gottesman. Improved simulation of stabilizer circuits. PRA 2004
+//!
+//! This makes checking equality of Clifford circuits polynomial in the number of qubits,
+//! rather than exponential as for tensors.
+
+use std::fmt;
+use num::Rational;
+use crate::circuit::Circuit;
+use crate::gate::*;
+use crate::linalg::*;
+
+/// An error raised when building a tableau from a circuit
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub enum TableauError {
+    /// The circuit contains a gate which isn't Clifford, so it has no tableau
+    NotClifford { gate: Gate },
+}
+
+impl fmt::Display for TableauError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableauError::NotClifford { gate } =>
+                write!(f, "Gate is not Clifford: {}", gate.to_qasm()),
+        }
+    }
+}
+
+impl std::error::Error for TableauError {}
+
+/// The tableau of a Clifford unitary U
+///
+/// Row i, for i < n, is the Pauli string U X_i U^dag, and row n + i is U Z_i U^dag. Each row is
+/// stored as an X part and a Z part over F2, so qubit j of row i is X, Z or Y if just `x[i][j]`,
+/// just `z[i][j]` or both are 1, and a sign bit, which is 1 for -1. Two tableaux are equal
+/// precisely when their unitaries are equal up to a global phase.
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub struct Tableau {
+    nqubits: usize,
+    x: Mat2,
+    z: Mat2,
+    r: Vec<u32>,
+}
+
+impl Tableau {
+    /// The tableau of the identity
+    pub fn new(nqubits: usize) -> Tableau {
+        let n = nqubits;
+        Tableau {
+            nqubits: n,
+            x: Mat2::build(2 * n, n, |i,j| i == j),
+            z: Mat2::build(2 * n, n, |i,j| i == j + n),
+            r: vec![0; 2 * n],
+        }
+    }
+
+    /// The tableau of a circuit, or an error if it has a gate which isn't Clifford
+    pub fn from_circuit(c: &Circuit) -> Result<Tableau, TableauError> {
+        let mut t = Tableau::new(c.num_qubits());
+        for g in &c.gates { t.apply_gate(g)?; }
+        Ok(t)
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.nqubits
+    }
+
+    /// Apply a Hadamard after the unitary
+    pub fn h(&mut self, q: usize) {
+        for i in 0..2 * self.nqubits {
+            self.r[i] ^= self.x[i][q] & self.z[i][q];
+            std::mem::swap(&mut self.x[i][q], &mut self.z[i][q]);
+        }
+    }
+
+    /// Apply an S gate after the unitary
+    pub fn s(&mut self, q: usize) {
+        for i in 0..2 * self.nqubits {
+            self.r[i] ^= self.x[i][q] & self.z[i][q];
+            self.z[i][q] ^= self.x[i][q];
+        }
+    }
+
+    /// Apply a CNOT after the unitary
+    pub fn cnot(&mut self, c: usize, t: usize) {
+        for i in 0..2 * self.nqubits {
+            self.r[i] ^= self.x[i][c] & self.z[i][t] & (self.x[i][t] ^ self.z[i][c] ^ 1);
+            self.x[i][t] ^= self.x[i][c];
+            self.z[i][c] ^= self.z[i][t];
+        }
+    }
+
+    /// Apply S^k, i.e. a Z rotation by k * pi/2, for a phase which is a multiple of 1/2
+    fn z_rotation(&mut self, q: usize, phase: Rational) -> bool {
+        let k = phase * 2;
+        if !k.is_integer() { return false; }
+        for _ in 0..k.to_integer().rem_euclid(4) { self.s(q); }
+        true
+    }
+
+    /// Apply a gate after the unitary, returning an error if it isn't Clifford
+    pub fn apply_gate(&mut self, g: &Gate) -> Result<(), TableauError> {
+        let err = || Err(TableauError::NotClifford { gate: g.clone() });
+        let qs = &g.qs;
+        match g.t {
+            HAD => self.h(qs[0]),
+            Z | S | Sdg | ZPhase => {
+                let phase = match g.t {
+                    Z => Rational::new(1, 1),
+                    S => Rational::new(1, 2),
+                    Sdg => Rational::new(-1, 2),
+                    _ => g.phase,
+                };
+                if !self.z_rotation(qs[0], phase) { return err(); }
+            },
+            NOT | XPhase => {
+                let phase = if g.t == NOT { Rational::new(1, 1) } else { g.phase };
+                self.h(qs[0]);
+                let clifford = self.z_rotation(qs[0], phase);
+                self.h(qs[0]);
+                if !clifford { return err(); }
+            },
+            CNOT => self.cnot(qs[0], qs[1]),
+            CZ => {
+                self.h(qs[1]);
+                self.cnot(qs[0], qs[1]);
+                self.h(qs[1]);
+            },
+            XCX => {
+                self.h(qs[0]);
+                self.cnot(qs[0], qs[1]);
+                self.h(qs[0]);
+            },
+            SWAP => {
+                self.cnot(qs[0], qs[1]);
+                self.cnot(qs[1], qs[0]);
+                self.cnot(qs[0], qs[1]);
+            },
+            ParityPhase if g.is_clifford() => {
+                for g1 in g.to_basic_gates() { self.apply_gate(&g1)?; }
+            },
+            _ => return err(),
+        }
+        Ok(())
+    }
+
+    /// Synthesise a Clifford circuit with this tableau
+    ///
+    /// This works one qubit at a time, applying Hadamards, S gates and CNOTs on that qubit and
+    /// the ones after it, until rows i and n + i are ±X_i and ±Z_i. The signs are then fixed by a
+    /// layer of Pauli gates, and the circuit is the Paulis followed by the inverse of the rest.
+    pub fn to_circuit(&self) -> Circuit {
+        let n = self.nqubits;
+        let mut t = self.clone();
+        let mut gates = Vec::new();
+        let mut apply = |t: &mut Tableau, g: Gate| {
+            t.apply_gate(&g).unwrap();
+            gates.push(g);
+        };
+
+        for i in 0..n {
+            // turn row i into X_i, first making it X or I on every qubit
+            for j in i..n {
+                match (t.x[i][j], t.z[i][j]) {
+                    (0, 1) => apply(&mut t, Gate::new(HAD, vec![j])),
+                    (1, 1) => apply(&mut t, Gate::new(S, vec![j])),
+                    _ => {},
+                }
+            }
+            if t.x[i][i] == 0 {
+                let j = (i+1..n).find(|&j| t.x[i][j] == 1).expect("Tableau should be valid");
+                apply(&mut t, Gate::new(CNOT, vec![j, i]));
+            }
+            for j in i+1..n {
+                if t.x[i][j] == 1 { apply(&mut t, Gate::new(CNOT, vec![i, j])); }
+            }
+
+            // since row n + i anticommutes with X_i, it is Z or Y on qubit i. Make it Z without
+            // changing X_i, then make it I or Z on the other qubits and clear them with CNOTs.
+            let row = n + i;
+            if t.x[row][i] == 1 {
+                apply(&mut t, Gate::new(HAD, vec![i]));
+                apply(&mut t, Gate::new(S, vec![i]));
+                apply(&mut t, Gate::new(HAD, vec![i]));
+            }
+            for j in i+1..n {
+                match (t.x[row][j], t.z[row][j]) {
+                    (1, 0) => apply(&mut t, Gate::new(HAD, vec![j])),
+                    (1, 1) => {
+                        apply(&mut t, Gate::new(S, vec![j]));
+                        apply(&mut t, Gate::new(HAD, vec![j]));
+                    },
+                    _ => {},
+                }
+                if t.z[row][j] == 1 { apply(&mut t, Gate::new(CNOT, vec![j, i])); }
+            }
+        }
+
+        // now the gates take U to a Pauli, which anticommutes with X_i if row i has a minus
+        // sign, and with Z_i if row n + i does
+        let mut c = Circuit::new(n);
+        for i in 0..n {
+            if t.r[i] == 1 { c.push(Gate::new(Z, vec![i])); }
+            if t.r[n + i] == 1 { c.push(Gate::new(NOT, vec![i])); }
+        }
+        for mut g in gates.into_iter().rev() {
+            g.adjoint();
+            c.push(g);
+        }
+        c
+    }
+
+    /// The Pauli string in the given row, with its sign
+    fn row_string(&self, i: usize) -> String {
+        let mut s = String::from(if self.r[i] == 1 { "-" } else { "+" });
+        for j in 0..self.nqubits {
+            s.push(match (self.x[i][j], self.z[i][j]) {
+                (0, 0) => 'I',
+                (1, 0) => 'X',
+                (0, _) => 'Z',
+                _ => 'Y',
+            });
+        }
+        s
+    }
+}
+
+/// Check whether two Clifford circuits are equal up to a global phase, using their tableaux
+///
+/// Returns an error if either circuit has a gate which isn't Clifford.
+pub fn clifford_equal(c1: &Circuit, c2: &Circuit) -> Result<bool, TableauError> {
+    Ok(c1.num_qubits() == c2.num_qubits() &&
+       Tableau::from_circuit(c1)? == Tableau::from_circuit(c2)?)
+}
+
+impl fmt::Display for Tableau {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = self.nqubits;
+        for i in 0..n {
+            writeln!(f, "X{} -> {}    Z{} -> {}", i, self.row_string(i), i, self.row_string(n + i))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+    use crate::simplify::*;
+    use crate::extract::ToCircuit;
+    use crate::vec_graph::Graph;
+
+    /// a fixed Clifford circuit with a mix of gates
+    fn clifford_circuit(n: usize, len: usize) -> Circuit {
+        let mut c = Circuit::new(n);
+        for i in 0..len {
+            let (q0, q1) = ((5 * i + 2) % n, (11 * i + 7 + i / n) % n);
+            match i % 7 {
+                0 => c.push(Gate::new(HAD, vec![q0])),
+                1 => c.push(Gate::new(S, vec![q0])),
+                2 => c.push(Gate::new_with_phase(XPhase, vec![q0], Rational::new(-1, 2))),
+                3 if q0 != q1 => c.push(Gate::new(CZ, vec![q0, q1])),
+                4 => c.push(Gate::new(NOT, vec![q0])),
+                _ if q0 != q1 => c.push(Gate::new(CNOT, vec![q0, q1])),
+                _ => c.push(Gate::new(Z, vec![q1])),
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn gates() {
+        let mut t = Tableau::from_circuit(&Circuit::from_qasm("qreg q[2]; h q[0]; cx q[0], q[1];").unwrap()).unwrap();
+        assert_eq!(t.to_string(), "X0 -> +ZI    Z0 -> +XX\nX1 -> +IX    Z1 -> +ZZ\n");
+        t.s(0);
+        t.apply_gate(&Gate::new(NOT, vec![1])).unwrap();
+        assert_eq!(t.to_string(), "X0 -> +ZI    Z0 -> +YX\nX1 -> +IX    Z1 -> -ZZ\n");
+
+        let c = Circuit::from_qasm("qreg q[2]; t q[0];").unwrap();
+        let err = Tableau::from_circuit(&c).unwrap_err();
+        assert_eq!(err, TableauError::NotClifford { gate: Gate::new(T, vec![0]) });
+        assert_eq!(err.to_string(), "Gate is not Clifford: t q[0]");
+    }
+
+    #[test]
+    fn equality() {
+        let c1 = Circuit::from_qasm("qreg q[2]; h q[0]; h q[1]; cx q[0], q[1]; h q[0]; h q[1];").unwrap();
+        let c2 = Circuit::from_qasm("qreg q[2]; cx q[1], q[0];").unwrap();
+        let c3 = Circuit::from_qasm("qreg q[2]; cx q[0], q[1];").unwrap();
+        let c4 = Circuit::from_qasm("qreg q[2]; swap q[0], q[1]; s q[1]; sdg q[1]; z q[0]; rz(pi) q[0];").unwrap();
+        let c5 = Circuit::from_qasm("qreg q[2]; cx q[0], q[1]; cx q[1], q[0]; cx q[0], q[1];").unwrap();
+        assert_eq!(clifford_equal(&c1, &c2), Ok(true));
+        assert_eq!(clifford_equal(&c1, &c3), Ok(false));
+        assert_eq!(clifford_equal(&c4, &c5), Ok(true));
+
+        // Y = iXZ, so these differ only by a global phase
+        let y1 = Circuit::from_qasm("qreg q[1]; x q[0]; z q[0];").unwrap();
+        let y2 = Circuit::from_qasm("qreg q[1]; rx(pi/2) q[0]; rx(pi/2) q[0]; rz(-pi) q[0];").unwrap();
+        assert_eq!(clifford_equal(&y1, &y2), Ok(true));
+        assert_eq!(clifford_equal(&y1, &Circuit::new(1)), Ok(false));
+    }
+
+    #[test]
+    fn synthesis() {
+        let c = clifford_circuit(5, 60);
+        let t = Tableau::from_circuit(&c).unwrap();
+        let c1 = t.to_circuit();
+        assert_eq!(Tableau::from_circuit(&c1).unwrap(), t);
+        assert!(equal_up_to_global_phase(&c.to_tensorf(), &c1.to_tensorf(), 1e-10));
+        assert_eq!(Tableau::new(3).to_circuit(), Circuit::new(3));
+    }
+
+    #[test]
+    fn large_clifford_simp() {
+        let n = 200;
+        let mut c = Circuit::new(n);
+        for layer in 0..3 {
+            for q in 0..n {
+                let t = [HAD, S, NOT, Sdg, Z][(q * 3 + layer) % 5];
+                c.push(Gate::new(t, vec![q]));
+                if q % 2 == 0 { c.push(Gate::new(HAD, vec![q])); }
+            }
+            for i in 0..100 {
+                let q0 = (7 * i + layer) % n;
+                let q1 = (q0 + 1 + (13 * i + 50 * layer) % (n - 1)) % n;
+                c.push(Gate::new(if i % 2 == 0 { CNOT } else { CZ }, vec![q0, q1]));
+            }
+        }
+        assert_eq!(c.gates.iter().filter(|g| g.qs.len() == 2).count(), 300);
+
+        let mut g: Graph = c.to_graph();
+        clifford_simp(&mut g);
+        let c1 = g.to_circuit().expect("Extraction should succeed");
+        assert_eq!(clifford_equal(&c, &c1), Ok(true));
+
+        let mut c2 = c1.clone();
+        c2.push(Gate::new(S, vec![150]));
+        assert_eq!(clifford_equal(&c, &c2), Ok(false));
+    }
+}
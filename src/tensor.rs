@@ -22,9 +22,9 @@ use num::{Complex,Rational};
 use ndarray::prelude::*;
 use ndarray::parallel::prelude::*;
 use ndarray::*;
-use std::collections::VecDeque;
+use std::collections::BTreeSet;
 use std::iter::FromIterator;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap,FxHashSet};
 
 pub type Tensor<A> = Array<A,IxDyn>;
 pub type Matrix<A> = Array<A,Ix2>;
@@ -43,6 +43,10 @@ impl FromPhase for Complex<f64> {
     }
 }
 
+impl FromScalar<Complex<f64>> for Complex<f64> {
+    fn from_scalar(s: &Complex<f64>) -> Complex<f64> { *s }
+}
+
 /// Wraps all the traits we need to compute tensors from ZX-diagrams.
 pub trait TensorElem: Copy + Send + Sync +
     Zero + One + Sqrt2 + FromPhase + FromScalar<ScalarN> +
@@ -68,6 +72,178 @@ pub trait ToTensor {
     fn to_tensorf(&self) -> Tensor<Complex<f64>> { self.to_tensor() }
 }
 
+/// Check two floating-point tensors are equal, up to rounding errors
+///
+/// Tensors are equal if they have the same shape and each pair of entries is
+/// at most `eps` apart.
+pub fn compare_tensors(a: &Tensor<Complex<f64>>, b: &Tensor<Complex<f64>>, eps: f64) -> bool {
+    a.shape() == b.shape() &&
+        a.iter().zip(b.iter()).all(|(x, y)| (x - y).norm() <= eps)
+}
+
+/// Find the scalar c such that c * a = b, if there is one
+///
+/// This divides the biggest entry of b by the corresponding entry of a, then
+/// checks the rest of the tensor with [compare_tensors].
+fn scalar_factor(a: &Tensor<Complex<f64>>, b: &Tensor<Complex<f64>>, eps: f64) -> Option<Complex<f64>> {
+    if a.shape() != b.shape() { return None; }
+    let (ai, bi) = a.iter().zip(b.iter())
+        .max_by(|(_, y0), (_, y1)| y0.norm().total_cmp(&y1.norm()))?;
+    if bi.norm() <= eps || ai.norm() <= eps { return None; }
+
+    let c = bi / ai;
+    if compare_tensors(&(a * c), b, eps) { Some(c) } else { None }
+}
+
+/// Check two floating-point tensors are equal, up to a global phase
+///
+/// That is, check b = e^(i θ) a for some θ, up to rounding errors. Zero
+/// tensors are only equal to zero tensors.
+pub fn equal_up_to_global_phase(a: &Tensor<Complex<f64>>, b: &Tensor<Complex<f64>>, eps: f64) -> bool {
+    match scalar_factor(a, b, eps) {
+        Some(c) => (c.norm() - 1.0).abs() <= eps,
+        None => compare_tensors(a, b, eps) && a.iter().all(|x| x.norm() <= eps),
+    }
+}
+
+/// Check two floating-point tensors are equal, up to a non-zero scalar
+///
+/// That is, check b = c a for some c != 0, up to rounding errors. Zero
+/// tensors are only equal to zero tensors.
+pub fn equal_up_to_scalar(a: &Tensor<Complex<f64>>, b: &Tensor<Complex<f64>>, eps: f64) -> bool {
+    match scalar_factor(a, b, eps) {
+        Some(_) => true,
+        None => compare_tensors(a, b, eps) && a.iter().all(|x| x.norm() <= eps),
+    }
+}
+
+/// Reshape a tensor with the given number of inputs into a matrix
+///
+/// The tensor should have its input indices first, followed by its outputs, as returned
+/// by [ToTensor::to_tensor]. The result is a 2^outputs x 2^inputs matrix. Basis states
+/// are big-endian: qubit 0 is the most significant bit, so the state |q0 q1 ... qn⟩
+/// has index q0 * 2^n + q1 * 2^(n-1) + ... + qn.
+pub fn tensor_to_matrix<A: TensorElem>(t: &Tensor<A>, num_inputs: usize) -> Matrix<A> {
+    let n = t.ndim();
+    if num_inputs > n { panic!("Tensor has fewer than {} indices", num_inputs); }
+    let perm: Vec<usize> = (num_inputs..n).chain(0..num_inputs).collect();
+    let shape = (1 << (n - num_inputs), 1 << num_inputs);
+    t.view().permuted_axes(perm).as_standard_layout().into_owned()
+        .into_shape(shape).expect("Tensor indices should all have dimension 2")
+}
+
+/// Trait that implements conversion of graphs and circuits to matrices
+///
+/// See [tensor_to_matrix] for the qubit ordering.
+pub trait ToMatrix {
+    fn to_matrix<A: TensorElem>(&self) -> Matrix<A>;
+
+    /// Shorthand for `to_matrix::<Scalar4>()`
+    fn to_matrix4(&self) -> Matrix<Scalar4> { self.to_matrix() }
+
+    /// Shorthand for `to_matrix::<Complex<f64>>()`
+    fn to_matrixf(&self) -> Matrix<Complex<f64>> { self.to_matrix() }
+}
+
+impl<G: GraphLike + Clone> ToMatrix for G {
+    fn to_matrix<A: TensorElem>(&self) -> Matrix<A> {
+        tensor_to_matrix(&self.to_tensor(), self.inputs().len())
+    }
+}
+
+impl ToMatrix for Circuit {
+    fn to_matrix<A: TensorElem>(&self) -> Matrix<A> {
+        let (t, num_inputs) = circuit_tensor(self);
+        tensor_to_matrix(&t, num_inputs)
+    }
+}
+
+const PRETTY_EPS: f64 = 1e-8;
+
+/// If x is close to a fraction with a small denominator, return it
+fn approx_rational(x: f64) -> Option<Rational> {
+    (1..=32).find_map(|d| {
+        let n = (x * d as f64).round();
+        if (x * d as f64 - n).abs() < PRETTY_EPS * d as f64 {
+            Some(Rational::new(n as isize, d))
+        } else { None }
+    })
+}
+
+/// Print the exponent iπp of a phase, e.g. "iπ/4", "-3iπ/4"
+fn phase_to_string(p: Rational) -> String {
+    let numer = match *p.numer() {
+        1 => String::new(),
+        -1 => String::from("-"),
+        n => n.to_string(),
+    };
+    if *p.denom() == 1 { format!("{}iπ", numer) }
+    else { format!("{}iπ/{}", numer, p.denom()) }
+}
+
+/// Print a single matrix entry as r e^(iπp), if r and p are nice enough
+fn entry_to_string(z: Complex<f64>) -> String {
+    let r = z.norm();
+    if r < PRETTY_EPS { return String::from("0"); }
+
+    let rs = if (r - r.round()).abs() < PRETTY_EPS { format!("{}", r.round()) }
+             else { format!("{:.4}", r) };
+    let p = match approx_rational(z.arg() / std::f64::consts::PI) {
+        Some(p) => p.mod2(),
+        None => return format!("{:.4}{:+.4}i", z.re, z.im),
+    };
+
+    let one = if rs == "1" { String::new() } else { rs.clone() };
+    if p.is_zero() { rs }
+    else if p == Rational::one() { format!("-{}", rs) }
+    else if p == Rational::new(1,2) { format!("{}i", one) }
+    else if p == Rational::new(-1,2) { format!("-{}i", one) }
+    else { format!("{}e^({})", one, phase_to_string(p)) }
+}
+
+/// Print a matrix in a human-readable form
+///
+/// If the smallest non-zero entry has magnitude 1/√2^k, this factor is pulled out of the
+/// matrix. Entries are then printed as r e^(iπp) for integers r and fractions p, where
+/// possible. Otherwise they are printed as floating-point numbers.
+///
+/// ```
+/// # use quizx::circuit::Circuit;
+/// # use quizx::tensor::*;
+/// let c = Circuit::from_qasm("qreg q[1]; h q[0]; t q[0];").unwrap();
+/// let s = pretty_matrix(&c.to_matrix4());
+/// assert_eq!(s, "1/√2 *\n[ 1            1            ]\n[ e^(iπ/4)     e^(-3iπ/4)   ]\n");
+/// ```
+pub fn pretty_matrix<A: Copy>(m: &Matrix<A>) -> String
+where Complex<f64>: FromScalar<A>
+{
+    let mut m: Matrix<Complex<f64>> = m.map(Complex::from_scalar);
+    let mut s = String::new();
+
+    let min = m.iter().map(|z| z.norm()).filter(|&r| r > PRETTY_EPS).fold(f64::INFINITY, f64::min);
+    if min.is_finite() {
+        let k = (-2.0 * min.log2()).round() as i32;
+        if k > 0 && (min * Complex::sqrt2_pow(k).re - 1.0).abs() < PRETTY_EPS {
+            if k == 1 { s.push_str("1/√2 *\n"); }
+            else { s.push_str(&format!("1/√2^{} *\n", k)); }
+            m *= Complex::sqrt2_pow(k);
+        }
+    }
+
+    let entries = m.map(|&z| entry_to_string(z));
+    let width = entries.iter().map(|e| e.chars().count()).max().unwrap_or(0) + 3;
+    for row in entries.genrows() {
+        s.push_str("[ ");
+        for e in row {
+            s.push_str(e);
+            for _ in e.chars().count()..width { s.push(' '); }
+        }
+        s.push_str("]\n");
+    }
+
+    s
+}
+
 pub trait QubitOps<A: TensorElem> {
     fn ident(q: usize) -> Self;
     fn delta(q: usize) -> Self;
@@ -75,6 +251,7 @@ pub trait QubitOps<A: TensorElem> {
     fn hadamard() -> Self;
     fn delta_at(&mut self, qs: &[usize]);
     fn cphase_at(&mut self, p: Rational, qs: &[usize]);
+    fn parity_phase_at(&mut self, p: Rational, qs: &[usize]);
     fn hadamard_at(&mut self, i: usize);
 
     /// split into two non-overlapping pieces, where index q=0 and q=1
@@ -138,6 +315,16 @@ impl<A: TensorElem> QubitOps<A> for Tensor<A> {
         *self *= &cp;
     }
 
+    fn parity_phase_at(&mut self, p: Rational, qs: &[usize]) {
+        let mut shape: Vec<usize> = vec![1; self.ndim()];
+        for &q in qs { shape[q] = 2; }
+        let pp: Tensor<A> = Tensor::from_shape_fn(vec![2;qs.len()], |ix| {
+            if (0..qs.len()).filter(|&i| ix[i] == 1).count() % 2 == 1 { A::from_phase(p) }
+            else { A::one() }
+        }).into_shape(shape).expect("Bad indices for parity_phase_at");
+        *self *= &pp;
+    }
+
     fn hadamard_at(&mut self, q: usize) {
         let n = A::one_over_sqrt2();
         let minus = A::from_phase(Rational::one()); // -1 = e^(i pi)
@@ -155,32 +342,461 @@ impl<A: TensorElem> QubitOps<A> for Tensor<A> {
     }
 }
 
-impl<G: GraphLike + Clone> ToTensor for G {
-    fn to_tensor<A: TensorElem>(&self) -> Tensor<A> {
-        let mut g = self.clone();
-        g.x_to_z();
-        // H-boxes are not implemented yet
-        for v in g.vertices() {
-            let t = g.vertex_type(v);
-            if t != VType::B && t != VType::Z {
-                panic!("Vertex type currently unsupported: {:?}", t);
+/// Prepare a graph for contraction
+///
+/// X spiders are turned into Z spiders, and every H-box is connected to Z spiders by normal
+/// edges. Then an H-box is just a controlled phase on the indices of its neighbours. Returns
+/// the new graph and a list of its H-boxes.
+fn tensor_network<G: GraphLike + Clone>(g: &G) -> (G, Vec<V>) {
+    let mut g = g.clone();
+    g.x_to_z();
+
+    let mut hboxes = Vec::new();
+    for h in g.vertex_vec() {
+        match g.vertex_type(h) {
+            VType::B | VType::Z => {},
+            VType::H => {
+                for (w, et) in g.incident_edge_vec(h) {
+                    if g.vertex_type(w) != VType::Z || et == EType::H {
+                        let z = g.add_vertex(VType::Z);
+                        g.remove_edge(h, w);
+                        g.add_edge_with_type(h, z, EType::N);
+                        g.add_edge_with_type(z, w, et);
+                    }
+                }
+                hboxes.push(h);
+            },
+            t => panic!("Vertex type currently unsupported: {:?}", t),
+        }
+    }
+
+    (g, hboxes)
+}
+
+/// The shape of a tensor network, as seen by the contraction
+///
+/// Every vertex apart from the H-boxes gets an index, and each edge or H-box
+/// is a list of the indices it touches.
+struct Network {
+    vs: Vec<V>,
+    index: FxHashMap<V,usize>,
+    edges: Vec<Vec<usize>>,
+    incident: Vec<Vec<usize>>,
+    boundary: Vec<bool>,
+}
+
+impl Network {
+    /// Build the network of a graph returned by [tensor_network]
+    fn new(g: &impl GraphLike) -> Network {
+        let vs: Vec<V> = g.vertices().filter(|&v| g.vertex_type(v) != VType::H).collect();
+        let index: FxHashMap<V,usize> = vs.iter().enumerate().map(|(i,&v)| (v,i)).collect();
+        let mut edges = Vec::new();
+        for (v0, v1, _) in g.edges() {
+            if let (Some(&i0), Some(&i1)) = (index.get(&v0), index.get(&v1)) {
+                edges.push(vec![i0, i1]);
             }
         }
 
-        let mut a = array![A::one()].into_dyn();
-        let inp = g.inputs().iter().copied();
-        let mid = g.vertices().filter(|&v| g.vertex_type(v) != VType::B);
-        let outp = g.outputs().iter().copied();
-        let mut vs: Vec<V> = inp.chain(mid.chain(outp)).collect();
+        for h in g.vertices() {
+            if g.vertex_type(h) == VType::H && g.degree(h) > 0 {
+                edges.push(g.neighbors(h).map(|w| index[&w]).collect());
+            }
+        }
 
-        if vs.len() < g.num_vertices() {
-            panic!("All boundary vertices must be an input or an output");
+        let mut incident = vec![Vec::new(); vs.len()];
+        for (e, ms) in edges.iter().enumerate() {
+            for &m in ms { incident[m].push(e); }
+        }
+
+        let boundary = vs.iter().map(|&v| g.vertex_type(v) == VType::B).collect();
+        Network { vs, index, edges, incident, boundary }
+    }
+
+    /// Turn an order on vertices into a complete order on indices
+    ///
+    /// Duplicates and vertices without an index are skipped. A vertex missing from
+    /// `order` is added straight after the first of its neighbours in `order`, or at
+    /// the end if it has no such neighbour.
+    fn complete_order(&self, order: &[V]) -> Vec<usize> {
+        let mut placed = vec![false; self.vs.len()];
+        let mut given = vec![false; self.vs.len()];
+        for v in order {
+            if let Some(&i) = self.index.get(v) { given[i] = true; }
+        }
+
+        let mut result = Vec::with_capacity(self.vs.len());
+        for v in order {
+            let i = match self.index.get(v) { Some(&i) => i, None => continue };
+            if placed[i] { continue; }
+            placed[i] = true;
+            result.push(i);
+            for &e in &self.incident[i] {
+                for &j in &self.edges[e] {
+                    if !given[j] && !placed[j] {
+                        placed[j] = true;
+                        result.push(j);
+                    }
+                }
+            }
+        }
+
+        result.extend((0..self.vs.len()).filter(|&i| !placed[i]));
+        result
+    }
+
+    /// The largest rank the tensor reaches when adding indices in the given order
+    fn peak_rank(&self, order: &[usize]) -> usize {
+        let mut t = RankTracker::new(self);
+        let mut peak = 0;
+        for &i in order {
+            peak = peak.max(t.open + 1);
+            t.add(i);
         }
+        peak
+    }
+}
+
+/// Keeps track of the open indices while a [Network] is contracted
+struct RankTracker<'a> {
+    net: &'a Network,
+    added: Vec<bool>,
+    closed: Vec<bool>,
+    // for each edge, the number of its indices not yet added
+    unseen: Vec<usize>,
+    // for each index, the number of its edges not yet in the tensor
+    pending: Vec<usize>,
+    open: usize,
+}
 
-        vs.reverse();
-        // TODO: pick a good sort order for mid
+impl<'a> RankTracker<'a> {
+    fn new(net: &'a Network) -> RankTracker<'a> {
+        RankTracker {
+            net,
+            added: vec![false; net.vs.len()],
+            closed: vec![false; net.vs.len()],
+            unseen: net.edges.iter().map(|ms| ms.len()).collect(),
+            pending: net.incident.iter().map(|es| es.len()).collect(),
+            open: 0,
+        }
+    }
+
+    /// Returns the number of indices that would be summed over by adding i, and
+    /// the number of edges that would be completed
+    fn closing(&self, i: usize) -> (usize, usize) {
+        let mut done: FxHashMap<usize,usize> = FxHashMap::default();
+        let mut num_edges = 0;
+        for &e in &self.net.incident[i] {
+            if self.unseen[e] == 1 {
+                num_edges += 1;
+                for &m in &self.net.edges[e] { *done.entry(m).or_insert(0) += 1; }
+            }
+        }
+
+        let closes = |m: usize, d: usize| {
+            !self.net.boundary[m] && (m == i || self.added[m]) && self.pending[m] == d
+        };
+        let mut num_closed = done.iter().filter(|&(&m, &d)| closes(m, d)).count();
+        if !done.contains_key(&i) && closes(i, 0) { num_closed += 1; }
+
+        (num_closed, num_edges)
+    }
+
+    fn add(&mut self, i: usize) {
+        self.added[i] = true;
+        self.open += 1;
+        let mut touched = vec![i];
+        for &e in &self.net.incident[i] {
+            self.unseen[e] -= 1;
+            if self.unseen[e] == 0 {
+                for &m in &self.net.edges[e] {
+                    self.pending[m] -= 1;
+                    touched.push(m);
+                }
+            }
+        }
 
-        let mut indexv: VecDeque<V> = VecDeque::new();
+        for m in touched {
+            if !self.net.boundary[m] && self.added[m] && !self.closed[m] && self.pending[m] == 0 {
+                self.closed[m] = true;
+                self.open -= 1;
+            }
+        }
+    }
+}
+
+/// The peak rank up to which [ContractionPlan::new] settles for insertion order
+pub const CHEAP_PLAN_RANK: usize = 16;
+
+/// The order in which to contract the vertices of a graph into a tensor
+///
+/// Vertices are added to the tensor one at a time, and the index of a vertex
+/// is summed over as soon as all of its edges are in the tensor. The cost of
+/// contraction is dominated by the largest rank the tensor reaches along the
+/// way, which is computed when the plan is made. This lets callers give up
+/// on graphs that are too big to contract.
+///
+/// ```
+/// # use quizx::circuit::Circuit;
+/// # use quizx::vec_graph::Graph;
+/// # use quizx::tensor::*;
+/// # use quizx::scalar::*;
+/// let c = Circuit::from_qasm("qreg q[2]; cx q[0], q[1]; h q[0];").unwrap();
+/// let g: Graph = c.to_graph();
+/// let plan = ContractionPlan::new(&g);
+/// assert!(plan.peak_rank() <= 20);
+/// let t = g.to_tensor_with_order::<Scalar4>(&plan);
+/// assert_eq!(t, g.to_tensor4());
+/// ```
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct ContractionPlan {
+    order: Vec<V>,
+    peak_rank: usize,
+}
+
+impl ContractionPlan {
+    /// Make a plan for contracting a graph
+    ///
+    /// This is [ContractionPlan::insertion_order], unless its peak rank is above
+    /// [CHEAP_PLAN_RANK], in which case contraction is costly enough that it is worth
+    /// looking for a better plan with [ContractionPlan::best].
+    pub fn new<G: GraphLike + Clone>(g: &G) -> ContractionPlan {
+        let plan = ContractionPlan::insertion_order(g);
+        if plan.peak_rank <= CHEAP_PLAN_RANK { plan }
+        else { ContractionPlan::best(g) }
+    }
+
+    /// Make the best plan out of [ContractionPlan::insertion_order], [ContractionPlan::greedy]
+    /// and [ContractionPlan::tree_decomposition]
+    pub fn best<G: GraphLike + Clone>(g: &G) -> ContractionPlan {
+        vec![ContractionPlan::insertion_order(g),
+             ContractionPlan::greedy(g),
+             ContractionPlan::tree_decomposition(g)]
+            .into_iter().min_by_key(|p| p.peak_rank).unwrap()
+    }
+
+    /// Make a plan that adds outputs, then the other vertices in reverse-insertion order, then
+    /// inputs
+    ///
+    /// For graphs coming straight from circuits, this sweeps across the circuit from the
+    /// outputs to the inputs, which is often hard to beat.
+    pub fn insertion_order<G: GraphLike + Clone>(g: &G) -> ContractionPlan {
+        let mut order: Vec<V> = g.inputs().clone();
+        order.extend(g.vertices().filter(|&v| g.vertex_type(v) != VType::B));
+        order.extend(g.outputs().iter().copied());
+        order.reverse();
+        ContractionPlan::from_order(g, &order)
+    }
+
+    /// The order in which vertices are added to the tensor
+    pub fn order(&self) -> &[V] { &self.order }
+
+    /// The largest rank the tensor reaches during contraction
+    pub fn peak_rank(&self) -> usize { self.peak_rank }
+
+    /// Make a plan that adds the vertices in the given order
+    ///
+    /// A vertex missing from `order` is added straight after the first of its
+    /// neighbours in `order`, or at the end if there is no such neighbour.
+    pub fn from_order<G: GraphLike + Clone>(g: &G, order: &[V]) -> ContractionPlan {
+        let (g, _) = tensor_network(g);
+        let net = Network::new(&g);
+        let order = net.complete_order(order);
+        ContractionPlan::from_network(&net, &order)
+    }
+
+    fn from_network(net: &Network, order: &[usize]) -> ContractionPlan {
+        ContractionPlan {
+            order: order.iter().map(|&i| net.vs[i]).collect(),
+            peak_rank: net.peak_rank(order),
+        }
+    }
+
+    /// Plan a contraction by greedily adding the vertex that keeps the rank smallest
+    ///
+    /// Only vertices sharing an edge with one that is already in the tensor are
+    /// considered, unless there aren't any. Ties are broken in favour of the vertex
+    /// that completes the most edges.
+    pub fn greedy<G: GraphLike + Clone>(g: &G) -> ContractionPlan {
+        let (g, _) = tensor_network(g);
+        let net = Network::new(&g);
+        let n = net.vs.len();
+        let mut t = RankTracker::new(&net);
+        let mut frontier: Vec<usize> = Vec::new();
+        let mut in_frontier = vec![false; n];
+        let mut order = Vec::with_capacity(n);
+
+        while order.len() < n {
+            let best = {
+                let score = |i: usize| {
+                    let (closed, edges) = t.closing(i);
+                    (-(closed as isize), -(edges as isize), i)
+                };
+                if frontier.is_empty() {
+                    (0..n).filter(|&i| !t.added[i]).min_by_key(|&i| score(i))
+                } else {
+                    frontier.iter().copied().min_by_key(|&i| score(i))
+                }
+            }.expect("there should be a vertex left to add");
+
+            t.add(best);
+            order.push(best);
+            frontier.retain(|&i| i != best);
+            in_frontier[best] = false;
+            for &e in &net.incident[best] {
+                for &m in &net.edges[e] {
+                    if !t.added[m] && !in_frontier[m] {
+                        in_frontier[m] = true;
+                        frontier.push(m);
+                    }
+                }
+            }
+        }
+
+        ContractionPlan::from_network(&net, &order)
+    }
+
+    /// Plan a contraction using a tree decomposition of the graph
+    ///
+    /// The tree decomposition comes from a min-fill elimination ordering. Vertices
+    /// are then added in a depth-first traversal of the decomposition, starting
+    /// from the root, so each bag separates the parts of the graph that are
+    /// contracted after it.
+    pub fn tree_decomposition<G: GraphLike + Clone>(g: &G) -> ContractionPlan {
+        let (g, _) = tensor_network(g);
+        let net = Network::new(&g);
+        let n = net.vs.len();
+
+        let mut adj: Vec<FxHashSet<usize>> = vec![FxHashSet::default(); n];
+        for ms in &net.edges {
+            for &m0 in ms {
+                for &m1 in ms {
+                    if m0 != m1 { adj[m0].insert(m1); }
+                }
+            }
+        }
+
+        let fill = |adj: &[FxHashSet<usize>], i: usize| {
+            let ns: Vec<usize> = adj[i].iter().copied().collect();
+            let mut f = 0;
+            for (k, &n0) in ns.iter().enumerate() {
+                for &n1 in &ns[k+1..] {
+                    if !adj[n0].contains(&n1) { f += 1; }
+                }
+            }
+            (f, ns.len(), i)
+        };
+
+        let mut scores: Vec<(usize,usize,usize)> = (0..n).map(|i| fill(&adj, i)).collect();
+        let mut queue: BTreeSet<(usize,usize,usize)> = scores.iter().copied().collect();
+        let mut bags: Vec<Vec<usize>> = vec![Vec::new(); n];
+        let mut elim_order = Vec::with_capacity(n);
+
+        while let Some(&first) = queue.iter().next() {
+            queue.remove(&first);
+            let i = first.2;
+            elim_order.push(i);
+
+            let ns: Vec<usize> = adj[i].drain().collect();
+            let mut affected: FxHashSet<usize> = FxHashSet::default();
+            for &n0 in &ns {
+                adj[n0].remove(&i);
+                for &n1 in &ns {
+                    if n0 != n1 { adj[n0].insert(n1); }
+                }
+                affected.insert(n0);
+                affected.extend(adj[n0].iter().copied());
+            }
+
+            for j in affected {
+                if queue.remove(&scores[j]) {
+                    scores[j] = fill(&adj, j);
+                    queue.insert(scores[j]);
+                }
+            }
+            bags[i] = ns;
+        }
+
+        // the parent of i in the elimination tree is the first of its remaining
+        // neighbours to be eliminated
+        let mut pos = vec![0; n];
+        for (k, &i) in elim_order.iter().enumerate() { pos[i] = k; }
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); n];
+        let mut roots = Vec::new();
+        for &i in &elim_order {
+            match bags[i].iter().min_by_key(|&&j| pos[j]) {
+                Some(&p) => children[p].push(i),
+                None => roots.push(i),
+            }
+        }
+
+        // depth-first from the roots, visiting later-eliminated vertices first
+        let mut order = Vec::with_capacity(n);
+        let mut stack = roots;
+        while let Some(i) = stack.pop() {
+            order.push(i);
+            stack.extend(children[i].iter().copied());
+        }
+
+        ContractionPlan::from_network(&net, &order)
+    }
+}
+
+/// The vertices owning the axes of a tensor being contracted
+///
+/// New axes are added at the end, so only removing an axis renumbers the
+/// others.
+#[derive(Default)]
+struct Indices {
+    vs: Vec<V>,
+    axis: FxHashMap<V,usize>,
+}
+
+impl Indices {
+    fn push(&mut self, v: V) {
+        self.axis.insert(v, self.vs.len());
+        self.vs.push(v);
+    }
+
+    fn axis(&self, v: V) -> Option<usize> { self.axis.get(&v).copied() }
+
+    fn remove(&mut self, v: V) -> Option<usize> {
+        let i = self.axis.remove(&v)?;
+        self.vs.remove(i);
+        for y in &self.vs[i..] { *self.axis.get_mut(y).unwrap() -= 1; }
+        Some(i)
+    }
+}
+
+/// Sum over the index of x, if all of its edges are already in the tensor
+fn contract_if_done<A: TensorElem>(g: &impl GraphLike, a: &mut Tensor<A>,
+                                   indices: &mut Indices,
+                                   seenv: &FxHashMap<V,usize>, x: V)
+{
+    if g.vertex_type(x) != VType::B && seenv.get(&x) == Some(&g.degree(x)) {
+        if let Some(xi) = indices.remove(x) {
+            *a = a.sum_axis(Axis(xi));
+        }
+    }
+}
+
+/// Contract a graph into a tensor, following a [ContractionPlan]
+pub trait ToTensorWithOrder {
+    fn to_tensor_with_order<A: TensorElem>(&self, plan: &ContractionPlan) -> Tensor<A>;
+}
+
+impl<G: GraphLike + Clone> ToTensorWithOrder for G {
+    fn to_tensor_with_order<A: TensorElem>(&self, plan: &ContractionPlan) -> Tensor<A> {
+        let (g, hboxes) = tensor_network(self);
+        let net = Network::new(&g);
+        let vs: Vec<V> = net.complete_order(plan.order()).iter().map(|&i| net.vs[i]).collect();
+
+        let io: Vec<V> = g.inputs().iter().chain(g.outputs().iter()).copied().collect();
+        if g.vertices().any(|v| g.vertex_type(v) == VType::B && !io.contains(&v)) {
+            panic!("All boundary vertices must be an input or an output");
+        }
+
+        let mut a = array![A::one()].into_dyn();
+        let mut indices = Indices::default();
         let mut seenv: FxHashMap<V,usize> = FxHashMap::default();
 
         let mut fst = true;
@@ -196,29 +812,36 @@ impl<G: GraphLike + Clone> ToTensor for G {
                 }
                 fst = false;
             } else {
+                let ax = Axis(a.ndim());
                 if p == Rational::new(0,1) {
-                    a = stack![Axis(0), a, a];
+                    a = stack![ax, a, a];
                 } else {
                     let f = A::from_phase(p);
-                    a = stack![Axis(0), a, &a * f];
+                    a = stack![ax, a, &a * f];
                 }
             }
 
-
-            indexv.push_front(v);
-            let mut deg_v = 0;
+            indices.push(v);
+            seenv.insert(v, 0);
 
             for (w, et) in g.incident_edges(v) {
-                if let Some(deg_w) = seenv.get_mut(&w) {
-                    deg_v += 1;
-                    *deg_w += 1;
+                if g.vertex_type(w) == VType::H {
+                    // apply the H-box once all of its neighbours have an index
+                    if !g.neighbors(w).all(|n| seenv.contains_key(&n)) { continue; }
+                    let ns = g.neighbor_vec(w);
+                    let qs: Vec<usize> = ns.iter().map(|&n| {
+                        indices.axis(n).expect("H-box neighbour should have an index")
+                    }).collect();
+                    a.cphase_at(g.phase(w), &qs);
 
-                    let vi = indexv.iter()
-                        .position(|x| *x == v)
-                        .expect("v should be in indexv");
-                    let mut wi = indexv.iter()
-                        .position(|x| *x == w)
-                        .expect("w should be in indexv");
+                    for &n in &ns { *seenv.get_mut(&n).unwrap() += 1; }
+                    for &n in &ns { contract_if_done(&g, &mut a, &mut indices, &seenv, n); }
+                } else if w != v && seenv.contains_key(&w) {
+                    *seenv.get_mut(&v).unwrap() += 1;
+                    *seenv.get_mut(&w).unwrap() += 1;
+
+                    let vi = indices.axis(v).expect("v should have an index");
+                    let wi = indices.axis(w).expect("w should have an index");
 
                     if et == EType::N {
                         a.delta_at(&[vi, wi]);
@@ -229,86 +852,136 @@ impl<G: GraphLike + Clone> ToTensor for G {
 
                     // if v and w now have all their edges in the tensor, contract away the
                     // index
-
-                    if g.vertex_type(v) != VType::B && g.degree(v) == deg_v {
-                        // println!("contracting v={}, deg_v={}", v, deg_v);
-                        a = a.sum_axis(Axis(vi));
-                        indexv.remove(vi);
-                        if wi > vi { wi -= 1; }
-                    }
-
-                    if g.vertex_type(w) != VType::B && g.degree(w) == *deg_w {
-                        // println!("contracting w={}, deg_w={}", w, *deg_w);
-                        a = a.sum_axis(Axis(wi));
-                        indexv.remove(wi);
-                    }
+                    contract_if_done(&g, &mut a, &mut indices, &seenv, v);
+                    contract_if_done(&g, &mut a, &mut indices, &seenv, w);
                 }
             }
-            seenv.insert(v, deg_v);
+
+            // catches vertices with no edges
+            contract_if_done(&g, &mut a, &mut indices, &seenv, v);
+        }
+
+        // only boundary indices are left, so put them in the order inputs, then outputs
+        if !io.is_empty() {
+            let perm: Vec<usize> = io.iter().map(|&b| {
+                indices.axis(b).expect("boundary should have an index")
+            }).collect();
+            a = a.permuted_axes(perm).as_standard_layout().into_owned();
+        }
+
+        let mut s = A::from_scalar(g.scalar()) * A::sqrt2_pow(-num_had);
+
+        // H-boxes with no neighbours are just scalars
+        for &h in &hboxes {
+            if g.degree(h) == 0 { s *= A::from_phase(g.phase(h)); }
         }
 
-        let s = A::from_scalar(g.scalar()) * A::sqrt2_pow(-num_had);
         a * s
     }
 }
 
-impl ToTensor for Circuit {
+impl<G: GraphLike + Clone> ToTensor for G {
     fn to_tensor<A: TensorElem>(&self) -> Tensor<A> {
-        use crate::gate::GType::*;
-        let q = self.num_qubits();
-
-        // start with the identity matrix
-        let mut a = Tensor::ident(q);
-
-        // since we are applying the gates to the input indices, this actually
-        // computes the transpose of the circuit, but all the gates are self-
-        // transposed, so we can get the circuit itself if we just reverse the order.
-        for g in self.gates.iter().rev() {
-            match g.t {
-                ZPhase => a.cphase_at(g.phase, &g.qs),
-                Z | CZ | CCZ => a.cphase_at(Rational::one(), &g.qs),
-                S => a.cphase_at(Rational::new(1, 2), &g.qs),
-                T => a.cphase_at(Rational::new(1, 4), &g.qs),
-                Sdg => a.cphase_at(Rational::new(-1, 2), &g.qs),
-                Tdg => a.cphase_at(Rational::new(-1, 4), &g.qs),
-                HAD => a.hadamard_at(g.qs[0]),
-                NOT => {
-                    a.hadamard_at(g.qs[0]);
-                    a.cphase_at(Rational::one(), &g.qs);
-                    a.hadamard_at(g.qs[0]);
-                },
-                XPhase => {
-                    a.hadamard_at(g.qs[0]);
-                    a.cphase_at(g.phase, &g.qs);
-                    a.hadamard_at(g.qs[0]);
-                },
-                CNOT => {
-                    a.hadamard_at(g.qs[1]);
-                    a.cphase_at(Rational::one(), &g.qs);
-                    a.hadamard_at(g.qs[1]);
-                },
-                TOFF => {
-                    a.hadamard_at(g.qs[2]);
-                    a.cphase_at(Rational::one(), &g.qs);
-                    a.hadamard_at(g.qs[2]);
-                },
-                SWAP => a.swap_axes(g.qs[0], g.qs[1]),
-                // n.b. these are pyzx-specific gates
-                XCX => {
-                    a.hadamard_at(g.qs[0]);
-                    a.hadamard_at(g.qs[1]);
-                    a.cphase_at(g.phase, &g.qs);
-                    a.hadamard_at(g.qs[0]);
-                    a.hadamard_at(g.qs[1]);
-                },
-                // TODO: these "gates" are not implemented yet
-                ParityPhase => { panic!("Unsupported gate: ParityPhase") },
-                InitAncilla => { panic!("Unsupported gate: InitAncilla") },
-                PostSelect => { panic!("Unsupported gate: PostSelect") },
-                UnknownGate => {}, // unknown gates are quietly ignored
-            }
+        self.to_tensor_with_order(&ContractionPlan::new(self))
+    }
+}
+
+/// Compute the tensor of a circuit, along with its number of inputs
+///
+/// This can be less than the number of qubits, if the circuit initialises ancillas.
+fn circuit_tensor<A: TensorElem>(c: &Circuit) -> (Tensor<A>, usize) {
+    use crate::gate::GType::*;
+    let q = c.num_qubits();
+
+    // start with the identity matrix, then apply the gates in order to the output
+    // indices. Each index is labelled as an input or output with its original qubit.
+    // Since SWAP and post-selection move and remove outputs, we keep track of which
+    // output is currently at each qubit, and which inputs haven't been touched yet.
+    let mut a = Tensor::ident(q);
+    let mut labels: Vec<(bool,usize)> = (0..q).map(|i| (false, i))
+        .chain((0..q).map(|i| (true, i))).collect();
+    let mut wire: Vec<Option<usize>> = (0..q).map(Some).collect();
+    let mut fresh: Vec<Option<usize>> = (0..q).map(Some).collect();
+
+    for g in &c.gates {
+        // gates involving a post-selected qubit are quietly ignored, as in to_graph()
+        if g.qs.iter().any(|&i| wire[i].is_none()) { continue; }
+        let ax: Vec<usize> = g.qs.iter().map(|&i| {
+            let l = (true, wire[i].unwrap());
+            labels.iter().position(|&l1| l1 == l).expect("output index not found")
+        }).collect();
+
+        match g.t {
+            ZPhase => a.cphase_at(g.phase, &ax),
+            Z | CZ | CCZ => a.cphase_at(Rational::one(), &ax),
+            S => a.cphase_at(Rational::new(1, 2), &ax),
+            T => a.cphase_at(Rational::new(1, 4), &ax),
+            Sdg => a.cphase_at(Rational::new(-1, 2), &ax),
+            Tdg => a.cphase_at(Rational::new(-1, 4), &ax),
+            HAD => a.hadamard_at(ax[0]),
+            NOT => {
+                a.hadamard_at(ax[0]);
+                a.cphase_at(Rational::one(), &ax);
+                a.hadamard_at(ax[0]);
+            },
+            XPhase => {
+                a.hadamard_at(ax[0]);
+                a.cphase_at(g.phase, &ax);
+                a.hadamard_at(ax[0]);
+            },
+            CNOT => {
+                a.hadamard_at(ax[1]);
+                a.cphase_at(Rational::one(), &ax);
+                a.hadamard_at(ax[1]);
+            },
+            TOFF => {
+                a.hadamard_at(ax[2]);
+                a.cphase_at(Rational::one(), &ax);
+                a.hadamard_at(ax[2]);
+            },
+            SWAP => wire.swap(g.qs[0], g.qs[1]),
+            ParityPhase => a.parity_phase_at(g.phase, &ax),
+            // n.b. these are pyzx-specific gates
+            XCX => {
+                a.hadamard_at(ax[0]);
+                a.hadamard_at(ax[1]);
+                a.cphase_at(Rational::one(), &ax);
+                a.hadamard_at(ax[0]);
+                a.hadamard_at(ax[1]);
+            },
+            InitAncilla => {
+                // plug |0> into the input, if nothing has happened to this qubit yet
+                if let Some(i) = fresh[g.qs[0]] {
+                    let ai = labels.iter().position(|&l| l == (false, i))
+                        .expect("input index not found");
+                    a = a.index_axis(Axis(ai), 0).to_owned();
+                    labels.remove(ai);
+                }
+            },
+            PostSelect => {
+                // plug <0| into the output, and forget about the qubit
+                a = a.index_axis(Axis(ax[0]), 0).to_owned();
+                labels.remove(ax[0]);
+                wire[g.qs[0]] = None;
+            },
+            UnknownGate => continue, // unknown gates are quietly ignored
         }
-        a
+
+        for &i in &g.qs { fresh[i] = None; }
+    }
+
+    // put the indices in the same order as the inputs and outputs of to_graph()
+    let inputs: Vec<(bool,usize)> = labels.iter().copied().filter(|&(o,_)| !o).collect();
+    let perm: Vec<usize> = inputs.iter().copied()
+        .chain(wire.iter().filter_map(|&w| w.map(|w| (true, w))))
+        .map(|l| labels.iter().position(|&l1| l1 == l).unwrap())
+        .collect();
+    (a.permuted_axes(perm).as_standard_layout().into_owned(), inputs.len())
+}
+
+impl ToTensor for Circuit {
+    fn to_tensor<A: TensorElem>(&self) -> Tensor<A> {
+        circuit_tensor(self).0
     }
 }
 
@@ -395,6 +1068,207 @@ mod tests {
         assert_eq!(t, Tensor::cphase(Rational::one(), 2));
     }
 
+    #[test]
+    fn tensor_hbox() {
+        let mut g = Graph::new();
+        let h = g.add_vertex(VType::H);
+        g.set_phase(h, Rational::new(1,2));
+        let mut bs = vec![];
+        for _ in 0..3 {
+            let b = g.add_vertex(VType::B);
+            g.add_edge(b, h);
+            bs.push(b);
+        }
+        g.set_inputs(vec![bs[0], bs[1]]);
+        g.set_outputs(vec![bs[2]]);
+
+        let t = g.to_tensor4();
+        let i = Scalar4::from_phase(Rational::new(1,2));
+        assert_eq!(t, Tensor::from_shape_fn(vec![2;3], |ix| {
+            if ix[0] == 1 && ix[1] == 1 && ix[2] == 1 { i } else { Scalar4::one() }
+        }));
+
+        // arity-0 H-boxes are scalars
+        let mut g = Graph::new();
+        let h = g.add_vertex(VType::H);
+        g.set_phase(h, Rational::new(1,4));
+        assert_eq!(g.to_tensor4(), array![Scalar4::from_phase(Rational::new(1,4))].into_dyn());
+    }
+
+    #[test]
+    fn tensor_ccz_hbox() {
+        let mut g = Graph::new();
+        let h = g.add_vertex(VType::H);
+        let mut inputs = vec![];
+        let mut outputs = vec![];
+        for _ in 0..3 {
+            let i = g.add_vertex(VType::B);
+            let v = g.add_vertex(VType::Z);
+            let o = g.add_vertex(VType::B);
+            g.add_edge(i, v);
+            g.add_edge(v, o);
+            g.add_edge(v, h);
+            inputs.push(i);
+            outputs.push(o);
+        }
+        g.set_inputs(inputs);
+        g.set_outputs(outputs);
+        g.set_phase(h, Rational::one());
+        assert_eq!(g.to_tensor4(), Tensor::cphase(Rational::one(), 3));
+
+        // the same, but with X spiders surrounded by Hadamard edges
+        let mut g1 = g.clone();
+        for v in g1.vertex_vec() {
+            if g1.vertex_type(v) == VType::Z {
+                g1.set_vertex_type(v, VType::X);
+                for w in g1.neighbor_vec(v) { g1.set_edge_type(v, w, EType::H); }
+            }
+        }
+        assert_eq!(g1.to_tensor4(), g.to_tensor4());
+    }
+
+    #[test]
+    fn plan_orders_agree() {
+        let c = Circuit::from_qasm(r#"
+            qreg q[3];
+            cx q[0], q[1];
+            h q[2];
+            ccz q[0], q[1], q[2];
+            t q[1];
+            cx q[2], q[0];
+            s q[0];
+            cz q[1], q[2];
+        "#).unwrap();
+        let mut g: Graph = c.to_graph();
+        let h = g.add_vertex(VType::H);
+        g.add_edge(h, 5);
+        g.add_edge(h, 6);
+
+        let mut rev = g.vertex_vec();
+        rev.reverse();
+        let t = g.to_tensor_with_order::<Scalar4>(&ContractionPlan::from_order(&g, &rev));
+        let plans = vec![
+            ContractionPlan::greedy(&g),
+            ContractionPlan::tree_decomposition(&g),
+            ContractionPlan::from_order(&g, &[]),
+            ContractionPlan::from_order(&g, &[7, 3, 7, 12]),
+        ];
+
+        for plan in plans {
+            assert_eq!(plan.order().len(), g.num_vertices() - 1);
+            assert_eq!(g.to_tensor_with_order::<Scalar4>(&plan), t);
+        }
+        assert_eq!(g.to_tensor4(), t);
+    }
+
+    #[test]
+    fn plan_peak_rank() {
+        // a line of 4 spiders
+        let mut g = Graph::new();
+        for _ in 0..4 { g.add_vertex(VType::Z); }
+        g.add_edge(0,1);
+        g.add_edge(1,2);
+        g.add_edge(2,3);
+
+        assert_eq!(ContractionPlan::from_order(&g, &[0,1,2,3]).peak_rank(), 2);
+        assert_eq!(ContractionPlan::from_order(&g, &[0,3,1,2]).peak_rank(), 3);
+        assert_eq!(ContractionPlan::greedy(&g).peak_rank(), 2);
+        assert_eq!(ContractionPlan::tree_decomposition(&g).peak_rank(), 2);
+        assert_eq!(ContractionPlan::new(&g).peak_rank(), 2);
+
+        let c = Circuit::from_file("circuits/tof_3.qasm").unwrap();
+        let mut g: Graph = c.to_graph();
+        let naive = ContractionPlan::insertion_order(&g);
+        assert!(ContractionPlan::new(&g).peak_rank() <= naive.peak_rank());
+
+        crate::simplify::full_reduce(&mut g);
+        let naive = ContractionPlan::insertion_order(&g);
+        assert!(ContractionPlan::new(&g).peak_rank() <= naive.peak_rank());
+        let plan = ContractionPlan::best(&g);
+        assert!(plan.peak_rank() < naive.peak_rank());
+        // the boundary indices stay open, so the rank can't be lower than this
+        assert!(plan.peak_rank() > g.inputs().len() + g.outputs().len());
+    }
+
+    #[test]
+    fn compare_float() {
+        let c = Circuit::from_file("circuits/tof_3.qasm").unwrap();
+        let g: Graph = c.to_graph();
+        let mut h = g.clone();
+        crate::simplify::full_reduce(&mut h);
+        let t = g.to_tensorf();
+        assert!(compare_tensors(&t, &h.to_tensorf(), 1e-10));
+        assert!(compare_tensors(&t, &c.to_tensorf(), 1e-10));
+
+        let ph = Complex::from_polar(1.0, 0.3);
+        assert!(!compare_tensors(&t, &(&t * ph), 1e-10));
+        assert!(equal_up_to_global_phase(&t, &(&t * ph), 1e-10));
+        assert!(equal_up_to_scalar(&t, &(&t * ph), 1e-10));
+
+        let s = Complex::new(0.0, 2.5);
+        assert!(!equal_up_to_global_phase(&t, &(&t * s), 1e-10));
+        assert!(equal_up_to_scalar(&t, &(&t * s), 1e-10));
+
+        let mut t1 = t.clone();
+        t1[&[0; 10][..]] += Complex::new(0.1, 0.0);
+        assert!(!compare_tensors(&t, &t1, 1e-10));
+        assert!(compare_tensors(&t, &t1, 0.2));
+        assert!(!equal_up_to_scalar(&t, &t1, 1e-10));
+
+        let z = Tensor::<Complex<f64>>::zeros(t.shape());
+        assert!(!equal_up_to_scalar(&t, &z, 1e-10));
+        assert!(!equal_up_to_scalar(&z, &t, 1e-10));
+        assert!(equal_up_to_scalar(&z, &z, 1e-10));
+        assert!(!compare_tensors(&t, &Tensor::ident(2), 1e-10));
+    }
+
+    #[test]
+    fn matrix_cnot() {
+        let c = Circuit::from_qasm("qreg q[2]; cx q[0], q[1];").unwrap();
+        let m = c.to_matrix4();
+        let (o, z) = (Scalar4::one(), Scalar4::zero());
+        assert_eq!(m, array![[o,z,z,z], [z,o,z,z], [z,z,z,o], [z,z,o,z]]);
+
+        let g: Graph = c.to_graph();
+        assert_eq!(g.to_matrix4(), m);
+
+        let c = Circuit::from_qasm("qreg q[2]; cx q[1], q[0];").unwrap();
+        let m = c.to_matrix4();
+        assert_eq!(m, array![[o,z,z,z], [z,z,z,o], [z,z,o,z], [z,o,z,z]]);
+    }
+
+    #[test]
+    fn matrix_state() {
+        // a single output with a phase is the state |0> + e^(iπ/2)|1>
+        let mut g = Graph::new();
+        let v = g.add_vertex(VType::Z);
+        g.set_phase(v, Rational::new(1,2));
+        let o = g.add_vertex(VType::B);
+        g.add_edge(v, o);
+        g.set_outputs(vec![o]);
+        let m = g.to_matrix4();
+        assert_eq!(m.shape(), &[2, 1]);
+        assert_eq!(m, array![[Scalar4::one()], [Scalar4::from_phase(Rational::new(1,2))]]);
+        assert_eq!(pretty_matrix(&m), "[ 1   ]\n[ i   ]\n");
+    }
+
+    #[test]
+    fn pretty_print() {
+        let c = Circuit::from_qasm("qreg q[2]; h q[0]; h q[1]; cz q[0], q[1];").unwrap();
+        let s = pretty_matrix(&c.to_matrixf());
+        assert_eq!(s, "1/√2^2 *\n\
+                       [ 1    1    1    1    ]\n\
+                       [ 1    -1   1    -1   ]\n\
+                       [ 1    1    -1   -1   ]\n\
+                       [ -1   1    1    -1   ]\n");
+
+        let m = array![[Complex::new(0.7, 0.0), Complex::new(0.6, 0.8)],
+                       [Complex::new(2.0, 0.0), Complex::new(0.0, -4.0)]];
+        let s = pretty_matrix(&m);
+        assert_eq!(s, "[ 0.7000           0.6000+0.8000i   ]\n\
+                       [ 2                -4i              ]\n");
+    }
+
     #[test]
     fn had_at() {
         let mut arr: Tensor<Scalar4> = Tensor::ident(1);
@@ -427,4 +1301,43 @@ mod tests {
         assert_eq!(c1.to_tensor4(), c2.to_tensor4());
 
     }
+
+    #[test]
+    fn circuit_special_gates() {
+        use crate::gate::*;
+        let mut c = Circuit::new(4);
+        c.push(Gate::new(InitAncilla, vec![3]));
+        c.push(Gate::new(HAD, vec![0]));
+        c.push(Gate::new_with_phase(ParityPhase, vec![0, 2, 3], Rational::new(1,4)));
+        c.push(Gate::new(XCX, vec![1, 3]));
+        c.push(Gate::new(SWAP, vec![0, 2]));
+        c.push(Gate::new(InitAncilla, vec![1])); // no-op, since q[1] has been used
+        c.push(Gate::new(PostSelect, vec![3]));
+        c.push(Gate::new(CNOT, vec![3, 0])); // ignored, since q[3] is gone
+        c.push(Gate::new_with_phase(ParityPhase, vec![0, 1], Rational::new(-1,2)));
+        c.push(Gate::new(PostSelect, vec![1]));
+
+        let t = c.to_tensor4();
+        assert_eq!(t.shape(), &[2; 5]);
+
+        let g: Graph = c.to_graph();
+        assert_eq!(g.inputs().len(), 3);
+        assert_eq!(g.outputs().len(), 2);
+        assert_eq!(g.to_tensor4(), t);
+        let h: Graph = c.to_graph_with_options(true);
+        assert_eq!(h.to_tensor4(), t);
+        assert_eq!(c.to_basic_gates().to_tensor4(), t);
+
+        // ancillas with nothing else are just a state
+        let mut c = Circuit::new(2);
+        c.push(Gate::new(InitAncilla, vec![0]));
+        c.push(Gate::new(InitAncilla, vec![1]));
+        c.push(Gate::new(HAD, vec![0]));
+        c.push(Gate::new(CNOT, vec![0, 1]));
+        let m = c.to_matrixf();
+        let r = 1.0 / f64::sqrt(2.0);
+        let z = Complex::new(0.0, 0.0);
+        assert!(compare_tensors(&m.into_dyn(),
+            &array![[Complex::new(r, 0.0)], [z], [z], [Complex::new(r, 0.0)]].into_dyn(), 1e-10));
+    }
 }
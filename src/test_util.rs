@@ -0,0 +1,52 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fixtures shared by the tests of several modules
+
+use crate::circuit::Circuit;
+use crate::gate::*;
+use crate::linalg::{Mat2, RowColOps};
+use crate::tensor::*;
+
+/// A fixed CNOT circuit on n qubits, and its parity matrix
+pub fn cnot_circuit(n: usize, len: usize) -> (Circuit, Mat2) {
+    let mut c = Circuit::new(n);
+    let mut m = Mat2::id(n);
+    for i in 0..len {
+        let (ctrl, tgt) = ((3 * i + 1) % n, (7 * i + 4 + i / n) % n);
+        if ctrl == tgt { continue; }
+        c.push(Gate::new(CNOT, vec![ctrl, tgt]));
+        m.row_add(ctrl, tgt);
+    }
+    (c, m)
+}
+
+/// Benchmark circuits which are small enough to compare tensors for
+pub const SMALL_BENCHMARKS: [&str; 3] = ["tof_3", "barenco_tof_3", "mod5_4"];
+
+/// Apply an optimisation to each of [SMALL_BENCHMARKS], in basic gates, and check it
+/// preserves the tensor and doesn't increase the T-count
+///
+/// Returns the name of each benchmark, along with the circuits before and after.
+pub fn check_benchmarks(opt: impl Fn(&Circuit) -> Circuit) -> Vec<(&'static str, Circuit, Circuit)> {
+    SMALL_BENCHMARKS.iter().map(|&f| {
+        let c = Circuit::from_file(&format!("circuits/{}.qasm", f)).unwrap().to_basic_gates();
+        let c1 = opt(&c);
+        assert!(c1.stats().tcount <= c.stats().tcount, "{}", f);
+        assert!(compare_tensors(&c.to_tensorf(), &c1.to_tensorf(), 1e-8), "{}", f);
+        (f, c, c1)
+    }).collect()
+}
@@ -21,7 +21,7 @@ use std::iter::FromIterator;
 
 pub type VTab<T> = Vec<Option<T>>;
 
-#[derive(Debug,Clone,PartialEq)]
+#[derive(Debug,Clone)]
 pub struct Graph {
     vdata: VTab<VData>,
     edata: VTab<Vec<(V,EType)>>,
@@ -31,6 +31,7 @@ pub struct Graph {
     numv: usize,
     nume: usize,
     pub scalar: ScalarN,
+    journal: Journal,
 }
 
 impl Graph {
@@ -57,6 +58,35 @@ impl Graph {
         }
     }
 
+    /// Reverse a change recorded in the journal
+    ///
+    /// Changes are undone in the opposite order to how they were made, so
+    /// holes are reused in LIFO order and end up as they were.
+    fn undo(&mut self, u: Undo) {
+        match u {
+            Undo::AddVertex(v) => {
+                self.remove_vertex(v);
+                if v + 1 == self.vdata.len() {
+                    self.holes.pop();
+                    self.vdata.pop();
+                    self.edata.pop();
+                }
+            }
+            Undo::RemoveVertex(v, d) => {
+                if let Some(i) = self.holes.iter().rposition(|&h| h == v) {
+                    self.holes.remove(i);
+                }
+                self.numv += 1;
+                self.vdata[v] = Some(d);
+                self.edata[v] = Some(Vec::new());
+            }
+            Undo::AddEdge(s, t) => self.remove_edge(s, t),
+            Undo::RemoveEdge(s, t, ety) => self.add_edge_with_type(s, t, ety),
+            Undo::SetEdgeType(s, t, ety) => self.set_edge_type(s, t, ety),
+            Undo::SetVData(v, d) => self.vdata[v] = Some(d),
+        }
+    }
+
     // Here are some simpler implementations of the vertices and edges functions,
     // but they can't be moved into the trait because they return "impl" types.
     // pub fn vertices2(&self) -> impl Iterator<Item=V> + '_ {
@@ -77,6 +107,20 @@ impl Graph {
     // }
 }
 
+// graphs are compared by their contents, so the journal is left out
+impl PartialEq for Graph {
+    fn eq(&self, other: &Graph) -> bool {
+        self.vdata == other.vdata &&
+        self.edata == other.edata &&
+        self.holes == other.holes &&
+        self.inputs == other.inputs &&
+        self.outputs == other.outputs &&
+        self.numv == other.numv &&
+        self.nume == other.nume &&
+        self.scalar == other.scalar
+    }
+}
+
 impl GraphLike for Graph {
     fn new() -> Graph {
         Graph {
@@ -88,6 +132,7 @@ impl GraphLike for Graph {
             numv: 0,
             nume: 0,
             scalar: Scalar::one(),
+            journal: Journal::default(),
         }
     }
 
@@ -120,7 +165,7 @@ impl GraphLike for Graph {
 
     fn add_vertex_with_data(&mut self, d: VData) -> V {
         self.numv += 1;
-        if let Some(v) = self.holes.pop() {
+        let v = if let Some(v) = self.holes.pop() {
             self.vdata[v] = Some(d);
             self.edata[v] = Some(Vec::new());
             v
@@ -128,10 +173,20 @@ impl GraphLike for Graph {
             self.vdata.push(Some(d));
             self.edata.push(Some(Vec::new()));
             self.vdata.len() - 1
-        }
+        };
+        self.journal.record(Undo::AddVertex(v));
+        v
     }
 
     fn remove_vertex(&mut self, v: V) {
+        if self.journal.is_recording() {
+            for (v1, et) in self.incident_edge_vec(v) {
+                self.journal.record(Undo::RemoveEdge(v, v1, et));
+            }
+            let d = self.vdata[v].expect("Vertex not found");
+            self.journal.record(Undo::RemoveVertex(v, d));
+        }
+
         self.numv -= 1;
         self.holes.push(v);
 
@@ -144,8 +199,13 @@ impl GraphLike for Graph {
         self.edata[v] = None;
     }
 
+    fn contains_vertex(&self, v: V) -> bool {
+        matches!(self.vdata.get(v), Some(Some(_)))
+    }
+
     fn add_edge_with_type(&mut self, s: V, t: V, ety: EType) {
         self.nume += 1;
+        self.journal.record(Undo::AddEdge(s, t));
 
         if let Some(Some(nhd)) = self.edata.get_mut(s) {
             nhd.push((t,ety));
@@ -162,6 +222,11 @@ impl GraphLike for Graph {
 
 
     fn remove_edge(&mut self, s: V, t: V) {
+        if self.journal.is_recording() {
+            let ety = self.edge_type_opt(s, t).expect("Edge not found");
+            self.journal.record(Undo::RemoveEdge(s, t, ety));
+        }
+
         self.nume -= 1;
         self.remove_half_edge(s,t);
         self.remove_half_edge(t,s);
@@ -169,6 +234,7 @@ impl GraphLike for Graph {
 
     fn set_phase(&mut self, v: V, phase: Rational) {
         if let Some(Some(d)) = self.vdata.get_mut(v) {
+            self.journal.record(Undo::SetVData(v, *d));
             d.phase = phase.mod2();
         } else {
             panic!("Vertex not found");
@@ -183,6 +249,7 @@ impl GraphLike for Graph {
 
     fn add_to_phase(&mut self, v: V, phase: Rational) {
         if let Some(Some(d)) = self.vdata.get_mut(v) {
+            self.journal.record(Undo::SetVData(v, *d));
             d.phase = (d.phase + phase).mod2();
         } else {
             panic!("Vertex not found");
@@ -191,6 +258,7 @@ impl GraphLike for Graph {
 
     fn set_vertex_type(&mut self, v: V, ty: VType) {
         if let Some(Some(d)) = self.vdata.get_mut(v) {
+            self.journal.record(Undo::SetVData(v, *d));
             d.ty = ty;
         } else {
             panic!("Vertex not found");
@@ -204,6 +272,11 @@ impl GraphLike for Graph {
     }
 
     fn set_edge_type(&mut self, s: V, t: V, ety: EType) {
+        if self.journal.is_recording() {
+            let old = self.edge_type_opt(s, t).expect("Edge not found");
+            self.journal.record(Undo::SetEdgeType(s, t, old));
+        }
+
         if let Some(Some(nhd)) = self.edata.get_mut(s) {
             let i = Graph::index(&nhd, t).expect("Edge not found");
             nhd[i] = (t, ety);
@@ -230,6 +303,7 @@ impl GraphLike for Graph {
 
     fn set_coord(&mut self, v: V, coord: (i32,i32)) {
         if let Some(Some(d)) = self.vdata.get_mut(v) {
+            self.journal.record(Undo::SetVData(v, *d));
             d.qubit = coord.0;
             d.row = coord.1;
         } else {
@@ -237,33 +311,35 @@ impl GraphLike for Graph {
         }
     }
 
-    fn coord(&mut self, v: V) -> (i32,i32) {
+    fn coord(&self, v: V) -> (i32,i32) {
         let d = self.vdata[v].expect("Vertex not found");
         (d.qubit, d.row)
     }
 
     fn set_qubit(&mut self, v: V, qubit: i32) {
         if let Some(Some(d)) = self.vdata.get_mut(v) {
+            self.journal.record(Undo::SetVData(v, *d));
             d.qubit = qubit;
         } else {
             panic!("Vertex not found")
         }
     }
 
-    fn qubit(&mut self, v: V) -> i32 {
+    fn qubit(&self, v: V) -> i32 {
         self.vdata[v]
             .expect("Vertex not found").qubit
     }
 
     fn set_row(&mut self, v: V, row: i32) {
         if let Some(Some(d)) = self.vdata.get_mut(v) {
+            self.journal.record(Undo::SetVData(v, *d));
             d.row = row;
         } else {
             panic!("Vertex not found")
         }
     }
 
-    fn row(&mut self, v: V) -> i32 {
+    fn row(&self, v: V) -> i32 {
         self.vdata[v]
             .expect("Vertex not found").row
     }
@@ -295,6 +371,25 @@ impl GraphLike for Graph {
     fn scalar(&self) -> &ScalarN { &self.scalar }
     fn scalar_mut(&mut self) -> &mut ScalarN { &mut self.scalar }
 
+    fn checkpoint(&mut self) -> Checkpoint {
+        self.journal.checkpoint(self.scalar.clone(), self.inputs.clone(), self.outputs.clone())
+    }
+
+    fn rollback(&mut self, cp: Checkpoint) {
+        let changes = self.journal.rollback(&cp);
+        // undoing shouldn't be recorded by any outer checkpoint
+        let journal = std::mem::take(&mut self.journal);
+        for u in changes { self.undo(u); }
+        self.journal = journal;
+        self.scalar = cp.scalar;
+        self.inputs = cp.inputs;
+        self.outputs = cp.outputs;
+    }
+
+    fn commit(&mut self, cp: Checkpoint) {
+        self.journal.commit(&cp);
+    }
+
     fn find_edge<F>(&self, f: F) -> Option<(V,V,EType)>
         where F : Fn(V,V,EType) -> bool
     {
@@ -320,6 +415,22 @@ impl GraphLike for Graph {
     }
 }
 
+impl std::ops::Add<&Graph> for Graph {
+    type Output = Graph;
+    fn add(mut self, rhs: &Graph) -> Self::Output {
+        self.compose(rhs);
+        self
+    }
+}
+
+impl std::ops::Add<Graph> for Graph {
+    type Output = Graph;
+    fn add(self, rhs: Graph) -> Self::Output { self.add(&rhs) } }
+
+impl std::ops::Add<Graph> for &Graph {
+    type Output = Graph;
+    fn add(self, rhs: Graph) -> Self::Output { self.clone().add(&rhs) } }
+
 #[cfg(test)]
 mod tests {
     use super::*;
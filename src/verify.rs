@@ -0,0 +1,287 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Equivalence checking of circuits
+//!
+//! Two circuits C1 and C2 are equal, up to a global scalar, precisely when the
+//! miter C2^dag C1 is a multiple of the identity. We build the miter as a
+//! ZX-diagram and simplify it. If it reduces to bare wires, the circuits are
+//! equal. Otherwise, if the remainder is small enough, contracting it either
+//! confirms the equality or gives a witness that the circuits differ.
+
+use std::fmt;
+use num::Complex;
+use crate::graph::*;
+use crate::scalar::*;
+use crate::circuit::Circuit;
+use crate::simplify::{full_reduce, id_simp};
+use crate::tensor::*;
+use crate::vec_graph::Graph;
+
+/// The largest number of qubits for which the reduced miter gets contracted
+const MAX_TENSOR_QUBITS: usize = 10;
+
+/// How far the peak rank may exceed 2n while contracting a reduced miter on n qubits
+const PEAK_RANK_SLACK: usize = 4;
+
+/// The largest peak rank allowed while contracting the reduced miter
+const MAX_PEAK_RANK: usize = 20;
+
+/// An error preventing two circuits from being compared
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum VerifyError {
+    /// The circuits act on different numbers of qubits
+    QubitCountMismatch { qubits1: usize, qubits2: usize },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::QubitCountMismatch { qubits1, qubits2 } =>
+                write!(f, "Cannot compare circuits with {} and {} qubits", qubits1, qubits2),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Evidence that two circuits are not equal
+///
+/// This is an entry `amplitude` = <output| C2^dag C1 |input> of the miter which
+/// is inconsistent with it being a multiple of the identity. Either `output` is
+/// not `input` and the amplitude is non-zero, or they are the same and the amplitude
+/// differs from the one for the all-zeroes state.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Witness {
+    pub input: Vec<bool>,
+    pub output: Vec<bool>,
+    pub amplitude: Complex<f64>,
+}
+
+/// The outcome of [verify_equality]
+#[derive(Debug,Clone,PartialEq)]
+pub enum Equality {
+    /// C1 = s C2 for the given scalar s, which is a global phase for unitaries
+    ///
+    /// The scalar is exact if the miter simplified to the identity, and a
+    /// floating-point approximation if it had to be contracted.
+    Equal(ScalarN),
+    /// The circuits differ
+    NotEqual(Witness),
+    /// The miter didn't reduce to the identity, and is too big to contract
+    Unknown,
+}
+
+fn bits_to_string(bits: &[bool]) -> String {
+    bits.iter().map(|&b| if b { '1' } else { '0' }).collect()
+}
+
+impl fmt::Display for Equality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Equality::Equal(s) => write!(f, "Equal, up to the scalar {}", s),
+            Equality::NotEqual(w) =>
+                write!(f, "Not equal: <{}| C2^dag C1 |{}> = {}",
+                       bits_to_string(&w.output), bits_to_string(&w.input), w.amplitude),
+            Equality::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+/// Check if a graph is just the identity on its boundaries
+fn is_identity<G: GraphLike>(g: &G) -> bool {
+    g.inputs().len() == g.outputs().len() &&
+    g.num_vertices() == 2 * g.inputs().len() &&
+    g.inputs().iter().zip(g.outputs()).all(|(&i, &o)| g.edge_type_opt(i, o) == Some(EType::N))
+}
+
+fn bits(n: usize, x: usize) -> Vec<bool> {
+    (0..n).map(|i| x & (1 << (n - 1 - i)) != 0).collect()
+}
+
+/// The largest peak rank allowed while contracting a reduced miter on n qubits
+fn peak_rank_cap(n: usize) -> usize {
+    usize::min(2 * n + PEAK_RANK_SLACK, MAX_PEAK_RANK)
+}
+
+/// Contract a small miter, and check whether it is a multiple of the identity
+///
+/// The contraction is done in floating point, and is only attempted if it stays
+/// within a few indices of the 2n of the result.
+fn check_tensor<G: GraphLike + Clone>(g: &G) -> Option<Equality> {
+    let n = g.inputs().len();
+    if n != g.outputs().len() || n > MAX_TENSOR_QUBITS { return None; }
+    let plan = ContractionPlan::new(g);
+    if plan.peak_rank() > peak_rank_cap(n) { return None; }
+
+    let t: Tensor<Complex<f64>> = g.to_tensor_with_order(&plan);
+    let m = tensor_to_matrix(&t, n);
+    let eps = 1e-8;
+    let s = m[[0,0]];
+    for x in 0..(1 << n) {
+        for y in 0..(1 << n) {
+            let a = m[[y,x]];
+            let expected = if x == y { s } else { Complex::new(0.0, 0.0) };
+            if (a - expected).norm() > eps {
+                return Some(Equality::NotEqual(Witness {
+                    input: bits(n, x), output: bits(n, y), amplitude: a
+                }));
+            }
+        }
+    }
+
+    Some(Equality::Equal(ScalarN::complex(s.re, s.im)))
+}
+
+/// Check whether two circuits are equal, up to a global scalar
+///
+/// This simplifies the miter `c1.to_graph()` followed by the adjoint of
+/// `c2.to_graph()`. If it becomes the identity, the circuits are equal, and its
+/// scalar is the global scalar relating them. Otherwise, small remainders are
+/// contracted, which decides the question. For bigger ones, the answer is
+/// [Equality::Unknown]. Circuits on different numbers of qubits can't be
+/// compared, and give an error.
+pub fn verify_equality(c1: &Circuit, c2: &Circuit) -> Result<Equality, VerifyError> {
+    if c1.num_qubits() != c2.num_qubits() {
+        return Err(VerifyError::QubitCountMismatch {
+            qubits1: c1.num_qubits(), qubits2: c2.num_qubits()
+        });
+    }
+
+    let g = reduced_miter(c1, c2);
+    if is_identity(&g) {
+        Ok(Equality::Equal(g.scalar().clone()))
+    } else {
+        Ok(check_tensor(&g).unwrap_or(Equality::Unknown))
+    }
+}
+
+/// The miter C2^dag C1, simplified as far as possible
+fn reduced_miter(c1: &Circuit, c2: &Circuit) -> Graph {
+    let mut g: Graph = c1.to_graph();
+    let mut h: Graph = c2.to_graph();
+    h.adjoint();
+    g.compose(&h);
+    full_reduce(&mut g);
+    // full_reduce keeps spiders next to the boundary, so remove any identities left there
+    id_simp(&mut g);
+    g
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::optimize::basic_optimization;
+    use crate::gate::*;
+    use crate::extract::ToCircuit;
+    use approx::assert_abs_diff_eq;
+    use num::Rational;
+
+    #[test]
+    fn equal_circuits() {
+        let c = Circuit::from_file("circuits/tof_3.qasm").unwrap();
+        let c1 = basic_optimization(&c);
+        assert!(c1.num_gates() < c.num_gates());
+        match verify_equality(&c, &c1).unwrap() {
+            Equality::Equal(s) => assert_abs_diff_eq!(s, ScalarN::one()),
+            e => panic!("Expected circuits to be equal, got: {}", e),
+        }
+
+        // equal on the nose, and up to a global phase, as X Z X = -Z
+        let c1 = Circuit::from_qasm("qreg q[1]; z q[0];").unwrap();
+        let c2 = Circuit::from_qasm("qreg q[1]; h q[0]; x q[0]; h q[0];").unwrap();
+        assert_eq!(verify_equality(&c1, &c2).unwrap(), Equality::Equal(ScalarN::one()));
+        let c2 = Circuit::from_qasm("qreg q[1]; x q[0]; z q[0]; x q[0];").unwrap();
+        let e = verify_equality(&c1, &c2).unwrap();
+        assert_eq!(e, Equality::Equal(ScalarN::from_phase(Rational::one())));
+        assert_eq!(e.to_string(), "Equal, up to the scalar -1");
+    }
+
+    #[test]
+    fn unequal_circuits() {
+        let c1 = Circuit::from_file("circuits/tof_3.qasm").unwrap().to_basic_gates();
+        let mut c2 = c1.clone();
+        let i = c2.gates.iter().position(|g| g.t == GType::T).unwrap();
+        c2.gates.remove(i);
+
+        let w = match verify_equality(&c1, &c2).unwrap() {
+            Equality::NotEqual(w) => w,
+            e => panic!("Expected circuits to differ, got: {}", e),
+        };
+
+        // check the witness against the tensor of the miter
+        let mut m = c2.to_adjoint();
+        m.gates.splice(0..0, c1.gates.iter().cloned());
+        let t = m.to_tensorf();
+        let ix: Vec<usize> = w.input.iter().chain(w.output.iter()).map(|&b| b as usize).collect();
+        assert!((t[&ix[..]] - w.amplitude).norm() < 1e-8);
+        let z = vec![0; ix.len()];
+        if w.input == w.output {
+            assert!((t[&z[..]] - w.amplitude).norm() > 1e-8);
+        } else {
+            assert!(w.amplitude.norm() > 1e-8);
+        }
+
+        // wires swapped
+        let c1 = Circuit::from_qasm("qreg q[2]; h q[0];").unwrap();
+        let c2 = Circuit::from_qasm("qreg q[2]; h q[1];").unwrap();
+        assert!(matches!(verify_equality(&c1, &c2).unwrap(), Equality::NotEqual(_)));
+
+        // different numbers of qubits
+        let c2 = Circuit::from_qasm("qreg q[3]; h q[0];").unwrap();
+        let e = verify_equality(&c1, &c2).unwrap_err();
+        assert_eq!(e, VerifyError::QubitCountMismatch { qubits1: 2, qubits2: 3 });
+        assert_eq!(e.to_string(), "Cannot compare circuits with 2 and 3 qubits");
+    }
+
+    #[test]
+    fn unknown() {
+        // a T gate in the middle of a big entangling circuit doesn't simplify away,
+        // and the miter is too wide to contract
+        let n = 12;
+        let mut c1 = Circuit::new(n);
+        for q in 0..n { c1.add_gate("h", vec![q]); }
+        for q in 0..n { c1.add_gate("cz", vec![q, (q + 1) % n]); }
+        for q in 0..n { c1.add_gate("t", vec![q]); }
+        for q in 0..n { c1.add_gate("cx", vec![q, (q + 5) % n]); }
+        for q in 0..n { c1.add_gate("h", vec![q]); }
+        let mut c2 = c1.clone();
+        c2.gates.insert(3 * n, Gate::new(GType::T, vec![0]));
+
+        assert_eq!(verify_equality(&c1, &c2).unwrap(), Equality::Unknown);
+        assert!(matches!(verify_equality(&c1, &c1).unwrap(), Equality::Equal(_)));
+    }
+
+    #[test]
+    fn costly_contractions() {
+        // the miters of these don't reduce to the identity, and are narrow enough to
+        // contract, but the contraction would go past the peak rank cap, so it is refused
+        for f in &["circuits/tof_5.qasm", "circuits/vbe_adder_3.qasm"] {
+            let c = Circuit::from_file(f).unwrap();
+            let mut g: Graph = c.to_graph();
+            full_reduce(&mut g);
+            let c1 = g.to_circuit().unwrap();
+
+            let miter = reduced_miter(&c, &c1);
+            let n = miter.inputs().len();
+            assert!(!is_identity(&miter), "{}", f);
+            assert!(n <= MAX_TENSOR_QUBITS, "{}", f);
+            assert!(ContractionPlan::new(&miter).peak_rank() > peak_rank_cap(n), "{}", f);
+            assert_eq!(check_tensor(&miter), None, "{}", f);
+            assert_eq!(verify_equality(&c, &c1).unwrap(), Equality::Unknown);
+        }
+    }
+}